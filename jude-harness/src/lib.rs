@@ -282,7 +282,9 @@ impl<'c> judeWalletRpc {
 
     pub async fn balance(&self) -> Result<u64> {
         self.client().refresh().await?;
-        self.client().get_balance(0).await
+        let balance = self.client().get_balance(0).await?;
+
+        Ok(balance.balance)
     }
 
     pub async fn refresh(&self) -> Result<Refreshed> {
@@ -49,7 +49,7 @@ impl Client {
     }
 
     /// Gets the balance of account by index.
-    pub async fn get_balance(&self, index: u32) -> Result<u64> {
+    pub async fn get_balance(&self, index: u32) -> Result<GetBalance> {
         let params = GetBalanceParams {
             account_index: index,
         };
@@ -71,9 +71,7 @@ impl Client {
 
         let res: Response<GetBalance> = serde_json::from_str(&response)?;
 
-        let balance = res.result.balance;
-
-        Ok(balance)
+        Ok(res.result)
     }
 
     pub async fn create_account(&self, label: &str) -> Result<CreateAccount> {
@@ -331,12 +329,12 @@ struct GetBalanceParams {
 }
 
 #[derive(Deserialize, Debug, Clone)]
-struct GetBalance {
-    balance: u64,
-    blocks_to_unlock: u32,
-    multisig_import_needed: bool,
-    time_to_unlock: u32,
-    unlocked_balance: u64,
+pub struct GetBalance {
+    pub balance: u64,
+    pub blocks_to_unlock: u32,
+    pub multisig_import_needed: bool,
+    pub time_to_unlock: u32,
+    pub unlocked_balance: u64,
 }
 
 #[derive(Serialize, Debug, Clone)]
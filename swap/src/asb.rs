@@ -1,14 +1,40 @@
+pub mod binance;
+pub mod bitfinex;
 pub mod command;
 pub mod config;
 pub mod fixed_rate;
 pub mod kraken;
+pub mod median;
 
 mod amounts;
+mod peer_limits;
+mod websocket;
 
-pub use amounts::Rate;
+pub use amounts::{QuoteBelowFlatFee, Rate, Spread, SpreadTier};
+pub use peer_limits::{LimitExceeded, PeerLimits};
+
+use std::time::Instant;
 
 pub trait LatestRate {
     type Error: std::error::Error + Send + Sync + 'static;
 
     fn latest_rate(&mut self) -> Result<Rate, Self::Error>;
 }
+
+/// A rate together with the last time the feed it came from confirmed it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateUpdate {
+    pub rate: Rate,
+    pub received_at: Instant,
+}
+
+/// A price feed that can be aggregated with other feeds.
+///
+/// In contrast to [`LatestRate`] a feed also reports how old its rate is, so
+/// that stale feeds can be told apart from fresh ones.
+pub trait RateFeed: Send {
+    /// Name of the feed, used in log messages.
+    fn name(&self) -> &'static str;
+
+    fn latest_update(&mut self) -> anyhow::Result<RateUpdate>;
+}
@@ -1,6 +1,7 @@
 use crate::{bitcoin, jude};
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde::{Deserialize, Serialize};
 use std::fmt::{Debug, Display, Formatter};
 
 /// Prices at which 1 jude will be traded, in BTC (jude/BTC pair)
@@ -18,8 +19,16 @@ impl Rate {
 
     // This function takes the quote amount as it is what Bob sends to Alice in the
     // swap request
-    pub fn sell_quote(&self, quote: bitcoin::Amount) -> Result<jude::Amount> {
-        Self::quote(self.ask, quote)
+    pub fn sell_quote(&self, quote: bitcoin::Amount, spread: &Spread) -> Result<jude::Amount> {
+        let ask = spread.adjust(*self, quote)?.ask;
+        if quote <= spread.flat_fee {
+            bail!(QuoteBelowFlatFee {
+                quote,
+                flat_fee: spread.flat_fee
+            })
+        }
+
+        Self::quote(ask, quote - spread.flat_fee)
     }
 
     fn quote(rate: bitcoin::Amount, quote: bitcoin::Amount) -> Result<jude::Amount> {
@@ -48,6 +57,76 @@ impl Rate {
     }
 }
 
+#[derive(Clone, Copy, Debug, thiserror::Error, PartialEq)]
+#[error("Quote of {quote} does not cover the flat fee of {flat_fee}")]
+pub struct QuoteBelowFlatFee {
+    pub quote: bitcoin::Amount,
+    pub flat_fee: bitcoin::Amount,
+}
+
+impl QuoteBelowFlatFee {
+    /// The smallest quote that buys any jude at all.
+    pub fn min_quote(&self) -> bitcoin::Amount {
+        self.flat_fee + bitcoin::Amount::from_sat(1)
+    }
+}
+
+/// The margin we add on top of the market rate.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Spread {
+    /// Premium on top of the market ask, in percent.
+    pub percent: Decimal,
+    /// Deducted from every quote before it is converted to jude.
+    #[serde(default, with = "::bitcoin::util::amount::serde::as_btc")]
+    pub flat_fee: bitcoin::Amount,
+    /// Replace `percent` for quotes of at least the tier's size.
+    #[serde(default)]
+    pub tiers: Vec<SpreadTier>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpreadTier {
+    #[serde(with = "::bitcoin::util::amount::serde::as_btc")]
+    pub min_btc: bitcoin::Amount,
+    pub percent: Decimal,
+}
+
+impl Spread {
+    /// The percentage applying to a quote, i.e. the one of the largest tier
+    /// the quote reaches or the base percentage if it reaches none.
+    fn percent_for(&self, quote: bitcoin::Amount) -> Decimal {
+        self.tiers
+            .iter()
+            .filter(|tier| tier.min_btc <= quote)
+            .max_by_key(|tier| tier.min_btc)
+            .map_or(self.percent, |tier| tier.percent)
+    }
+
+    /// Adds the spread applying to a quote of the given size to the rate.
+    ///
+    /// The flat fee is not part of the rate as it does not scale with the
+    /// quote.
+    pub fn adjust(&self, rate: Rate, quote: bitcoin::Amount) -> Result<Rate> {
+        let factor = Decimal::from(100)
+            .checked_add(self.percent_for(quote))
+            .and_then(|percent| percent.checked_div(Decimal::from(100)))
+            .ok_or_else(|| anyhow!("spread overflow"))?;
+
+        let ask = Decimal::from(rate.ask.as_sat())
+            .checked_mul(factor)
+            .ok_or_else(|| anyhow!("multiplication overflow"))?
+            .ceil()
+            .to_u64()
+            .ok_or_else(|| anyhow!("decimal cannot be represented as u64"))?;
+
+        Ok(Rate {
+            ask: bitcoin::Amount::from_sat(ask),
+        })
+    }
+}
+
 impl Display for Rate {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.ask)
@@ -66,8 +145,88 @@ mod tests {
 
         let btc_amount = bitcoin::Amount::from_btc(2.5).unwrap();
 
-        let jude_amount = rate.sell_quote(btc_amount).unwrap();
+        let jude_amount = rate.sell_quote(btc_amount, &Spread::default()).unwrap();
 
         assert_eq!(jude_amount, jude::Amount::from_jude(1000.0).unwrap())
     }
+
+    #[test]
+    fn sell_quote_with_spread() {
+        let rate = Rate {
+            ask: bitcoin::Amount::from_btc(0.002_000).unwrap(),
+        };
+        let spread = Spread {
+            percent: Decimal::from(25),
+            flat_fee: bitcoin::Amount::from_btc(0.5).unwrap(),
+            tiers: vec![],
+        };
+
+        let btc_amount = bitcoin::Amount::from_btc(3.0).unwrap();
+
+        let jude_amount = rate.sell_quote(btc_amount, &spread).unwrap();
+
+        assert_eq!(
+            spread.adjust(rate, btc_amount).unwrap().ask,
+            bitcoin::Amount::from_btc(0.002_500).unwrap()
+        );
+        assert_eq!(jude_amount, jude::Amount::from_jude(1000.0).unwrap())
+    }
+
+    #[test]
+    fn largest_reached_tier_applies() {
+        let rate = Rate {
+            ask: bitcoin::Amount::from_sat(100_000),
+        };
+        let spread = Spread {
+            percent: Decimal::from(3),
+            flat_fee: bitcoin::Amount::ZERO,
+            tiers: vec![
+                SpreadTier {
+                    min_btc: bitcoin::Amount::from_btc(10.0).unwrap(),
+                    percent: Decimal::from(1),
+                },
+                SpreadTier {
+                    min_btc: bitcoin::Amount::from_btc(1.0).unwrap(),
+                    percent: Decimal::from(2),
+                },
+            ],
+        };
+
+        let adjusted_ask = |btc| {
+            spread
+                .adjust(rate, bitcoin::Amount::from_btc(btc).unwrap())
+                .unwrap()
+                .ask
+        };
+
+        assert_eq!(adjusted_ask(0.5), bitcoin::Amount::from_sat(103_000));
+        assert_eq!(adjusted_ask(1.0), bitcoin::Amount::from_sat(102_000));
+        assert_eq!(adjusted_ask(20.0), bitcoin::Amount::from_sat(101_000));
+    }
+
+    #[test]
+    fn given_quote_below_flat_fee_then_error() {
+        let spread = Spread {
+            flat_fee: bitcoin::Amount::from_sat(10_000),
+            ..Spread::default()
+        };
+
+        let rate = Rate {
+            ask: bitcoin::Amount::from_sat(100_000),
+        };
+
+        for quote in &[5_000, 10_000] {
+            let error = rate
+                .sell_quote(bitcoin::Amount::from_sat(*quote), &spread)
+                .unwrap_err();
+
+            assert_eq!(
+                error
+                    .downcast_ref::<QuoteBelowFlatFee>()
+                    .unwrap()
+                    .min_quote(),
+                bitcoin::Amount::from_sat(10_001)
+            );
+        }
+    }
 }
@@ -0,0 +1,120 @@
+use crate::asb::{
+    websocket::{self, Error, Ticker},
+    LatestRate, Rate, RateFeed, RateUpdate,
+};
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::watch::Receiver;
+use url::Url;
+
+const BINANCE_WS_URL: &str = "wss://stream.binance.com:9443/ws";
+const SUBSCRIBE_jude_BTC_BOOK_TICKER_PAYLOAD: &str = r#"
+{ "method": "SUBSCRIBE",
+  "params": [ "judebtc@bookTicker" ],
+  "id": 1
+}"#;
+
+#[derive(Clone)]
+pub struct RateService {
+    receiver: Receiver<Result<RateUpdate, Error>>,
+}
+
+impl LatestRate for RateService {
+    type Error = Error;
+
+    fn latest_rate(&mut self) -> Result<Rate, Self::Error> {
+        (*self.receiver.borrow()).clone().map(|update| update.rate)
+    }
+}
+
+impl RateFeed for RateService {
+    fn name(&self) -> &'static str {
+        "Binance"
+    }
+
+    fn latest_update(&mut self) -> Result<RateUpdate> {
+        Ok((*self.receiver.borrow()).clone()?)
+    }
+}
+
+impl RateService {
+    pub async fn new() -> Result<Self> {
+        Self::connect(Url::parse(BINANCE_WS_URL).expect("valid url")).await
+    }
+
+    pub async fn connect(url: Url) -> Result<Self> {
+        let receiver = websocket::connect(
+            "Binance",
+            url,
+            SUBSCRIBE_jude_BTC_BOOK_TICKER_PAYLOAD,
+            parse,
+        )
+        .await?;
+
+        Ok(Self { receiver })
+    }
+}
+
+/// Binance answers the subscription with `{"result":null,"id":1}` and then
+/// pushes book tickers.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum StreamMessage {
+    BookTicker(BookTicker),
+    Other(Value),
+}
+
+#[derive(Debug, Deserialize)]
+struct BookTicker {
+    #[serde(rename = "a")]
+    ask: String,
+}
+
+fn parse(msg: &str) -> Result<Ticker, Error> {
+    let ticker = match serde_json::from_str::<StreamMessage>(msg)? {
+        StreamMessage::BookTicker(ticker) => ticker,
+        StreamMessage::Other(_) => return Ok(Ticker::Ignored),
+    };
+
+    let ask = bitcoin::Amount::from_str_in(&ticker.ask, ::bitcoin::Denomination::Bitcoin)?;
+
+    Ok(Ticker::Rate(Rate { ask }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asb::websocket::stand_in;
+
+    const SUBSCRIBED: &str = r#"{"result":null,"id":1}"#;
+    const BOOK_TICKER: &str = r#"{"u":400900217,"s":"judeBTC","b":"0.00520600","B":"70.35668921","a":"0.00521900","A":"4.84775132"}"#;
+
+    #[test]
+    fn parse_book_ticker() {
+        let ticker = parse(BOOK_TICKER).unwrap();
+
+        assert_eq!(
+            ticker,
+            Ticker::Rate(Rate {
+                ask: bitcoin::Amount::from_sat(521_900)
+            })
+        );
+    }
+
+    #[test]
+    fn subscription_confirmation_is_ignored() {
+        assert_eq!(parse(SUBSCRIBED).unwrap(), Ticker::Ignored);
+    }
+
+    #[tokio::test]
+    async fn receives_rate_from_stand_in() {
+        let (url, subscription) = stand_in::serve(vec![SUBSCRIBED, BOOK_TICKER]).await;
+
+        let mut rate_service = RateService::connect(url).await.unwrap();
+        let update = stand_in::wait_for_update(&mut rate_service).await;
+
+        assert!(subscription.await.unwrap().contains("judebtc@bookTicker"));
+        assert_eq!(update.rate.ask, bitcoin::Amount::from_sat(521_900));
+    }
+}
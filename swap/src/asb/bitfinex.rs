@@ -0,0 +1,173 @@
+use crate::asb::{
+    websocket::{self, Error, Ticker},
+    LatestRate, Rate, RateFeed, RateUpdate,
+};
+use anyhow::Result;
+use serde::{de::IgnoredAny, Deserialize};
+use serde_json::{value::RawValue, Value};
+use tokio::sync::watch::Receiver;
+use url::Url;
+
+const BITFINEX_WS_URL: &str = "wss://api-pub.bitfinex.com/ws/2";
+const SUBSCRIBE_jude_BTC_TICKER_PAYLOAD: &str = r#"
+{ "event": "subscribe",
+  "channel": "ticker",
+  "symbol": "tjudeBTC"
+}"#;
+
+#[derive(Clone)]
+pub struct RateService {
+    receiver: Receiver<Result<RateUpdate, Error>>,
+}
+
+impl LatestRate for RateService {
+    type Error = Error;
+
+    fn latest_rate(&mut self) -> Result<Rate, Self::Error> {
+        (*self.receiver.borrow()).clone().map(|update| update.rate)
+    }
+}
+
+impl RateFeed for RateService {
+    fn name(&self) -> &'static str {
+        "Bitfinex"
+    }
+
+    fn latest_update(&mut self) -> Result<RateUpdate> {
+        Ok((*self.receiver.borrow()).clone()?)
+    }
+}
+
+impl RateService {
+    pub async fn new() -> Result<Self> {
+        Self::connect(Url::parse(BITFINEX_WS_URL).expect("valid url")).await
+    }
+
+    pub async fn connect(url: Url) -> Result<Self> {
+        let receiver =
+            websocket::connect("Bitfinex", url, SUBSCRIBE_jude_BTC_TICKER_PAYLOAD, parse).await?;
+
+        Ok(Self { receiver })
+    }
+}
+
+/// Bitfinex sends events such as the subscription confirmation as objects
+/// and channel updates as arrays prefixed with the channel id.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum StreamMessage {
+    Ticker(u64, Vec<Value>),
+    Heartbeat(u64, String),
+    Event(Value),
+}
+
+/// `[BID, BID_SIZE, ASK, ASK_SIZE, DAILY_CHANGE, DAILY_CHANGE_RELATIVE,
+/// LAST_PRICE, VOLUME, HIGH, LOW]`
+///
+/// Prices are sent as JSON numbers, the ask is kept as sent so that it is
+/// parsed from its decimal representation rather than through a float.
+#[derive(Debug, Deserialize)]
+struct TickerData(
+    IgnoredAny,
+    IgnoredAny,
+    Box<RawValue>,
+    IgnoredAny,
+    IgnoredAny,
+    IgnoredAny,
+    IgnoredAny,
+    IgnoredAny,
+    IgnoredAny,
+    IgnoredAny,
+);
+
+fn parse(msg: &str) -> Result<Ticker, Error> {
+    match serde_json::from_str::<StreamMessage>(msg)? {
+        StreamMessage::Ticker(..) => {}
+        StreamMessage::Heartbeat(..) => return Ok(Ticker::Heartbeat),
+        StreamMessage::Event(_) => return Ok(Ticker::Ignored),
+    };
+
+    // Raw values cannot be read through an untagged enum, hence the ticker is
+    // parsed a second time.
+    let (_, ticker) = serde_json::from_str::<(u64, TickerData)>(msg)?;
+    let ask = bitcoin::Amount::from_str_in(ticker.2.get(), ::bitcoin::Denomination::Bitcoin)?;
+
+    Ok(Ticker::Rate(Rate { ask }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asb::websocket::stand_in;
+    use std::time::Duration;
+
+    const SUBSCRIBED: &str = r#"{"event":"subscribed","channel":"ticker","chanId":224555,"symbol":"tjudeBTC","pair":"judeBTC"}"#;
+    const TICKER: &str = r#"[224555,[0.005206,70.35668921,0.005219,4.84775132,0.0000317,0.0061,0.005207,18530.4051086,0.00525,0.004483]]"#;
+    const HEARTBEAT: &str = r#"[224555,"hb"]"#;
+
+    #[test]
+    fn parse_ticker() {
+        let ticker = parse(TICKER).unwrap();
+
+        assert_eq!(
+            ticker,
+            Ticker::Rate(Rate {
+                ask: bitcoin::Amount::from_sat(521_900)
+            })
+        );
+    }
+
+    #[test]
+    fn ask_is_parsed_from_its_decimal_representation() {
+        let precise =
+            r#"[224555,[0.005206,70.3,0.00521901,4.8,0.0,0.0,0.005207,18530.4,0.00525,0.004483]]"#;
+        let too_precise =
+            r#"[224555,[0.005206,70.3,0.005219011,4.8,0.0,0.0,0.005207,18530.4,0.00525,0.004483]]"#;
+
+        assert_eq!(
+            parse(precise).unwrap(),
+            Ticker::Rate(Rate {
+                ask: bitcoin::Amount::from_sat(521_901)
+            })
+        );
+        assert!(matches!(
+            parse(too_precise),
+            Err(Error::BitcoinParseAmount(_))
+        ));
+    }
+
+    #[test]
+    fn events_and_heartbeats_carry_no_rate() {
+        assert_eq!(parse(SUBSCRIBED).unwrap(), Ticker::Ignored);
+        assert_eq!(parse(HEARTBEAT).unwrap(), Ticker::Heartbeat);
+    }
+
+    #[tokio::test]
+    async fn receives_rate_from_stand_in() {
+        let (url, subscription) = stand_in::serve(vec![SUBSCRIBED, TICKER, HEARTBEAT]).await;
+
+        let mut rate_service = RateService::connect(url).await.unwrap();
+        let update = stand_in::wait_for_update(&mut rate_service).await;
+
+        assert!(subscription.await.unwrap().contains("tjudeBTC"));
+        assert_eq!(update.rate.ask, bitcoin::Amount::from_sat(521_900));
+    }
+
+    #[tokio::test]
+    async fn heartbeats_do_not_refresh_the_rate() {
+        let (url, _) = stand_in::serve_in_rounds(
+            vec![vec![SUBSCRIBED, TICKER], vec![HEARTBEAT, HEARTBEAT]],
+            Duration::from_millis(200),
+        )
+        .await;
+
+        let mut rate_service = RateService::connect(url).await.unwrap();
+        let update = stand_in::wait_for_update(&mut rate_service).await;
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(
+            rate_service.latest_update().unwrap().received_at,
+            update.received_at
+        );
+    }
+}
@@ -1,6 +1,7 @@
-use crate::jude::Amount;
+use crate::{bitcoin, env::Env, jude::Amount, profile::Profile};
 use anyhow::Result;
 use std::path::PathBuf;
+use uuid::Uuid;
 
 #[derive(structopt::StructOpt, Debug)]
 pub struct Arguments {
@@ -11,6 +12,25 @@ pub struct Arguments {
     )]
     pub config: Option<PathBuf>,
 
+    #[structopt(
+        long = "env",
+        help = "The network to swap on, one of mainnet, testnet or regtest. Must match the network of an existing configuration file."
+    )]
+    pub env: Option<Env>,
+
+    #[structopt(
+        long = "profile",
+        help = "The profile to use, each profile has its own seed, database and wallets.",
+        default_value = "default"
+    )]
+    pub profile: Profile,
+
+    #[structopt(
+        long,
+        help = "Print the result as JSON to stdout, logs are still written to stderr."
+    )]
+    pub json: bool,
+
     #[structopt(subcommand)]
     pub cmd: Command,
 }
@@ -21,11 +41,113 @@ pub enum Command {
     Start {
         #[structopt(long = "max-sell-jude", help = "The maximum amount of jude the ASB is willing to sell.", default_value="0.5", parse(try_from_str = parse_jude))]
         max_sell: Amount,
+
+        #[structopt(long = "min-buy-btc", help = "The minimum amount of BTC the ASB is willing to buy.", default_value="0.001", parse(try_from_str = parse_btc))]
+        min_buy: bitcoin::Amount,
+
+        #[structopt(
+            long = "max-concurrent-swaps-per-peer",
+            help = "The maximum number of swaps a single peer can run at the same time.",
+            default_value = "3"
+        )]
+        max_concurrent_swaps_per_peer: usize,
+
+        #[structopt(
+            long = "max-swaps-per-peer-per-hour",
+            help = "The maximum number of swaps a single peer can start within an hour.",
+            default_value = "10"
+        )]
+        max_swaps_per_peer_per_hour: usize,
     },
     History,
+    SwapDetails {
+        #[structopt(long = "swap-id")]
+        swap_id: Uuid,
+    },
+    Balance,
+    DepositAddress,
+    WithdrawBtc {
+        #[structopt(long = "address", help = "The address to send the BTC to.")]
+        address: bitcoin::Address,
+
+        #[structopt(
+            long = "amount",
+            help = "The amount of BTC to withdraw. Withdraws the whole balance of the internal wallet if not given.",
+            parse(try_from_str = parse_btc)
+        )]
+        amount: Option<bitcoin::Amount>,
+    },
+    Export {
+        #[structopt(
+            long = "swap-id",
+            help = "The swap to export. Exports all swaps if not given."
+        )]
+        swap_id: Option<Uuid>,
+
+        #[structopt(
+            long = "file",
+            help = "The file to write the swaps to, it must not exist yet.",
+            parse(from_os_str)
+        )]
+        file: PathBuf,
+
+        #[structopt(
+            long = "encrypt",
+            help = "Encrypt the file with a passphrase that is asked for. Exports of an encrypted database are encrypted with its passphrase otherwise."
+        )]
+        encrypt: bool,
+    },
+    Import {
+        #[structopt(
+            long = "file",
+            help = "A file written by the export command. Swaps already known are skipped.",
+            parse(from_os_str)
+        )]
+        file: PathBuf,
+    },
+    #[structopt(
+        about = "Encrypts the seed and the database with a new passphrase, removes the encryption if the passphrase is empty. The new passphrase is read from SWAP_NEW_PASSPHRASE if set."
+    )]
+    ChangePassword,
+    #[structopt(
+        about = "Prints the seed as a mnemonic of 24 words. Everything needed to restore the Bitcoin wallet and the peer id can be restored from it."
+    )]
+    ExportSeed,
+    #[structopt(
+        about = "Replaces the seed with one restored from a mnemonic and rescans the Bitcoin wallet. The mnemonic is asked for, or read from stdin if it is not a terminal."
+    )]
+    RestoreSeed {
+        #[structopt(
+            long = "rescan-from-height",
+            help = "The block height from which the Bitcoin wallet transactions are fetched again.",
+            default_value = "0"
+        )]
+        rescan_from_height: u32,
+
+        #[structopt(
+            long = "force",
+            help = "Replace a different seed even if swaps or a Bitcoin wallet depend on it."
+        )]
+        force: bool,
+    },
+    Profiles {
+        #[structopt(subcommand)]
+        cmd: ProfilesCommand,
+    },
+}
+
+#[derive(structopt::StructOpt, Debug)]
+pub enum ProfilesCommand {
+    #[structopt(about = "Lists the default profile and every profile that has been used.")]
+    List,
 }
 
 fn parse_jude(str: &str) -> Result<Amount> {
     let amount = Amount::parse_jude(str)?;
     Ok(amount)
 }
+
+fn parse_btc(str: &str) -> Result<bitcoin::Amount> {
+    let amount = bitcoin::Amount::from_str_in(str, ::bitcoin::Denomination::Bitcoin)?;
+    Ok(amount)
+}
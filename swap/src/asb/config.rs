@@ -1,5 +1,10 @@
-use crate::fs::{default_data_dir, ensure_directory_exists};
-use anyhow::{Context, Result};
+use crate::{
+    asb::Spread,
+    bitcoin::FeeConfig,
+    env::Env,
+    fs::{default_data_dir, ensure_directory_exists},
+};
+use anyhow::{anyhow, Context, Result};
 use config::ConfigError;
 use dialoguer::{theme::ColorfulTheme, Input};
 use libp2p::core::Multiaddr;
@@ -13,16 +18,21 @@ use tracing::info;
 use url::Url;
 
 const DEFAULT_LISTEN_ADDRESS: &str = "/ip4/0.0.0.0/tcp/9939";
-const DEFAULT_ELECTRUM_HTTP_URL: &str = "https://blockstream.info/testnet/api/";
-const DEFAULT_ELECTRUM_RPC_URL: &str = "ssl://electrum.blockstream.info:60002";
-const DEFAULT_jude_WALLET_RPC_TESTNET_URL: &str = "http://127.0.0.1:38083/json_rpc";
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, PartialEq)]
 pub struct Config {
+    #[serde(default)]
+    pub env: Env,
     pub data: Data,
     pub network: Network,
     pub bitcoin: Bitcoin,
     pub jude: jude,
+    #[serde(default)]
+    pub price_feeds: PriceFeeds,
+    #[serde(default)]
+    pub spread: Spread,
+    #[serde(default)]
+    pub tor: Option<Tor>,
 }
 
 impl Config {
@@ -36,6 +46,12 @@ impl Config {
         config.merge(config::File::from(config_file))?;
         config.try_into()
     }
+
+    /// The directory holding the seed, database and wallets of the configured
+    /// environment.
+    pub fn env_data_dir(&self) -> PathBuf {
+        self.env.data_dir(&self.data.dir)
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -48,6 +64,24 @@ pub struct Data {
 #[serde(deny_unknown_fields)]
 pub struct Network {
     pub listen: Multiaddr,
+    /// Where to register as a seller, including the peer id of the
+    /// rendezvous point, e.g. `/ip4/1.2.3.4/tcp/8888/p2p/12D3KooW...`.
+    #[serde(default)]
+    pub rendezvous_point: Option<Multiaddr>,
+    /// The addresses advertised at the rendezvous point, required to register
+    /// there unless the asb is reachable through Tor.
+    #[serde(default)]
+    pub external_addresses: Vec<Multiaddr>,
+}
+
+/// Makes the asb reachable as an onion service, which is advertised at the
+/// rendezvous point instead of the listen addresses.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tor {
+    /// The control port of the local Tor daemon through which the onion
+    /// service is published.
+    pub control_port: u16,
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -55,6 +89,8 @@ pub struct Network {
 pub struct Bitcoin {
     pub electrum_http_url: Url,
     pub electrum_rpc_url: Url,
+    #[serde(default)]
+    pub fees: FeeConfig,
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -63,6 +99,39 @@ pub struct jude {
     pub wallet_rpc_url: Url,
 }
 
+/// The exchanges our rate is derived from, see [`crate::asb::median`].
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PriceFeeds {
+    pub sources: Vec<PriceSource>,
+    /// The minimum number of fresh sources required to quote a rate.
+    pub min_sources: usize,
+    /// Sources that have not confirmed their rate for longer than this are
+    /// considered stale.
+    pub max_age_secs: u64,
+    /// Sources deviating from the median by more than this are ignored.
+    pub max_deviation_percent: f64,
+}
+
+impl Default for PriceFeeds {
+    fn default() -> Self {
+        Self {
+            sources: vec![PriceSource::Kraken],
+            min_sources: 1,
+            max_age_secs: 120,
+            max_deviation_percent: 5.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, strum::Display)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceSource {
+    Kraken,
+    Binance,
+    Bitfinex,
+}
+
 #[derive(thiserror::Error, Debug, Clone, Copy)]
 #[error("config not initialized")]
 pub struct ConfigNotInitialized {}
@@ -101,7 +170,7 @@ where
     Ok(())
 }
 
-pub fn query_user_for_initial_testnet_config() -> Result<Config> {
+pub fn query_user_for_initial_config(env: Env) -> Result<Config> {
     println!();
     let data_dir = Input::with_theme(&ColorfulTheme::default())
         .with_prompt("Enter data directory for asb or hit return to use default")
@@ -121,44 +190,98 @@ pub fn query_user_for_initial_testnet_config() -> Result<Config> {
         .interact_text()?;
     let listen_address = listen_address.as_str().parse()?;
 
+    let rendezvous_point: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(
+            "Enter multiaddress of a rendezvous point to register at or hit return to not register",
+        )
+        .allow_empty(true)
+        .interact_text()?;
+    let rendezvous_point = match rendezvous_point.trim() {
+        "" => None,
+        rendezvous_point => Some(rendezvous_point.parse()?),
+    };
+
+    let external_addresses = match rendezvous_point {
+        Some(_) => {
+            let external_addresses: String = Input::with_theme(&ColorfulTheme::default())
+                .with_prompt(
+                    "Enter the comma-separated multiaddresses others can reach the asb at, to advertise at the rendezvous point",
+                )
+                .validate_with(|input: &String| -> Result<(), String> {
+                    parse_multiaddresses(input).map(|_| ())
+                })
+                .interact_text()?;
+            parse_multiaddresses(&external_addresses).map_err(|error| anyhow!(error))?
+        }
+        None => vec![],
+    };
+
     let electrum_http_url: String = Input::with_theme(&ColorfulTheme::default())
         .with_prompt("Enter Electrum HTTP URL or hit return to use default")
-        .default(DEFAULT_ELECTRUM_HTTP_URL.to_owned())
+        .default(env.default_electrum_http_url().to_string())
         .interact_text()?;
     let electrum_http_url = Url::parse(electrum_http_url.as_str())?;
 
     let electrum_rpc_url: String = Input::with_theme(&ColorfulTheme::default())
         .with_prompt("Enter Electrum RPC URL or hit return to use default")
-        .default(DEFAULT_ELECTRUM_RPC_URL.to_owned())
+        .default(env.default_electrum_rpc_url().to_string())
         .interact_text()?;
     let electrum_rpc_url = Url::parse(electrum_rpc_url.as_str())?;
 
     let jude_wallet_rpc_url = Input::with_theme(&ColorfulTheme::default())
         .with_prompt("Enter jude Wallet RPC URL or hit enter to use default")
-        .default(DEFAULT_jude_WALLET_RPC_TESTNET_URL.to_owned())
+        .default(env.default_jude_wallet_rpc_url().to_string())
         .interact_text()?;
     let jude_wallet_rpc_url = jude_wallet_rpc_url.as_str().parse()?;
     println!();
 
     Ok(Config {
+        env,
         data: Data { dir: data_dir },
         network: Network {
             listen: listen_address,
+            rendezvous_point,
+            external_addresses,
         },
         bitcoin: Bitcoin {
             electrum_http_url,
             electrum_rpc_url,
+            fees: FeeConfig::default(),
         },
         jude: jude {
             wallet_rpc_url: jude_wallet_rpc_url,
         },
+        price_feeds: PriceFeeds::default(),
+        spread: Spread::default(),
+        tor: None,
     })
 }
 
+/// Parses a comma-separated list of at least one multiaddress.
+fn parse_multiaddresses(input: &str) -> Result<Vec<Multiaddr>, String> {
+    let addresses = input
+        .split(',')
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .map(|address| {
+            address
+                .parse()
+                .map_err(|error| format!("Invalid multiaddress {}: {}", address, error))
+        })
+        .collect::<Result<Vec<Multiaddr>, String>>()?;
+
+    if addresses.is_empty() {
+        return Err("At least one address is required".to_string());
+    }
+
+    Ok(addresses)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::str::FromStr;
+    use crate::asb::SpreadTier;
+    use rust_decimal::Decimal;
     use tempfile::tempdir;
 
     #[test]
@@ -167,20 +290,48 @@ mod tests {
         let config_path = Path::join(&temp_dir, "config.toml");
 
         let expected = Config {
+            env: Env::Mainnet,
             data: Data {
                 dir: Default::default(),
             },
             bitcoin: Bitcoin {
-                electrum_http_url: Url::from_str(DEFAULT_ELECTRUM_HTTP_URL).unwrap(),
-                electrum_rpc_url: Url::from_str(DEFAULT_ELECTRUM_RPC_URL).unwrap(),
+                electrum_http_url: Env::Mainnet.default_electrum_http_url(),
+                electrum_rpc_url: Env::Mainnet.default_electrum_rpc_url(),
+                fees: FeeConfig {
+                    target_block: 3,
+                    min_sat_per_vb: 2.0,
+                    max_sat_per_vb: 50.0,
+                    fallback_sat_per_vb: 10.0,
+                },
             },
             network: Network {
                 listen: DEFAULT_LISTEN_ADDRESS.parse().unwrap(),
+                rendezvous_point: None,
+                external_addresses: vec![],
             },
 
             jude: jude {
-                wallet_rpc_url: Url::from_str(DEFAULT_jude_WALLET_RPC_TESTNET_URL).unwrap(),
+                wallet_rpc_url: Env::Mainnet.default_jude_wallet_rpc_url(),
+            },
+            price_feeds: PriceFeeds {
+                sources: vec![
+                    PriceSource::Kraken,
+                    PriceSource::Binance,
+                    PriceSource::Bitfinex,
+                ],
+                min_sources: 2,
+                max_age_secs: 60,
+                max_deviation_percent: 2.5,
             },
+            spread: Spread {
+                percent: Decimal::new(15, 1),
+                flat_fee: ::bitcoin::Amount::from_sat(10_000),
+                tiers: vec![SpreadTier {
+                    min_btc: ::bitcoin::Amount::ONE_BTC,
+                    percent: Decimal::from(1),
+                }],
+            },
+            tor: Some(Tor { control_port: 9051 }),
         };
 
         initial_setup(config_path.clone(), || Ok(expected.clone())).unwrap();
@@ -188,4 +339,17 @@ mod tests {
 
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn parses_comma_separated_external_addresses() {
+        assert_eq!(
+            parse_multiaddresses("/ip4/1.2.3.4/tcp/9939, /dns4/example.com/tcp/9939,").unwrap(),
+            vec![
+                "/ip4/1.2.3.4/tcp/9939".parse::<Multiaddr>().unwrap(),
+                "/dns4/example.com/tcp/9939".parse().unwrap(),
+            ]
+        );
+        assert!(parse_multiaddresses(" ").is_err());
+        assert!(parse_multiaddresses("example.com:9939").is_err());
+    }
 }
@@ -1,15 +1,14 @@
-use crate::asb::{LatestRate, Rate};
+use crate::asb::{
+    websocket::{self, FeedError, Ticker},
+    LatestRate, Rate, RateFeed, RateUpdate,
+};
 use anyhow::Result;
 use bitcoin::util::amount::ParseAmountError;
-use futures::{SinkExt, StreamExt};
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::convert::TryFrom;
-use tokio::sync::watch;
-use tokio_tungstenite::tungstenite::{protocol::CloseFrame, Message};
-use tracing::{error, trace};
-use watch::Receiver;
+use tokio::sync::watch::Receiver;
 
 const KRAKEN_WS_URL: &str = "wss://ws.kraken.com";
 const SUBSCRIBE_jude_BTC_TICKER_PAYLOAD: &str = r#"
@@ -22,14 +21,24 @@ const SUBSCRIBE_jude_BTC_TICKER_PAYLOAD: &str = r#"
 
 #[derive(Clone)]
 pub struct RateService {
-    receiver: Receiver<Result<Rate, Error>>,
+    receiver: Receiver<Result<RateUpdate, Error>>,
 }
 
 impl LatestRate for RateService {
     type Error = Error;
 
     fn latest_rate(&mut self) -> Result<Rate, Self::Error> {
-        (*self.receiver.borrow()).clone()
+        (*self.receiver.borrow()).clone().map(|update| update.rate)
+    }
+}
+
+impl RateFeed for RateService {
+    fn name(&self) -> &'static str {
+        "Kraken"
+    }
+
+    fn latest_update(&mut self) -> Result<RateUpdate> {
+        Ok((*self.receiver.borrow()).clone()?)
     }
 }
 
@@ -53,6 +62,16 @@ pub enum Error {
     BitcoinParseAmount(#[from] ParseAmountError),
 }
 
+impl FeedError for Error {
+    fn not_yet_retrieved() -> Self {
+        Error::NotYetRetrieved
+    }
+
+    fn close_message() -> Self {
+        Error::CloseMessage
+    }
+}
+
 impl From<tokio_tungstenite::tungstenite::Error> for Error {
     fn from(err: tokio_tungstenite::tungstenite::Error) -> Self {
         Error::WebSocket(format!("{:#}", err))
@@ -67,78 +86,36 @@ impl From<serde_json::Error> for Error {
 
 impl RateService {
     pub async fn new() -> Result<Self> {
-        let (rate_update, rate_update_receiver) = watch::channel(Err(Error::NotYetRetrieved));
-
-        let (rate_stream, _response) =
-            tokio_tungstenite::connect_async(Url::parse(KRAKEN_WS_URL).expect("valid url")).await?;
-
-        let (mut rate_stream_sink, mut rate_stream) = rate_stream.split();
-
-        tokio::spawn(async move {
-            while let Some(msg) = rate_stream.next().await {
-                let msg = match msg {
-                    Ok(Message::Text(msg)) => msg,
-                    Ok(Message::Close(close_frame)) => {
-                        if let Some(CloseFrame { code, reason }) = close_frame {
-                            error!(
-                                "Kraken rate stream was closed with code {} and reason: {}",
-                                code, reason
-                            );
-                        } else {
-                            error!("Kraken rate stream was closed without code and reason");
-                        }
-                        let _ = rate_update.send(Err(Error::CloseMessage));
-                        continue;
-                    }
-                    Ok(msg) => {
-                        trace!(
-                            "Kraken rate stream returned non text message that will be ignored: {}",
-                            msg
-                        );
-                        continue;
-                    }
-                    Err(e) => {
-                        error!("Error when reading from Kraken rate stream: {}", e);
-                        let _ = rate_update.send(Err(e.into()));
-                        continue;
-                    }
-                };
-
-                // If we encounter a heartbeat we skip it and iterate again
-                if msg.eq(r#"{"event":"heartbeat"}"#) {
-                    continue;
-                }
-
-                let ticker = match serde_json::from_str::<TickerUpdate>(&msg) {
-                    Ok(ticker) => ticker,
-                    Err(e) => {
-                        let _ = rate_update.send(Err(e.into()));
-                        continue;
-                    }
-                };
-
-                let rate = match Rate::try_from(ticker) {
-                    Ok(rate) => rate,
-                    Err(e) => {
-                        let _ = rate_update.send(Err(e));
-                        continue;
-                    }
-                };
-
-                let _ = rate_update.send(Ok(rate));
-            }
-        });
+        Self::connect(Url::parse(KRAKEN_WS_URL).expect("valid url")).await
+    }
 
-        rate_stream_sink
-            .send(SUBSCRIBE_jude_BTC_TICKER_PAYLOAD.into())
-            .await?;
+    pub async fn connect(url: Url) -> Result<Self> {
+        let receiver =
+            websocket::connect("Kraken", url, SUBSCRIBE_jude_BTC_TICKER_PAYLOAD, parse).await?;
 
-        Ok(Self {
-            receiver: rate_update_receiver,
-        })
+        Ok(Self { receiver })
     }
 }
 
+fn parse(msg: &str) -> Result<Ticker, Error> {
+    let ticker = match serde_json::from_str::<StreamMessage>(msg)? {
+        StreamMessage::Ticker(ticker) => ticker,
+        StreamMessage::Event { event } if event == "heartbeat" => return Ok(Ticker::Heartbeat),
+        StreamMessage::Event { .. } => return Ok(Ticker::Ignored),
+    };
+
+    Ok(Ticker::Rate(Rate::try_from(ticker)?))
+}
+
+/// Tickers are sent as arrays, everything else, e.g. the `systemStatus` and
+/// `subscriptionStatus` events, as objects naming the event.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum StreamMessage {
+    Ticker(TickerUpdate),
+    Event { event: String },
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(transparent)]
 struct TickerUpdate(Vec<TickerField>);
@@ -192,6 +169,7 @@ impl TryFrom<TickerUpdate> for Rate {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::asb::websocket::stand_in;
 
     #[tokio::test]
     async fn deserialize_ticker_update() {
@@ -199,4 +177,33 @@ mod tests {
 
         let _ = serde_json::from_str::<TickerUpdate>(sample_response).unwrap();
     }
+
+    #[test]
+    fn events_and_heartbeats_carry_no_rate() {
+        let system_status = r#"{"connectionID":8628615390848610000,"event":"systemStatus","status":"maintenance","version":"1.0.0"}"#;
+        let subscribed = r#"{"channelID":980,"channelName":"ticker","event":"subscriptionStatus","pair":"jude/XBT","status":"subscribed","subscription":{"name":"ticker"}}"#;
+
+        assert_eq!(parse(system_status).unwrap(), Ticker::Ignored);
+        assert_eq!(parse(subscribed).unwrap(), Ticker::Ignored);
+        assert_eq!(
+            parse(r#"{"event":"heartbeat"}"#).unwrap(),
+            Ticker::Heartbeat
+        );
+    }
+
+    #[tokio::test]
+    async fn receives_rate_from_stand_in() {
+        let (url, subscription) = stand_in::serve(vec![
+            r#"{"connectionID":8628615390848610000,"event":"systemStatus","status":"online","version":"1.0.0"}"#,
+            r#"{"event":"heartbeat"}"#,
+            r#"[980,{"a":["0.00521900",4,"4.84775132"],"b":["0.00520600",70,"70.35668921"]},"ticker","jude/XBT"]"#,
+        ])
+        .await;
+
+        let mut rate_service = RateService::connect(url).await.unwrap();
+        let update = stand_in::wait_for_update(&mut rate_service).await;
+
+        assert!(subscription.await.unwrap().contains("jude/XBT"));
+        assert_eq!(update.rate.ask, bitcoin::Amount::from_sat(521_900));
+    }
 }
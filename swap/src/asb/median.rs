@@ -0,0 +1,276 @@
+//! Aggregates several price feeds into a single rate.
+//!
+//! The rate is the median ask of all feeds that are fresh, i.e. confirmed
+//! their rate within `max_age`, and that do not deviate from the median of
+//! the fresh feeds by more than `max_deviation_percent`. If fewer than
+//! `min_sources` feeds remain no rate is returned at all, so that a single
+//! broken or manipulated exchange cannot set our price.
+
+use crate::{
+    asb::{
+        binance, bitfinex,
+        config::{PriceFeeds, PriceSource},
+        kraken, LatestRate, Rate, RateFeed,
+    },
+    bitcoin,
+};
+use anyhow::{bail, Result};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+#[allow(missing_debug_implementations)]
+pub struct RateService {
+    feeds: Vec<Box<dyn RateFeed>>,
+    min_sources: usize,
+    max_age: Duration,
+    max_deviation_percent: f64,
+}
+
+#[derive(Clone, Copy, Debug, thiserror::Error, PartialEq)]
+pub enum Error {
+    #[error("Only {fresh} of the required {required} price feeds are available")]
+    NotEnoughSources { fresh: usize, required: usize },
+}
+
+impl RateService {
+    pub fn new(
+        feeds: Vec<Box<dyn RateFeed>>,
+        min_sources: usize,
+        max_age: Duration,
+        max_deviation_percent: f64,
+    ) -> Self {
+        Self {
+            feeds,
+            min_sources: min_sources.max(1),
+            max_age,
+            max_deviation_percent,
+        }
+    }
+
+    /// Connects to all configured sources.
+    ///
+    /// Sources that cannot be reached are skipped, as long as enough remain
+    /// to satisfy `min_sources`.
+    pub async fn connect(config: &PriceFeeds) -> Result<Self> {
+        let mut feeds = Vec::<Box<dyn RateFeed>>::new();
+
+        for source in &config.sources {
+            let feed = match source {
+                PriceSource::Kraken => kraken::RateService::new()
+                    .await
+                    .map(|feed| Box::new(feed) as Box<dyn RateFeed>),
+                PriceSource::Binance => binance::RateService::new()
+                    .await
+                    .map(|feed| Box::new(feed) as Box<dyn RateFeed>),
+                PriceSource::Bitfinex => bitfinex::RateService::new()
+                    .await
+                    .map(|feed| Box::new(feed) as Box<dyn RateFeed>),
+            };
+
+            match feed {
+                Ok(feed) => feeds.push(feed),
+                Err(e) => warn!("Failed to connect to {} price feed: {:#}", source, e),
+            }
+        }
+
+        let rate_service = Self::new(
+            feeds,
+            config.min_sources,
+            Duration::from_secs(config.max_age_secs),
+            config.max_deviation_percent,
+        );
+
+        if rate_service.feeds.len() < rate_service.min_sources {
+            bail!(Error::NotEnoughSources {
+                fresh: rate_service.feeds.len(),
+                required: rate_service.min_sources
+            })
+        }
+
+        Ok(rate_service)
+    }
+}
+
+impl LatestRate for RateService {
+    type Error = Error;
+
+    fn latest_rate(&mut self) -> Result<Rate, Error> {
+        let now = Instant::now();
+        let max_age = self.max_age;
+
+        let asks = self
+            .feeds
+            .iter_mut()
+            .filter_map(|feed| match feed.latest_update() {
+                Ok(update) if now.saturating_duration_since(update.received_at) <= max_age => {
+                    Some(update.rate.ask)
+                }
+                Ok(update) => {
+                    debug!(
+                        "Ignoring {} rate, last update was {}s ago",
+                        feed.name(),
+                        now.saturating_duration_since(update.received_at).as_secs()
+                    );
+                    None
+                }
+                Err(e) => {
+                    debug!("Ignoring {} rate: {:#}", feed.name(), e);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let not_enough_sources = |fresh| Error::NotEnoughSources {
+            fresh,
+            required: self.min_sources,
+        };
+
+        let median_of_fresh = median(&asks).ok_or_else(|| not_enough_sources(0))?;
+        let asks = asks
+            .into_iter()
+            .filter(|ask| {
+                let deviation = deviation_percent(*ask, median_of_fresh);
+                if deviation > self.max_deviation_percent {
+                    debug!(
+                        "Ignoring outlier rate {}, deviates {:.2}% from median {}",
+                        ask, deviation, median_of_fresh
+                    );
+                    return false;
+                }
+                true
+            })
+            .collect::<Vec<_>>();
+
+        if asks.len() < self.min_sources {
+            return Err(not_enough_sources(asks.len()));
+        }
+
+        let ask = median(&asks).ok_or_else(|| not_enough_sources(0))?;
+
+        Ok(Rate { ask })
+    }
+}
+
+fn median(asks: &[bitcoin::Amount]) -> Option<bitcoin::Amount> {
+    let mut sats = asks.iter().map(|ask| ask.as_sat()).collect::<Vec<_>>();
+    sats.sort_unstable();
+
+    let middle = sats.len() / 2;
+    let median = match sats.len() {
+        0 => return None,
+        len if len % 2 == 0 => (sats[middle - 1] + sats[middle]) / 2,
+        _ => sats[middle],
+    };
+
+    Some(bitcoin::Amount::from_sat(median))
+}
+
+fn deviation_percent(ask: bitcoin::Amount, median: bitcoin::Amount) -> f64 {
+    // Rates are way below the range in which f64 loses precision.
+    #[allow(clippy::cast_precision_loss)]
+    let (ask, median) = (ask.as_sat() as f64, median.as_sat() as f64);
+
+    (ask - median).abs() / median * 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asb::RateUpdate;
+
+    struct StaticFeed(Option<RateUpdate>);
+
+    impl StaticFeed {
+        fn fresh(ask: u64) -> Box<dyn RateFeed> {
+            Box::new(Self(Some(RateUpdate {
+                rate: Rate {
+                    ask: bitcoin::Amount::from_sat(ask),
+                },
+                received_at: Instant::now(),
+            })))
+        }
+
+        fn stale(ask: u64) -> Box<dyn RateFeed> {
+            Box::new(Self(Some(RateUpdate {
+                rate: Rate {
+                    ask: bitcoin::Amount::from_sat(ask),
+                },
+                received_at: Instant::now() - Duration::from_secs(120),
+            })))
+        }
+
+        fn failing() -> Box<dyn RateFeed> {
+            Box::new(Self(None))
+        }
+    }
+
+    impl RateFeed for StaticFeed {
+        fn name(&self) -> &'static str {
+            "static"
+        }
+
+        fn latest_update(&mut self) -> Result<RateUpdate> {
+            self.0.ok_or_else(|| anyhow::anyhow!("no rate"))
+        }
+    }
+
+    fn rate_service(feeds: Vec<Box<dyn RateFeed>>, min_sources: usize) -> RateService {
+        RateService::new(feeds, min_sources, Duration::from_secs(60), 5.0)
+    }
+
+    #[test]
+    fn returns_median_of_fresh_feeds() {
+        let mut odd = rate_service(
+            vec![
+                StaticFeed::fresh(500_000),
+                StaticFeed::fresh(510_000),
+                StaticFeed::fresh(505_000),
+            ],
+            2,
+        );
+        let mut even = rate_service(
+            vec![StaticFeed::fresh(500_000), StaticFeed::fresh(510_000)],
+            2,
+        );
+
+        assert_eq!(odd.latest_rate().unwrap().ask.as_sat(), 505_000);
+        assert_eq!(even.latest_rate().unwrap().ask.as_sat(), 505_000);
+    }
+
+    #[test]
+    fn ignores_stale_failing_and_outlier_feeds() {
+        let mut rate_service = rate_service(
+            vec![
+                StaticFeed::fresh(500_000),
+                StaticFeed::fresh(502_000),
+                StaticFeed::fresh(504_000),
+                StaticFeed::fresh(900_000),
+                StaticFeed::stale(100_000),
+                StaticFeed::failing(),
+            ],
+            3,
+        );
+
+        assert_eq!(rate_service.latest_rate().unwrap().ask.as_sat(), 502_000);
+    }
+
+    #[test]
+    fn given_too_few_fresh_feeds_then_error() {
+        let mut rate_service = rate_service(
+            vec![
+                StaticFeed::fresh(500_000),
+                StaticFeed::stale(500_000),
+                StaticFeed::failing(),
+            ],
+            2,
+        );
+
+        assert_eq!(
+            rate_service.latest_rate().unwrap_err(),
+            Error::NotEnoughSources {
+                fresh: 1,
+                required: 2
+            }
+        );
+    }
+}
@@ -0,0 +1,327 @@
+use libp2p::PeerId;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+const ONE_HOUR: Duration = Duration::from_secs(60 * 60);
+
+/// How long Bob has to complete the execution setup once we accepted his
+/// quote request. Bob may ask his user to confirm the quote first.
+const SETUP_TIMEOUT: Duration = Duration::from_secs(2 * 60);
+
+/// Limits the number of swaps a single peer can set up with us.
+///
+/// Every accepted quote triggers an execution setup, hence without these
+/// limits a single peer could keep us busy by spamming quote requests.
+///
+/// A slot of the peer is taken when we accept a quote. It is given back if
+/// the execution setup fails, is not done within [`SETUP_TIMEOUT`] or the
+/// peer disconnects before it is done, otherwise once the swap is over.
+#[derive(Debug)]
+pub struct PeerLimits {
+    max_concurrent: usize,
+    max_per_hour: usize,
+    peers: HashMap<PeerId, PeerSwaps>,
+}
+
+#[derive(Debug, Default)]
+struct PeerSwaps {
+    running: usize,
+    /// The swaps whose execution setup is yet to be done, with the time by
+    /// which it has to be.
+    setups: HashMap<Uuid, Instant>,
+    started_at: VecDeque<Instant>,
+}
+
+impl PeerSwaps {
+    /// Forgets the swaps started more than an hour ago.
+    fn prune(&mut self, now: Instant) {
+        while let Some(started_at) = self.started_at.front() {
+            if now.saturating_duration_since(*started_at) < ONE_HOUR {
+                break;
+            }
+            self.started_at.pop_front();
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.running == 0 && self.setups.is_empty() && self.started_at.is_empty()
+    }
+}
+
+#[derive(Clone, Copy, Debug, thiserror::Error, PartialEq)]
+pub enum LimitExceeded {
+    #[error("Peer already has {max} swaps running")]
+    Concurrent { max: usize },
+    #[error("Peer already started {max} swaps within the last hour")]
+    PerHour { max: usize },
+}
+
+impl PeerLimits {
+    pub fn new(max_concurrent: usize, max_per_hour: usize) -> Self {
+        Self {
+            max_concurrent,
+            max_per_hour,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Reserves a slot for the execution setup of a new swap with the peer
+    /// unless that exceeds one of the limits.
+    ///
+    /// The setup has to be reported with [`PeerLimits::setup_done`] or
+    /// [`PeerLimits::setup_failed`], otherwise the slot is given back once
+    /// the setup expires or the peer disconnects.
+    pub fn try_start(
+        &mut self,
+        peer: PeerId,
+        swap_id: Uuid,
+        now: Instant,
+    ) -> Result<(), LimitExceeded> {
+        let swaps = self.peers.entry(peer).or_default();
+        swaps.prune(now);
+
+        if swaps.running + swaps.setups.len() >= self.max_concurrent {
+            return Err(LimitExceeded::Concurrent {
+                max: self.max_concurrent,
+            });
+        }
+        if swaps.started_at.len() >= self.max_per_hour {
+            return Err(LimitExceeded::PerHour {
+                max: self.max_per_hour,
+            });
+        }
+
+        swaps.setups.insert(swap_id, now + SETUP_TIMEOUT);
+        swaps.started_at.push_back(now);
+
+        Ok(())
+    }
+
+    /// Hands the slot of the setup over to the swap, which has to be paired
+    /// with a call to [`PeerLimits::finish`] once the swap is over.
+    ///
+    /// Returns false if the setup is unknown, e.g. because it expired, in
+    /// which case the swap must not be started.
+    pub fn setup_done(&mut self, peer: PeerId, swap_id: Uuid) -> bool {
+        let swaps = match self.peers.get_mut(&peer) {
+            Some(swaps) => swaps,
+            None => return false,
+        };
+
+        if swaps.setups.remove(&swap_id).is_none() {
+            return false;
+        }
+        swaps.running += 1;
+
+        true
+    }
+
+    /// Gives back the slot of a setup that failed.
+    pub fn setup_failed(&mut self, peer: PeerId, swap_id: Uuid) {
+        if let Some(swaps) = self.peers.get_mut(&peer) {
+            swaps.setups.remove(&swap_id);
+        }
+        self.forget_if_idle(peer);
+    }
+
+    /// Gives back the slots of the setups that were not done in time and
+    /// returns them. Peers without any swap in the last hour are forgotten.
+    pub fn expire_setups(&mut self, now: Instant) -> Vec<(PeerId, Uuid)> {
+        let mut expired = Vec::new();
+
+        for (peer, swaps) in self.peers.iter_mut() {
+            swaps.setups.retain(|swap_id, expires_at| {
+                if *expires_at > now {
+                    return true;
+                }
+                expired.push((*peer, *swap_id));
+                false
+            });
+            swaps.prune(now);
+        }
+        self.peers.retain(|_, swaps| !swaps.is_idle());
+
+        expired
+    }
+
+    /// Gives back the slots of the setups with a peer that disconnected, a
+    /// setup cannot continue on another connection. Returns the swaps of the
+    /// setups.
+    pub fn disconnected(&mut self, peer: PeerId, now: Instant) -> Vec<Uuid> {
+        let swap_ids = match self.peers.get_mut(&peer) {
+            Some(swaps) => {
+                swaps.prune(now);
+                swaps.setups.drain().map(|(swap_id, _)| swap_id).collect()
+            }
+            None => Vec::new(),
+        };
+        self.forget_if_idle(peer);
+
+        swap_ids
+    }
+
+    pub fn finish(&mut self, peer: PeerId, now: Instant) {
+        if let Some(swaps) = self.peers.get_mut(&peer) {
+            swaps.running = swaps.running.saturating_sub(1);
+            swaps.prune(now);
+        }
+        self.forget_if_idle(peer);
+    }
+
+    fn forget_if_idle(&mut self, peer: PeerId) {
+        if self.peers.get(&peer).map_or(false, PeerSwaps::is_idle) {
+            self.peers.remove(&peer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start_swap(limits: &mut PeerLimits, peer: PeerId, now: Instant) -> Uuid {
+        let swap_id = Uuid::new_v4();
+        limits.try_start(peer, swap_id, now).unwrap();
+        assert!(limits.setup_done(peer, swap_id));
+
+        swap_id
+    }
+
+    #[test]
+    fn given_too_many_running_swaps_then_rejects_until_one_finishes() {
+        let peer = PeerId::random();
+        let now = Instant::now();
+        let mut limits = PeerLimits::new(2, 10);
+
+        start_swap(&mut limits, peer, now);
+        start_swap(&mut limits, peer, now);
+
+        assert_eq!(
+            limits.try_start(peer, Uuid::new_v4(), now),
+            Err(LimitExceeded::Concurrent { max: 2 })
+        );
+        assert!(limits
+            .try_start(PeerId::random(), Uuid::new_v4(), now)
+            .is_ok());
+
+        limits.finish(peer, now);
+
+        assert!(limits.try_start(peer, Uuid::new_v4(), now).is_ok());
+    }
+
+    #[test]
+    fn given_too_many_swaps_within_an_hour_then_rejects_until_the_hour_passed() {
+        let peer = PeerId::random();
+        let now = Instant::now();
+        let mut limits = PeerLimits::new(10, 2);
+
+        start_swap(&mut limits, peer, now);
+        limits.finish(peer, now);
+        start_swap(&mut limits, peer, now);
+        limits.finish(peer, now);
+
+        assert_eq!(
+            limits.try_start(peer, Uuid::new_v4(), now + Duration::from_secs(60)),
+            Err(LimitExceeded::PerHour { max: 2 })
+        );
+        assert!(limits
+            .try_start(peer, Uuid::new_v4(), now + ONE_HOUR)
+            .is_ok());
+    }
+
+    #[test]
+    fn given_declined_quotes_then_slots_are_given_back_once_the_setups_expire() {
+        let peer = PeerId::random();
+        let now = Instant::now();
+        let mut limits = PeerLimits::new(2, 10);
+
+        let first = Uuid::new_v4();
+        let second = Uuid::new_v4();
+        limits.try_start(peer, first, now).unwrap();
+        limits.try_start(peer, second, now).unwrap();
+        assert!(limits.try_start(peer, Uuid::new_v4(), now).is_err());
+
+        assert!(limits.expire_setups(now + SETUP_TIMEOUT / 2).is_empty());
+
+        let mut expired = limits.expire_setups(now + SETUP_TIMEOUT);
+        expired.sort_by_key(|(_, swap_id)| *swap_id);
+        let mut expected = vec![(peer, first), (peer, second)];
+        expected.sort_by_key(|(_, swap_id)| *swap_id);
+        assert_eq!(expired, expected);
+
+        assert!(limits.try_start(peer, Uuid::new_v4(), now).is_ok());
+        // A setup that is done after it expired must not start a swap
+        assert!(!limits.setup_done(peer, first));
+    }
+
+    #[test]
+    fn given_peer_disconnects_during_setup_then_slot_is_given_back() {
+        let peer = PeerId::random();
+        let now = Instant::now();
+        let mut limits = PeerLimits::new(1, 10);
+
+        let swap_id = Uuid::new_v4();
+        limits.try_start(peer, swap_id, now).unwrap();
+        assert!(limits.try_start(peer, Uuid::new_v4(), now).is_err());
+
+        assert_eq!(limits.disconnected(peer, now), vec![swap_id]);
+
+        assert!(limits.try_start(peer, Uuid::new_v4(), now).is_ok());
+        assert!(!limits.setup_done(peer, swap_id));
+    }
+
+    #[test]
+    fn disconnect_does_not_give_back_slots_of_running_swaps() {
+        let peer = PeerId::random();
+        let now = Instant::now();
+        let mut limits = PeerLimits::new(1, 10);
+
+        start_swap(&mut limits, peer, now);
+
+        assert!(limits.disconnected(peer, now).is_empty());
+        assert!(limits.try_start(peer, Uuid::new_v4(), now).is_err());
+    }
+
+    #[test]
+    fn peers_are_forgotten_once_their_last_swap_is_an_hour_old() {
+        let now = Instant::now();
+        let mut limits = PeerLimits::new(1, 10);
+
+        let finished = PeerId::random();
+        start_swap(&mut limits, finished, now);
+        limits.finish(finished, now);
+        let declined = PeerId::random();
+        limits.try_start(declined, Uuid::new_v4(), now).unwrap();
+        let running = PeerId::random();
+        start_swap(&mut limits, running, now);
+
+        limits.expire_setups(now + SETUP_TIMEOUT);
+        assert_eq!(limits.peers.len(), 3);
+
+        limits.expire_setups(now + ONE_HOUR);
+        assert_eq!(limits.peers.keys().collect::<Vec<_>>(), vec![&running]);
+
+        limits.finish(running, now + ONE_HOUR);
+        assert!(limits.peers.is_empty());
+    }
+
+    #[test]
+    fn failed_setup_gives_back_its_slot_once() {
+        let peer = PeerId::random();
+        let now = Instant::now();
+        let mut limits = PeerLimits::new(1, 10);
+
+        let swap_id = Uuid::new_v4();
+        limits.try_start(peer, swap_id, now).unwrap();
+        limits.setup_failed(peer, swap_id);
+        start_swap(&mut limits, peer, now);
+
+        // Reported again, e.g. after it expired
+        limits.setup_failed(peer, swap_id);
+
+        assert!(limits.try_start(peer, Uuid::new_v4(), now).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! Plumbing shared by the price feeds that stream tickers from an exchange
+//! over a websocket.
+
+use crate::asb::{Rate, RateUpdate};
+use anyhow::Result;
+use bitcoin::util::amount::ParseAmountError;
+use futures::{SinkExt, StreamExt};
+use std::{fmt::Display, time::Instant};
+use tokio::sync::watch;
+use tokio_tungstenite::tungstenite::{self, protocol::CloseFrame, Message};
+use tracing::{error, trace};
+use url::Url;
+
+/// Errors every websocket feed has to be able to report in addition to its
+/// exchange specific ones.
+pub trait FeedError: From<tungstenite::Error> + Clone + Display + Send + Sync + 'static {
+    fn not_yet_retrieved() -> Self;
+    fn close_message() -> Self;
+}
+
+/// The errors of feeds that run into nothing but what every websocket feed
+/// can run into.
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Rate has not yet been retrieved from the websocket API")]
+    NotYetRetrieved,
+    #[error("Received close message")]
+    CloseMessage,
+    #[error("Websocket: {0}")]
+    WebSocket(String),
+    #[error("Serde: {0}")]
+    Serde(String),
+    #[error("Bitcoin amount parse error: {0}")]
+    BitcoinParseAmount(#[from] ParseAmountError),
+}
+
+impl FeedError for Error {
+    fn not_yet_retrieved() -> Self {
+        Error::NotYetRetrieved
+    }
+
+    fn close_message() -> Self {
+        Error::CloseMessage
+    }
+}
+
+impl From<tungstenite::Error> for Error {
+    fn from(err: tungstenite::Error) -> Self {
+        Error::WebSocket(format!("{:#}", err))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Serde(format!("{:#}", err))
+    }
+}
+
+/// What an exchange sent us over the websocket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ticker {
+    Rate(Rate),
+    /// The exchange is still alive, which says nothing about the rate.
+    Heartbeat,
+    /// Anything else, e.g. subscription confirmations.
+    Ignored,
+}
+
+/// Connects to `url`, subscribes with `subscribe_payload` and keeps the
+/// returned receiver updated with the latest rate parsed from the stream.
+///
+/// Only tickers refresh the timestamp of the latest rate, an exchange that
+/// keeps sending heartbeats but no tickers goes stale.
+pub async fn connect<E, F>(
+    exchange: &'static str,
+    url: Url,
+    subscribe_payload: &str,
+    parse: F,
+) -> Result<watch::Receiver<Result<RateUpdate, E>>>
+where
+    E: FeedError,
+    F: Fn(&str) -> Result<Ticker, E> + Send + 'static,
+{
+    let (rate_update, rate_update_receiver) = watch::channel(Err(E::not_yet_retrieved()));
+
+    let (rate_stream, _response) = tokio_tungstenite::connect_async(url).await?;
+
+    let (mut rate_stream_sink, mut rate_stream) = rate_stream.split();
+
+    tokio::spawn(async move {
+        while let Some(msg) = rate_stream.next().await {
+            let ticker = match msg {
+                Ok(Message::Text(msg)) => match parse(&msg) {
+                    Ok(ticker) => ticker,
+                    Err(e) => {
+                        let _ = rate_update.send(Err(e));
+                        continue;
+                    }
+                },
+                Ok(Message::Close(close_frame)) => {
+                    if let Some(CloseFrame { code, reason }) = close_frame {
+                        error!(
+                            "{} rate stream was closed with code {} and reason: {}",
+                            exchange, code, reason
+                        );
+                    } else {
+                        error!(
+                            "{} rate stream was closed without code and reason",
+                            exchange
+                        );
+                    }
+                    let _ = rate_update.send(Err(E::close_message()));
+                    continue;
+                }
+                Ok(msg) => {
+                    trace!(
+                        "{} rate stream returned non text message that will be ignored: {}",
+                        exchange,
+                        msg
+                    );
+                    continue;
+                }
+                Err(e) => {
+                    error!("Error when reading from {} rate stream: {}", exchange, e);
+                    let _ = rate_update.send(Err(e.into()));
+                    continue;
+                }
+            };
+
+            let rate = match ticker {
+                Ticker::Rate(rate) => rate,
+                Ticker::Heartbeat => {
+                    trace!("Received heartbeat from {}", exchange);
+                    continue;
+                }
+                Ticker::Ignored => continue,
+            };
+
+            let _ = rate_update.send(Ok(RateUpdate {
+                rate,
+                received_at: Instant::now(),
+            }));
+        }
+    });
+
+    rate_stream_sink
+        .send(Message::Text(subscribe_payload.to_owned()))
+        .await?;
+
+    Ok(rate_update_receiver)
+}
+
+#[cfg(test)]
+pub mod stand_in {
+    //! A local websocket server standing in for an exchange.
+
+    use crate::asb::{RateFeed, RateUpdate};
+    use futures::{SinkExt, StreamExt};
+    use std::time::Duration;
+    use tokio::{net::TcpListener, sync::oneshot};
+    use tokio_tungstenite::tungstenite::Message;
+    use url::Url;
+
+    /// Accepts a single client, waits for its subscription and replies with
+    /// `messages`.
+    ///
+    /// Returns the url to connect to and the subscription sent by the client.
+    pub async fn serve(messages: Vec<&'static str>) -> (Url, oneshot::Receiver<String>) {
+        serve_in_rounds(vec![messages], Duration::from_secs(0)).await
+    }
+
+    /// Like [`serve`], but pauses between the rounds of messages.
+    pub async fn serve_in_rounds(
+        rounds: Vec<Vec<&'static str>>,
+        pause: Duration,
+    ) -> (Url, oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let (subscription_sender, subscription) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut websocket = tokio_tungstenite::accept_async(stream).await.unwrap();
+
+            if let Some(Ok(Message::Text(msg))) = websocket.next().await {
+                let _ = subscription_sender.send(msg);
+            }
+
+            for messages in rounds {
+                for msg in messages {
+                    websocket.send(Message::Text(msg.to_owned())).await.unwrap();
+                }
+                tokio::time::sleep(pause).await;
+            }
+
+            // Keep the connection open for the rest of the test
+            futures::future::pending::<()>().await;
+        });
+
+        (url, subscription)
+    }
+
+    pub async fn wait_for_update(feed: &mut impl RateFeed) -> RateUpdate {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Ok(update) = feed.latest_update() {
+                    return update;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("feed to receive a rate from the stand-in")
+    }
+}
@@ -12,29 +12,37 @@
 #![forbid(unsafe_code)]
 #![allow(non_snake_case)]
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use bdk::{descriptor::Segwitv0, keys::DerivableKey};
 use prettytable::{row, Table};
-use std::{path::Path, sync::Arc};
+use std::{convert::TryFrom, fs, path::Path, sync::Arc};
 use structopt::StructOpt;
 use swap::{
     asb::{
-        command::{Arguments, Command},
+        command::{Arguments, Command, ProfilesCommand},
         config::{
-            initial_setup, query_user_for_initial_testnet_config, read_config, Config,
-            ConfigNotInitialized,
+            initial_setup, query_user_for_initial_config, read_config, Config, ConfigNotInitialized,
         },
-        kraken,
+        median, PeerLimits,
     },
     bitcoin,
-    database::Database,
-    execution_params,
-    execution_params::GetExecutionParams,
+    bitcoin::BlockHeight,
+    database::{Backup, Database},
+    encryption::{new_passphrase, prompt_new_passphrase, prompt_passphrase, PassphraseSource},
+    env::migrate_legacy_data_dir,
     fs::default_config_path,
     jude,
     jude::{Amount, CreateWallet, OpenWallet},
-    protocol::alice::EventLoop,
-    seed::Seed,
+    network::{rendezvous::RendezvousPoint, tor::OnionService},
+    output::{
+        format_duration, format_timestamp, print_balance, print_deposit_addresses, unix_timestamp,
+    },
+    profile::Profile,
+    protocol::{
+        alice::{AliceState, EventLoop},
+        details::Report,
+    },
+    seed::{read_mnemonic, Seed},
     trace::init_tracing,
 };
 use tracing::{info, warn};
@@ -44,14 +52,14 @@ use tracing_subscriber::filter::LevelFilter;
 extern crate prettytable;
 
 const DEFAULT_WALLET_NAME: &str = "asb-wallet";
-const BITCOIN_NETWORK: bitcoin::Network = bitcoin::Network::Testnet;
-const jude_NETWORK: jude::Network = jude::Network::Stagenet;
+const ONION_SERVICE_KEY_FILE: &str = "onion_service_key";
 
 #[tokio::main]
 async fn main() -> Result<()> {
     init_tracing(LevelFilter::DEBUG).expect("initialize tracing");
 
     let opt = Arguments::from_args();
+    let json = opt.json;
 
     let config_path = if let Some(config_path) = opt.config {
         config_path
@@ -62,34 +70,64 @@ async fn main() -> Result<()> {
     let config = match read_config(config_path.clone())? {
         Ok(config) => config,
         Err(ConfigNotInitialized {}) => {
-            initial_setup(config_path.clone(), query_user_for_initial_testnet_config)?;
+            let env = opt.env.unwrap_or_default();
+            initial_setup(config_path.clone(), || query_user_for_initial_config(env))?;
             read_config(config_path)?.expect("after initial setup config can be read")
         }
     };
+    let env = config.env.ensure_matches(opt.env)?;
+    migrate_legacy_data_dir(&config.data.dir)?;
+    let env_data_dir = config.env_data_dir();
+
+    if let Command::Profiles {
+        cmd: ProfilesCommand::List,
+    } = opt.cmd
+    {
+        for profile in Profile::list(&env_data_dir)? {
+            println!("{}", profile);
+        }
 
+        return Ok(());
+    }
+
+    let profile = opt.profile;
+    let data_dir = profile.data_dir(&env_data_dir);
+    let jude_wallet_name = profile.wallet_name(DEFAULT_WALLET_NAME);
+
+    info!("Running on {}", env);
+    if !profile.is_default() {
+        info!("Using profile {}", profile);
+    }
     info!(
         "Database and Seed will be stored in directory: {}",
-        config.data.dir.display()
+        data_dir.display()
     );
 
-    let db_path = config.data.dir.join("database");
+    let mut passphrase = PassphraseSource::default();
+    let mut db =
+        Database::open_with_passphrase(data_dir.join("database").as_path(), || passphrase.get())
+            .context("Could not open database")?;
+    db.ensure_env(env)?;
 
-    let db = Database::open(config.data.dir.join(db_path).as_path())
-        .context("Could not open database")?;
-
-    let wallet_data_dir = config.data.dir.join("wallet");
+    let wallet_data_dir = data_dir.join("wallet");
 
     match opt.cmd {
-        Command::Start { max_sell } => {
-            let seed = Seed::from_file_or_generate(&config.data.dir)
-                .expect("Could not retrieve/initialize seed");
+        Command::Start {
+            max_sell,
+            min_buy,
+            max_concurrent_swaps_per_peer,
+            max_swaps_per_peer_per_hour,
+        } => {
+            let seed = Seed::from_file_or_generate(&data_dir, env, || passphrase.get())
+                .context("Could not retrieve/initialize seed")?;
 
-            let execution_params = execution_params::Testnet::get_execution_params();
+            let execution_params = env.execution_params();
 
             let (bitcoin_wallet, jude_wallet) = init_wallets(
                 config.clone(),
                 &wallet_data_dir,
-                seed.derive_extended_private_key(BITCOIN_NETWORK)?,
+                seed.derive_extended_private_key(env.bitcoin_network())?,
+                &jude_wallet_name,
             )
             .await?;
 
@@ -98,36 +136,285 @@ async fn main() -> Result<()> {
                 bitcoin_wallet.new_address().await?
             );
 
-            let rate_service = kraken::RateService::new().await?;
+            let rate_service = median::RateService::connect(&config.price_feeds).await?;
 
-            let (event_loop, _) = EventLoop::new(
-                config.network.listen,
+            let (mut event_loop, _) = EventLoop::new(
+                config.network.listen.clone(),
                 seed,
                 execution_params,
                 Arc::new(bitcoin_wallet),
                 Arc::new(jude_wallet),
                 Arc::new(db),
                 rate_service,
+                config.spread.clone(),
+                min_buy,
                 max_sell,
+                PeerLimits::new(max_concurrent_swaps_per_peer, max_swaps_per_peer_per_hour),
             )
             .unwrap();
 
             info!("Our peer id is {}", event_loop.peer_id());
 
+            // Tor removes the onion service once we drop it.
+            let onion_service = match &config.tor {
+                Some(tor) => {
+                    let onion_service = OnionService::publish(
+                        tor.control_port,
+                        &config.network.listen,
+                        &data_dir.join(ONION_SERVICE_KEY_FILE),
+                    )
+                    .await
+                    .context("Could not publish onion service")?;
+                    info!("Reachable through Tor at {}", onion_service.address);
+
+                    Some(onion_service)
+                }
+                None => None,
+            };
+
+            if let Some(rendezvous_point) = config.network.rendezvous_point {
+                let rendezvous_point = RendezvousPoint::try_from(rendezvous_point)
+                    .context("Invalid rendezvous point in config")?;
+                let external_addresses = match &onion_service {
+                    Some(onion_service) => vec![onion_service.address.clone()],
+                    None => config.network.external_addresses,
+                };
+
+                info!("Registering as seller at {}", rendezvous_point);
+                event_loop
+                    .register_with(rendezvous_point, env, external_addresses)
+                    .context("Add the external_addresses others reach the asb at to the config or enable Tor")?;
+            }
+
+            event_loop.resume_unfinished_swaps().await?;
             event_loop.run().await;
         }
         Command::History => {
-            let mut table = Table::new();
+            let swaps = db.all_by_start_time()?;
+
+            if json {
+                let records = swaps
+                    .into_iter()
+                    .map(|(swap_id, state, history)| {
+                        let state = AliceState::from(state.try_into_alice()?);
+
+                        Ok(serde_json::json!({
+                            "swap_id": swap_id,
+                            "state": state.name(),
+                            "started_at": history.started_at().map(unix_timestamp),
+                            "timestamp": history.last_updated_at().map(unix_timestamp),
+                        }))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                println!("{}", serde_json::to_string(&records)?);
+            } else {
+                let mut table = Table::new();
+
+                table.add_row(row!["SWAP ID", "STARTED (UTC)", "DURATION", "STATE"]);
+
+                for (swap_id, state, history) in swaps {
+                    let started_at = history
+                        .started_at()
+                        .map_or_else(|| "unknown".to_string(), format_timestamp);
+                    let duration = history
+                        .duration()
+                        .map_or_else(|| "unknown".to_string(), format_duration);
+
+                    table.add_row(row![swap_id, started_at, duration, state]);
+                }
+
+                // Print the table to stdout
+                table.printstd();
+            }
+        }
+        Command::SwapDetails { swap_id } => {
+            let seed = Seed::from_file_or_generate(&data_dir, env, || passphrase.get())
+                .context("Could not retrieve/initialize seed")?;
+
+            let bitcoin_wallet = init_bitcoin_wallet(
+                config,
+                &wallet_data_dir,
+                seed.derive_extended_private_key(env.bitcoin_network())?,
+            )
+            .await?;
+
+            let state = db.get_state(swap_id)?.try_into_alice()?.into();
+            let report = Report::alice(swap_id, &state, &bitcoin_wallet).await?;
+
+            if json {
+                println!("{}", serde_json::to_string(&report)?);
+            } else {
+                println!("{}", report);
+            }
+        }
+        Command::Balance => {
+            let seed = Seed::from_file_or_generate(&data_dir, env, || passphrase.get())
+                .context("Could not retrieve/initialize seed")?;
+
+            let (bitcoin_wallet, jude_wallet) = init_wallets(
+                config,
+                &wallet_data_dir,
+                seed.derive_extended_private_key(env.bitcoin_network())?,
+                &jude_wallet_name,
+            )
+            .await?;
+
+            print_balance(
+                bitcoin_wallet.detailed_balance().await?,
+                jude_wallet.get_detailed_balance().await?,
+                json,
+            );
+        }
+        Command::DepositAddress => {
+            let seed = Seed::from_file_or_generate(&data_dir, env, || passphrase.get())
+                .context("Could not retrieve/initialize seed")?;
+
+            let (bitcoin_wallet, jude_wallet) = init_wallets(
+                config,
+                &wallet_data_dir,
+                seed.derive_extended_private_key(env.bitcoin_network())?,
+                &jude_wallet_name,
+            )
+            .await?;
+
+            print_deposit_addresses(
+                bitcoin_wallet.new_address().await?,
+                jude_wallet.get_main_address().await?,
+                json,
+            );
+        }
+        Command::WithdrawBtc { address, amount } => {
+            let seed = Seed::from_file_or_generate(&data_dir, env, || passphrase.get())
+                .context("Could not retrieve/initialize seed")?;
+
+            let bitcoin_wallet = init_bitcoin_wallet(
+                config,
+                &wallet_data_dir,
+                seed.derive_extended_private_key(env.bitcoin_network())?,
+            )
+            .await?;
+
+            let txid = bitcoin_wallet.withdraw(address.clone(), amount).await?;
+            info!("Withdrew BTC to {}", address);
+
+            println!("{}", txid);
+        }
+        Command::Export {
+            swap_id,
+            file,
+            encrypt,
+        } => {
+            let swaps = match swap_id {
+                Some(swap_id) => vec![db.export_swap(swap_id)?],
+                None => db.export_all()?,
+            };
+            let backup_passphrase = if encrypt {
+                Some(prompt_new_passphrase()?)
+            } else if db.is_encrypted() {
+                // The secrets of an encrypted database are never written in plaintext
+                Some(passphrase.get()?)
+            } else {
+                None
+            };
+
+            let count = swaps.len();
+            Backup { env, swaps }.write_to(&file, backup_passphrase.as_deref())?;
+            info!("Exported {} swap(s) to {}", count, file.display());
+        }
+        Command::Import { file } => {
+            let backup = Backup::read_from(&file, prompt_passphrase)?;
+            backup.ensure_env(env)?;
+
+            for swap in backup.swaps {
+                let swap_id = swap.swap_id;
+
+                if db.import_swap(swap).await? {
+                    info!("Imported swap {}", swap_id);
+                } else {
+                    warn!("Skipped swap {} which is already in the database", swap_id);
+                }
+            }
+        }
+        Command::ChangePassword => {
+            let seed = Seed::from_file_or_generate(&data_dir, env, || passphrase.get())
+                .context("Could not retrieve/initialize seed")?;
 
-            table.add_row(row!["SWAP ID", "STATE"]);
+            let new_passphrase = new_passphrase()?;
 
-            for (swap_id, state) in db.all()? {
-                table.add_row(row![swap_id, state]);
+            let old_passphrase = if db.is_encrypted() {
+                Some(passphrase.get()?)
+            } else {
+                None
+            };
+
+            // The database is changed first as it can be changed back, the seed
+            // and the database must never end up with different passphrases
+            db.change_passphrase(new_passphrase.as_deref())?;
+            if let Err(error) = seed.write_to_data_dir(&data_dir, env, new_passphrase.as_deref()) {
+                db.change_passphrase(old_passphrase.as_deref())
+                    .context("Could not restore the passphrase of the database")?;
+                return Err(error).context("Could not write the seed with the new passphrase");
+            }
+
+            if db.is_encrypted() {
+                info!("Encrypted the seed and the database with the new passphrase");
+            } else {
+                info!("The seed and the database are no longer encrypted");
             }
+        }
+        Command::ExportSeed => {
+            let seed = Seed::from_file_or_generate(&data_dir, env, || passphrase.get())
+                .context("Could not retrieve/initialize seed")?;
+
+            println!("{}", seed.to_mnemonic());
+        }
+        Command::RestoreSeed {
+            rescan_from_height,
+            force,
+        } => {
+            let seed = Seed::from_file_or_generate(&data_dir, env, || passphrase.get())
+                .context("Could not retrieve/initialize seed")?;
+            let restored = Seed::from_mnemonic(&read_mnemonic()?)?;
+
+            if restored != seed {
+                if !force && (!db.all()?.is_empty() || wallet_data_dir.exists()) {
+                    bail!("Another seed is in use already, swaps and funds depending on it become inaccessible if it is replaced. Export it first and pass --force to replace it anyway")
+                }
+
+                let seed_passphrase = if db.is_encrypted() {
+                    Some(passphrase.get()?)
+                } else {
+                    None
+                };
+                restored.write_to_data_dir(&data_dir, env, seed_passphrase.as_deref())?;
+
+                // The cached wallet belongs to the keys of the replaced seed
+                if wallet_data_dir.exists() {
+                    fs::remove_dir_all(&wallet_data_dir)?;
+                }
+
+                info!("Restored the seed from the mnemonic");
+            }
+
+            let bitcoin_wallet = bitcoin::Wallet::new(
+                config.bitcoin.electrum_rpc_url,
+                config.bitcoin.electrum_http_url,
+                env.bitcoin_network(),
+                &wallet_data_dir,
+                restored.derive_extended_private_key(env.bitcoin_network())?,
+                config.bitcoin.fees,
+            )
+            .await?;
+            bitcoin_wallet
+                .rescan(BlockHeight::new(rescan_from_height))
+                .await?;
 
-            // Print the table to stdout
-            table.printstd();
+            info!(
+                "Rescanned the Bitcoin wallet, balance: {}",
+                bitcoin_wallet.balance().await?
+            );
         }
+        Command::Profiles { .. } => unreachable!("profiles are listed before opening the database"),
     };
 
     Ok(())
@@ -137,31 +424,14 @@ async fn init_wallets(
     config: Config,
     bitcoin_wallet_data_dir: &Path,
     key: impl DerivableKey<Segwitv0> + Clone,
+    jude_wallet_name: &str,
 ) -> Result<(bitcoin::Wallet, jude::Wallet)> {
-    let bitcoin_wallet = bitcoin::Wallet::new(
-        config.bitcoin.electrum_rpc_url,
-        config.bitcoin.electrum_http_url,
-        BITCOIN_NETWORK,
-        bitcoin_wallet_data_dir,
-        key,
-    )
-    .await?;
-
-    bitcoin_wallet
-        .sync_wallet()
-        .await
-        .expect("Could not sync btc wallet");
-
-    let bitcoin_balance = bitcoin_wallet.balance().await?;
-    info!(
-        "Connection to Bitcoin wallet succeeded, balance: {}",
-        bitcoin_balance
-    );
+    let bitcoin_wallet = init_bitcoin_wallet(config.clone(), bitcoin_wallet_data_dir, key).await?;
 
     let jude_wallet = jude::Wallet::new(
         config.jude.wallet_rpc_url.clone(),
-        jude_NETWORK,
-        DEFAULT_WALLET_NAME.to_string(),
+        config.env.jude_network(),
+        jude_wallet_name.to_string(),
     );
 
     // Setup the jude wallet
@@ -173,9 +443,9 @@ async fn init_wallets(
             config.jude.wallet_rpc_url
         ))?;
 
-        info!("Created jude wallet {}", DEFAULT_WALLET_NAME);
+        info!("Created jude wallet {}", jude_wallet_name);
     } else {
-        info!("Opened jude wallet {}", DEFAULT_WALLET_NAME);
+        info!("Opened jude wallet {}", jude_wallet_name);
     }
 
     let balance = jude_wallet.get_balance().await?;
@@ -191,3 +461,32 @@ async fn init_wallets(
 
     Ok((bitcoin_wallet, jude_wallet))
 }
+
+async fn init_bitcoin_wallet(
+    config: Config,
+    bitcoin_wallet_data_dir: &Path,
+    key: impl DerivableKey<Segwitv0> + Clone,
+) -> Result<bitcoin::Wallet> {
+    let bitcoin_wallet = bitcoin::Wallet::new(
+        config.bitcoin.electrum_rpc_url,
+        config.bitcoin.electrum_http_url,
+        config.env.bitcoin_network(),
+        bitcoin_wallet_data_dir,
+        key,
+        config.bitcoin.fees,
+    )
+    .await?;
+
+    bitcoin_wallet
+        .sync_wallet()
+        .await
+        .expect("Could not sync btc wallet");
+
+    let bitcoin_balance = bitcoin_wallet.balance().await?;
+    info!(
+        "Connection to Bitcoin wallet succeeded, balance: {}",
+        bitcoin_balance
+    );
+
+    Ok(bitcoin_wallet)
+}
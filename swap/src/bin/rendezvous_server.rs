@@ -0,0 +1,77 @@
+#![warn(
+    unused_extern_crates,
+    missing_copy_implementations,
+    rust_2018_idioms,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::fallible_impl_from,
+    clippy::cast_precision_loss,
+    clippy::cast_possible_wrap,
+    clippy::dbg_macro
+)]
+#![forbid(unsafe_code)]
+
+use anyhow::{Context, Result};
+use libp2p::Multiaddr;
+use std::path::PathBuf;
+use structopt::StructOpt;
+use swap::{
+    encryption::prompt_passphrase,
+    env::Env,
+    fs::default_data_dir,
+    network::rendezvous::{RendezvousPoint, Server},
+    seed::Seed,
+    trace::init_tracing,
+};
+use tracing::info;
+use tracing_subscriber::filter::LevelFilter;
+
+#[derive(structopt::StructOpt, Debug)]
+#[structopt(
+    name = "rendezvous_server",
+    about = "Keeps the registrations of sellers and hands them out to buyers."
+)]
+struct Arguments {
+    #[structopt(
+        long = "listen",
+        help = "The multiaddress to listen on.",
+        default_value = "/ip4/0.0.0.0/tcp/8888"
+    )]
+    listen: Multiaddr,
+
+    #[structopt(
+        long = "data-dir",
+        help = "The directory holding the seed the peer id is derived from.",
+        parse(from_os_str)
+    )]
+    data_dir: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    init_tracing(LevelFilter::DEBUG).expect("initialize tracing");
+
+    let args = Arguments::from_args();
+
+    let data_dir = match args.data_dir {
+        Some(data_dir) => data_dir,
+        None => default_data_dir()
+            .context("No default data dir value for this system")?
+            .join("rendezvous"),
+    };
+
+    // The registrations of all networks are kept, the seed only determines our
+    // peer id.
+    let seed = Seed::from_file_or_generate(&data_dir, Env::Mainnet, prompt_passphrase)
+        .context("Could not retrieve/initialize seed")?;
+    let server = Server::new(&seed.derive_libp2p_identity(), args.listen.clone())?;
+
+    info!("Listening as {}", RendezvousPoint {
+        peer_id: server.peer_id(),
+        address: args.listen,
+    });
+
+    server.run().await;
+
+    Ok(())
+}
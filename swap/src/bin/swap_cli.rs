@@ -12,28 +12,37 @@
 #![forbid(unsafe_code)]
 #![allow(non_snake_case)]
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use dialoguer::{theme::ColorfulTheme, Confirm};
+use futures::{stream::FuturesUnordered, StreamExt};
 use prettytable::{row, Table};
 use reqwest::Url;
-use std::{path::Path, sync::Arc, time::Duration};
+use serde::Serialize;
+use std::{fs, net::SocketAddr, path::Path, sync::Arc, time::Duration};
 use structopt::StructOpt;
 use swap::{
     bitcoin,
-    bitcoin::{Amount, TxLock},
+    bitcoin::{Amount, BlockHeight, TxLock},
     cli::{
-        command::{Arguments, Command},
+        command::{Arguments, Command, ProfilesCommand},
         config::{read_config, Config},
+        output::{BidQuoteRecord, SellerRecord, SwapRecord},
+        progress::Progress,
     },
-    database::Database,
-    execution_params,
-    execution_params::GetExecutionParams,
+    database::{Backup, Database, Peer},
+    encryption::{new_passphrase, prompt_new_passphrase, prompt_passphrase, PassphraseSource},
+    env::migrate_legacy_data_dir,
     jude,
     jude::{CreateWallet, OpenWallet},
+    network::rendezvous,
+    output::{format_duration, format_timestamp, print_balance, print_deposit_addresses},
+    profile::Profile,
     protocol::{
         bob,
-        bob::{cancel::CancelError, Builder, EventLoop},
+        bob::{cancel::CancelError, BobState, Builder, EventLoop, Quote, QuoteCheck},
+        details::Report,
     },
-    seed::Seed,
+    seed::{read_mnemonic, Seed},
 };
 use tracing::{debug, error, info, warn, Level};
 use tracing_subscriber::FmtSubscriber;
@@ -47,6 +56,10 @@ const jude_BLOCKCHAIN_MONITORING_WALLET_NAME: &str = "swap-tool-blockchain-monit
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Arguments::from_args();
+    let json = args.json;
+    let tor_socks5_proxy = args
+        .tor_socks5_port
+        .map(|port| SocketAddr::from(([127, 0, 0, 1], port)));
 
     let is_terminal = atty::is(atty::Stream::Stderr);
     let base_subscriber = |level| {
@@ -76,78 +89,105 @@ async fn main() -> Result<()> {
 
     let config = match args.config {
         Some(config_path) => read_config(config_path)??,
-        None => Config::testnet(),
+        None => Config::for_env(args.env.unwrap_or_default()),
     };
+    let env = config.env.ensure_matches(args.env)?;
+    migrate_legacy_data_dir(&config.data.dir)?;
+    let env_data_dir = config.env_data_dir();
+
+    let cmd = args.cmd.unwrap_or_default();
+    if let Command::Profiles {
+        cmd: ProfilesCommand::List,
+    } = cmd
+    {
+        let profiles = Profile::list(&env_data_dir)?;
+
+        if json {
+            print_json(
+                &profiles
+                    .iter()
+                    .map(|profile| profile.to_string())
+                    .collect::<Vec<_>>(),
+            )?;
+        } else {
+            for profile in profiles {
+                println!("{}", profile);
+            }
+        }
 
-    debug!(
-        "Database and seed will be stored in {}",
-        config.data.dir.display()
-    );
+        return Ok(());
+    }
+
+    let profile = args.profile;
+    let data_dir = profile.data_dir(&env_data_dir);
 
-    let db = Database::open(config.data.dir.join("database").as_path())
-        .context("Could not open database")?;
+    info!("Swapping on {}", env);
+    if !profile.is_default() {
+        info!("Using profile {}", profile);
+    }
+    debug!("Database and seed will be stored in {}", data_dir.display());
+
+    let mut passphrase = PassphraseSource::default();
+    let mut db =
+        Database::open_with_passphrase(data_dir.join("database").as_path(), || passphrase.get())
+            .context("Could not open database")?;
+    db.ensure_env(env)?;
 
-    let wallet_data_dir = config.data.dir.join("wallet");
-    let seed =
-        Seed::from_file_or_generate(&config.data.dir).expect("Could not retrieve/initialize seed");
+    let wallet_data_dir = data_dir.join("wallet");
+    let seed = Seed::from_file_or_generate(&data_dir, env, || passphrase.get())
+        .context("Could not retrieve/initialize seed")?;
 
-    // hardcode to testnet/stagenet
-    let bitcoin_network = bitcoin::Network::Testnet;
-    let jude_network = jude::Network::Stagenet;
-    let execution_params = execution_params::Testnet::get_execution_params();
+    let bitcoin_network = env.bitcoin_network();
+    let jude_network = env.jude_network();
+    let execution_params = env.execution_params();
 
-    let jude_wallet_rpc = jude::WalletRpc::new(config.data.dir.join("jude")).await?;
+    let jude_wallet_name = profile.wallet_name(jude_BLOCKCHAIN_MONITORING_WALLET_NAME);
+    let jude_wallet_rpc = jude::WalletRpc::new(env_data_dir.join("jude")).await?;
 
     let jude_wallet_rpc_process = jude_wallet_rpc
-        .run(jude_network, "stagenet.community.jude.to")
+        .run(jude_network, env.default_jude_daemon_host())
         .await?;
 
-    match args.cmd.unwrap_or_default() {
+    match cmd {
         Command::Buyjude {
             alice_peer_id,
             alice_addr,
+            send_btc,
+            min_jude,
+            max_rate,
         } => {
             let bitcoin_wallet =
                 init_bitcoin_wallet(config, bitcoin_network, &wallet_data_dir, seed).await?;
-            let jude_wallet =
-                init_jude_wallet(jude_network, jude_wallet_rpc_process.endpoint()).await?;
+            let jude_wallet = init_jude_wallet(
+                jude_network,
+                jude_wallet_rpc_process.endpoint(),
+                &jude_wallet_name,
+            )
+            .await?;
             let bitcoin_wallet = Arc::new(bitcoin_wallet);
 
             let swap_id = Uuid::new_v4();
 
-            // TODO: Also wait for more funds if balance < dust
-            if bitcoin_wallet.balance().await? == Amount::ZERO {
-                info!(
-                    "Please deposit BTC to {}",
-                    bitcoin_wallet.new_address().await?
-                );
-
-                while bitcoin_wallet.balance().await? == Amount::ZERO {
-                    bitcoin_wallet.sync_wallet().await?;
+            let send_bitcoin = determine_btc_to_swap(&bitcoin_wallet, send_btc).await?;
 
-                    tokio::time::sleep(Duration::from_secs(1)).await;
-                }
-
-                debug!("Received {}", bitcoin_wallet.balance().await?);
-            } else {
-                info!(
-                    "Still got {} left in wallet, swapping ...",
-                    bitcoin_wallet.balance().await?
-                );
-            }
+            db.insert_peer(swap_id, Peer {
+                peer_id: alice_peer_id,
+                address: alice_addr.clone(),
+            })
+            .await?;
 
-            let send_bitcoin = bitcoin_wallet.max_giveable(TxLock::script_size()).await?;
-
-            let (event_loop, event_loop_handle) = EventLoop::new(
+            let (event_loop, swap_registry) = EventLoop::new(
                 &seed.derive_libp2p_identity(),
-                alice_peer_id,
-                alice_addr,
+                tor_socks5_proxy,
                 bitcoin_wallet.clone(),
             )?;
             let handle = tokio::spawn(event_loop.run());
+            let event_loop_handle = swap_registry
+                .register(swap_id, alice_peer_id, alice_addr)
+                .await?;
 
             let swap = Builder::new(
-                db,
+                db.clone(),
                 swap_id,
                 bitcoin_wallet.clone(),
                 Arc::new(jude_wallet),
@@ -155,6 +195,7 @@ async fn main() -> Result<()> {
                 event_loop_handle,
             )
             .with_init_params(send_bitcoin)
+            .with_quote_check(quote_check(min_jude, max_rate))
             .build()?;
 
             let swap = bob::run(swap);
@@ -164,16 +205,205 @@ async fn main() -> Result<()> {
                 },
                 swap_result = swap => {
                     swap_result?;
+
+                    if json {
+                        print_swap_record(&db, swap_id)?;
+                    }
                 }
             }
         }
+        Command::BuyjudeMany {
+            swaps,
+            resume,
+            max_rate,
+        } => {
+            if swaps.is_empty() && resume.is_empty() {
+                bail!("Nothing to do, please pass at least one --swap or --resume")
+            }
+
+            let bitcoin_wallet =
+                init_bitcoin_wallet(config, bitcoin_network, &wallet_data_dir, seed).await?;
+            let jude_wallet = init_jude_wallet(
+                jude_network,
+                jude_wallet_rpc_process.endpoint(),
+                &jude_wallet_name,
+            )
+            .await?;
+            let bitcoin_wallet = Arc::new(bitcoin_wallet);
+            let jude_wallet = Arc::new(jude_wallet);
+
+            let send_bitcoin = swaps
+                .iter()
+                .fold(Amount::from_sat(0), |total, swap| total + swap.send_btc);
+            let max_giveable = max_giveable_btc(&bitcoin_wallet).await?;
+            if send_bitcoin > max_giveable {
+                bail!(
+                    "The swaps need {} in total but at most {} can be spent",
+                    send_bitcoin,
+                    max_giveable
+                )
+            }
+
+            let (event_loop, swap_registry) = EventLoop::new(
+                &seed.derive_libp2p_identity(),
+                tor_socks5_proxy,
+                bitcoin_wallet.clone(),
+            )?;
+            let event_loop = tokio::spawn(event_loop.run());
+
+            let mut running = Vec::new();
+            for params in swaps {
+                let swap_id = Uuid::new_v4();
+                let peer = Peer {
+                    peer_id: params.alice_peer_id,
+                    address: params.alice_addr,
+                };
+                db.insert_peer(swap_id, peer.clone()).await?;
+
+                running.push((swap_id, peer, Some(params.send_btc)));
+            }
+            for swap_id in resume {
+                let peer = db.get_peer(swap_id)?.with_context(|| {
+                    format!(
+                        "No counterparty recorded for swap {}, please resume it on its own",
+                        swap_id
+                    )
+                })?;
+
+                running.push((swap_id, peer, None));
+            }
+
+            let mut progress = Progress::new(
+                running
+                    .iter()
+                    .map(|(swap_id, peer, _)| (*swap_id, peer.peer_id)),
+            );
+
+            let mut swaps = FuturesUnordered::new();
+            for (swap_id, peer, send_bitcoin) in running {
+                info!(
+                    "Starting swap {} with {} at {}",
+                    swap_id, peer.peer_id, peer.address
+                );
+
+                let event_loop_handle = swap_registry
+                    .register(swap_id, peer.peer_id, peer.address)
+                    .await?;
+                let builder = Builder::new(
+                    db.clone(),
+                    swap_id,
+                    bitcoin_wallet.clone(),
+                    jude_wallet.clone(),
+                    execution_params,
+                    event_loop_handle,
+                )
+                .with_quote_check(quote_check(None, Some(max_rate)));
+                let swap = match send_bitcoin {
+                    Some(send_bitcoin) => builder.with_init_params(send_bitcoin),
+                    None => builder,
+                }
+                .build()?;
+
+                swaps.push(async move { (swap_id, bob::run(swap).await) });
+            }
+
+            let mut refresh = tokio::time::interval(Duration::from_secs(1));
+            let mut failed = 0;
+            while !swaps.is_empty() {
+                tokio::select! {
+                    event_loop_result = &mut event_loop => {
+                        event_loop_result??;
+                    },
+                    Some((swap_id, swap_result)) = swaps.next() => {
+                        if let Err(error) = swap_result {
+                            error!("Swap {} failed: {:#}", swap_id, error);
+                            progress.failed(swap_id, &error);
+                            failed += 1;
+                        }
+                    },
+                    _ = refresh.tick() => {}
+                }
+
+                if progress.refresh(&db)? && !json {
+                    progress.table().print(&mut std::io::stderr())?;
+                }
+            }
+
+            if json {
+                let records = progress
+                    .started()
+                    .map(|swap_id| {
+                        swap_record(&db, swap_id, db.get_state(swap_id)?.try_into_bob()?.into())
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                print_json(&records)?;
+            } else {
+                progress.table().print(&mut std::io::stderr())?;
+            }
+
+            if failed > 0 {
+                bail!("{} of the swaps failed", failed)
+            }
+        }
+        Command::Quote {
+            alice_peer_id,
+            alice_addr,
+        } => {
+            let quote = bob::request_bid_quote(
+                &seed.derive_libp2p_identity(),
+                tor_socks5_proxy,
+                alice_peer_id,
+                alice_addr,
+            )
+            .await?;
+
+            if json {
+                print_json(&BidQuoteRecord::from(quote))?;
+            } else {
+                println!(
+                    "{} per jude for swaps of at least {}, selling at most {}",
+                    quote.price, quote.min_buy, quote.max_sell
+                );
+            }
+        }
+        Command::History if json => {
+            let records = db
+                .all_by_start_time()?
+                .into_iter()
+                .map(|(swap_id, state, _)| swap_record(&db, swap_id, state.try_into_bob()?.into()))
+                .collect::<Result<Vec<_>>>()?;
+
+            print_json(&records)?;
+        }
         Command::History => {
             let mut table = Table::new();
 
-            table.add_row(row!["SWAP ID", "STATE"]);
-
-            for (swap_id, state) in db.all()? {
-                table.add_row(row![swap_id, state]);
+            table.add_row(row![
+                "SWAP ID",
+                "STARTED (UTC)",
+                "DURATION",
+                "STATE",
+                "PEER ID",
+                "ADDRESS"
+            ]);
+
+            for (swap_id, state, history) in db.all_by_start_time()? {
+                let started_at = history
+                    .started_at()
+                    .map_or_else(|| "unknown".to_string(), format_timestamp);
+                let duration = history
+                    .duration()
+                    .map_or_else(|| "unknown".to_string(), format_duration);
+
+                match db.get_peer(swap_id)? {
+                    Some(Peer { peer_id, address }) => {
+                        table.add_row(row![swap_id, started_at, duration, state, peer_id, address])
+                    }
+                    None => table.add_row(row![
+                        swap_id, started_at, duration, state, "unknown", "unknown"
+                    ]),
+                };
             }
 
             // Print the table to stdout
@@ -186,26 +416,57 @@ async fn main() -> Result<()> {
         } => {
             let bitcoin_wallet =
                 init_bitcoin_wallet(config, bitcoin_network, &wallet_data_dir, seed).await?;
-            let jude_wallet =
-                init_jude_wallet(jude_network, jude_wallet_rpc_process.endpoint()).await?;
+            let jude_wallet = init_jude_wallet(
+                jude_network,
+                jude_wallet_rpc_process.endpoint(),
+                &jude_wallet_name,
+            )
+            .await?;
             let bitcoin_wallet = Arc::new(bitcoin_wallet);
 
-            let (event_loop, event_loop_handle) = EventLoop::new(
+            let stored_peer = db.get_peer(swap_id)?;
+            let peer = match (stored_peer, alice_peer_id, alice_addr) {
+                (Some(peer), None, None) => peer,
+                (stored_peer, alice_peer_id, alice_addr) => {
+                    let peer_id = alice_peer_id
+                        .or_else(|| stored_peer.as_ref().map(|peer| peer.peer_id))
+                        .context(
+                            "No counterparty recorded for this swap, please provide its peer id",
+                        )?;
+                    let address = alice_addr
+                        .or_else(|| stored_peer.map(|peer| peer.address))
+                        .context(
+                            "No counterparty recorded for this swap, please provide its address",
+                        )?;
+
+                    let peer = Peer { peer_id, address };
+                    db.insert_peer(swap_id, peer.clone()).await?;
+
+                    peer
+                }
+            };
+
+            info!("Resuming swap with {} at {}", peer.peer_id, peer.address);
+
+            let (event_loop, swap_registry) = EventLoop::new(
                 &seed.derive_libp2p_identity(),
-                alice_peer_id,
-                alice_addr,
+                tor_socks5_proxy,
                 bitcoin_wallet.clone(),
             )?;
             let handle = tokio::spawn(event_loop.run());
+            let event_loop_handle = swap_registry
+                .register(swap_id, peer.peer_id, peer.address)
+                .await?;
 
             let swap = Builder::new(
-                db,
+                db.clone(),
                 swap_id,
                 bitcoin_wallet.clone(),
                 Arc::new(jude_wallet),
                 execution_params,
                 event_loop_handle,
             )
+            .with_quote_check(quote_check(None, None))
             .build()?;
 
             let swap = bob::run(swap);
@@ -215,6 +476,10 @@ async fn main() -> Result<()> {
                 },
                 swap_result = swap => {
                     swap_result?;
+
+                    if json {
+                        print_swap_record(&db, swap_id)?;
+                    }
                 }
             }
         }
@@ -223,8 +488,14 @@ async fn main() -> Result<()> {
                 init_bitcoin_wallet(config, bitcoin_network, &wallet_data_dir, seed).await?;
 
             let resume_state = db.get_state(swap_id)?.try_into_bob()?.into();
-            let cancel =
-                bob::cancel(swap_id, resume_state, Arc::new(bitcoin_wallet), db, force).await?;
+            let cancel = bob::cancel(
+                swap_id,
+                resume_state,
+                Arc::new(bitcoin_wallet),
+                db.clone(),
+                force,
+            )
+            .await?;
 
             match cancel {
                 Ok((txid, _)) => {
@@ -238,6 +509,10 @@ async fn main() -> Result<()> {
                     warn!("The Cancel Transaction has already been published.")
                 }
             }
+
+            if json {
+                print_swap_record(&db, swap_id)?;
+            }
         }
         Command::Refund { swap_id, force } => {
             let bitcoin_wallet =
@@ -250,15 +525,348 @@ async fn main() -> Result<()> {
                 resume_state,
                 execution_params,
                 Arc::new(bitcoin_wallet),
-                db,
+                db.clone(),
                 force,
             )
             .await??;
+
+            if json {
+                print_swap_record(&db, swap_id)?;
+            }
+        }
+        Command::SwapDetails { swap_id } => {
+            let bitcoin_wallet =
+                init_bitcoin_wallet(config, bitcoin_network, &wallet_data_dir, seed).await?;
+
+            let state = db.get_state(swap_id)?.try_into_bob()?.into();
+            let report = Report::bob(swap_id, &state, &bitcoin_wallet).await?;
+
+            if json {
+                print_json(&report)?;
+            } else {
+                println!("{}", report);
+            }
         }
+        Command::Balance => {
+            let bitcoin_wallet =
+                init_bitcoin_wallet(config, bitcoin_network, &wallet_data_dir, seed).await?;
+            let jude_wallet = init_jude_wallet(
+                jude_network,
+                jude_wallet_rpc_process.endpoint(),
+                &jude_wallet_name,
+            )
+            .await?;
+
+            print_balance(
+                bitcoin_wallet.detailed_balance().await?,
+                jude_wallet.get_detailed_balance().await?,
+                json,
+            );
+        }
+        Command::DepositAddress => {
+            let bitcoin_wallet =
+                init_bitcoin_wallet(config, bitcoin_network, &wallet_data_dir, seed).await?;
+            let jude_wallet = init_jude_wallet(
+                jude_network,
+                jude_wallet_rpc_process.endpoint(),
+                &jude_wallet_name,
+            )
+            .await?;
+
+            print_deposit_addresses(
+                bitcoin_wallet.new_address().await?,
+                jude_wallet.get_main_address().await?,
+                json,
+            );
+        }
+        Command::WithdrawBtc { address, amount } => {
+            let bitcoin_wallet =
+                init_bitcoin_wallet(config, bitcoin_network, &wallet_data_dir, seed).await?;
+
+            let txid = bitcoin_wallet.withdraw(address.clone(), amount).await?;
+            info!("Withdrew BTC to {}", address);
+
+            println!("{}", txid);
+        }
+        Command::Export {
+            swap_id,
+            file,
+            encrypt,
+        } => {
+            let swaps = match swap_id {
+                Some(swap_id) => vec![db.export_swap(swap_id)?],
+                None => db.export_all()?,
+            };
+            let backup_passphrase = if encrypt {
+                Some(prompt_new_passphrase()?)
+            } else if db.is_encrypted() {
+                // The secrets of an encrypted database are never written in plaintext
+                Some(passphrase.get()?)
+            } else {
+                None
+            };
+
+            let count = swaps.len();
+            Backup { env, swaps }.write_to(&file, backup_passphrase.as_deref())?;
+            info!("Exported {} swap(s) to {}", count, file.display());
+        }
+        Command::ListSellers { rendezvous_point } => {
+            let sellers = rendezvous::list_sellers(
+                &seed.derive_libp2p_identity(),
+                tor_socks5_proxy,
+                &rendezvous_point,
+                env,
+            )
+            .await?;
+
+            if json {
+                print_json(
+                    &sellers
+                        .into_iter()
+                        .map(SellerRecord::from)
+                        .collect::<Vec<_>>(),
+                )?;
+            } else {
+                let mut table = Table::new();
+
+                table.add_row(row!["PEER ID", "PRICE", "MIN BUY", "MAX SELL", "ADDRESSES"]);
+
+                for seller in sellers {
+                    let addresses = seller
+                        .addresses
+                        .iter()
+                        .map(|address| address.to_string())
+                        .collect::<Vec<_>>()
+                        .join("\n");
+
+                    table.add_row(row![
+                        seller.peer_id,
+                        seller.quote.price,
+                        seller.quote.min_buy,
+                        seller.quote.max_sell,
+                        addresses
+                    ]);
+                }
+
+                // Print the table to stdout
+                table.printstd();
+            }
+        }
+        Command::Import { file } => {
+            let backup = Backup::read_from(&file, prompt_passphrase)?;
+            backup.ensure_env(env)?;
+
+            for swap in backup.swaps {
+                let swap_id = swap.swap_id;
+
+                if db.import_swap(swap).await? {
+                    info!("Imported swap {}", swap_id);
+                } else {
+                    warn!("Skipped swap {} which is already in the database", swap_id);
+                }
+            }
+        }
+        Command::ChangePassword => {
+            let new_passphrase = new_passphrase()?;
+
+            let old_passphrase = if db.is_encrypted() {
+                Some(passphrase.get()?)
+            } else {
+                None
+            };
+
+            // The database is changed first as it can be changed back, the seed
+            // and the database must never end up with different passphrases
+            db.change_passphrase(new_passphrase.as_deref())?;
+            if let Err(error) = seed.write_to_data_dir(&data_dir, env, new_passphrase.as_deref()) {
+                db.change_passphrase(old_passphrase.as_deref())
+                    .context("Could not restore the passphrase of the database")?;
+                return Err(error).context("Could not write the seed with the new passphrase");
+            }
+
+            if db.is_encrypted() {
+                info!("Encrypted the seed and the database with the new passphrase");
+            } else {
+                info!("The seed and the database are no longer encrypted");
+            }
+        }
+        Command::ExportSeed => {
+            let mnemonic = seed.to_mnemonic().to_string();
+
+            if json {
+                print_json(&serde_json::json!({ "mnemonic": mnemonic }))?;
+            } else {
+                println!("{}", mnemonic);
+            }
+        }
+        Command::RestoreSeed {
+            rescan_from_height,
+            force,
+        } => {
+            let restored = Seed::from_mnemonic(&read_mnemonic()?)?;
+
+            if restored != seed {
+                if !force && (!db.all()?.is_empty() || wallet_data_dir.exists()) {
+                    bail!("Another seed is in use already, swaps and funds depending on it become inaccessible if it is replaced. Export it first and pass --force to replace it anyway")
+                }
+
+                let seed_passphrase = if db.is_encrypted() {
+                    Some(passphrase.get()?)
+                } else {
+                    None
+                };
+                restored.write_to_data_dir(&data_dir, env, seed_passphrase.as_deref())?;
+
+                // The cached wallet belongs to the keys of the replaced seed
+                if wallet_data_dir.exists() {
+                    fs::remove_dir_all(&wallet_data_dir)?;
+                }
+
+                info!("Restored the seed from the mnemonic");
+            }
+
+            let bitcoin_wallet = bitcoin::Wallet::new(
+                config.bitcoin.electrum_rpc_url,
+                config.bitcoin.electrum_http_url,
+                bitcoin_network,
+                &wallet_data_dir,
+                restored.derive_extended_private_key(bitcoin_network)?,
+                config.bitcoin.fees,
+            )
+            .await?;
+            bitcoin_wallet
+                .rescan(BlockHeight::new(rescan_from_height))
+                .await?;
+
+            info!(
+                "Rescanned the Bitcoin wallet, balance: {}",
+                bitcoin_wallet.balance().await?
+            );
+        }
+        Command::Profiles { .. } => unreachable!("profiles are listed before opening the database"),
     };
     Ok(())
 }
 
+/// Returns the amount of BTC to swap, waiting for a deposit if the wallet
+/// cannot cover it yet.
+///
+/// Without an explicit amount the whole balance is swapped.
+async fn determine_btc_to_swap(
+    bitcoin_wallet: &bitcoin::Wallet,
+    send_btc: Option<Amount>,
+) -> Result<Amount> {
+    if send_btc == Some(Amount::ZERO) {
+        bail!("Cannot swap an amount of 0 BTC")
+    }
+
+    let is_sufficient = |max_giveable: Amount| match send_btc {
+        Some(send_btc) => max_giveable >= send_btc,
+        None => max_giveable > Amount::ZERO,
+    };
+
+    let mut max_giveable = max_giveable_btc(bitcoin_wallet).await?;
+
+    if !is_sufficient(max_giveable) {
+        info!(
+            "Please deposit BTC to {}",
+            bitcoin_wallet.new_address().await?
+        );
+        if let Some(send_btc) = send_btc {
+            info!(
+                "At least {} more are needed to swap {}",
+                send_btc - max_giveable,
+                send_btc
+            );
+        }
+
+        while !is_sufficient(max_giveable) {
+            bitcoin_wallet.sync_wallet().await?;
+            max_giveable = max_giveable_btc(bitcoin_wallet).await?;
+
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+
+        debug!("Received {}", bitcoin_wallet.balance().await?);
+    }
+
+    let send_btc = send_btc.unwrap_or(max_giveable);
+    info!(
+        "Swapping {} of the {} available in the wallet",
+        send_btc, max_giveable
+    );
+
+    Ok(send_btc)
+}
+
+/// The most BTC we can lock, zero until the deposit covers the fees of the
+/// lock transaction.
+async fn max_giveable_btc(bitcoin_wallet: &bitcoin::Wallet) -> Result<Amount> {
+    bitcoin_wallet.max_giveable(TxLock::script_size()).await
+}
+
+/// Checks the quote against the given bounds or, if there are none, asks the
+/// user to confirm it.
+fn quote_check(min_jude: Option<jude::Amount>, max_rate: Option<bitcoin::Amount>) -> QuoteCheck {
+    if min_jude.is_some() || max_rate.is_some() {
+        return QuoteCheck {
+            min_jude,
+            max_rate,
+            confirm: None,
+        };
+    }
+
+    QuoteCheck {
+        min_jude: None,
+        max_rate: None,
+        confirm: Some(Arc::new(confirm_quote)),
+    }
+}
+
+fn confirm_quote(quote: Quote) -> Result<bool> {
+    if !atty::is(atty::Stream::Stdin) {
+        bail!("Cannot confirm the quote without a terminal, please pass --min-jude or --max-rate")
+    }
+
+    let rate = quote
+        .rate()
+        .map_or_else(|| "unknown".to_string(), |rate| rate.to_string());
+    let prompt = format!(
+        "Alice offers {} for {} (a rate of {} per jude), do you want to swap?",
+        quote.jude_amount, quote.btc_amount, rate
+    );
+
+    // Blocks until the user answered, the event loop keeps running on the
+    // other worker threads in the meantime.
+    let confirmed = tokio::task::block_in_place(|| {
+        Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(prompt)
+            .default(false)
+            .interact()
+    })?;
+
+    Ok(confirmed)
+}
+
+fn swap_record(db: &Database, swap_id: Uuid, state: BobState) -> Result<SwapRecord> {
+    Ok(SwapRecord::new(swap_id, &state, db.get_peer(swap_id)?)
+        .with_history(db.get_history(swap_id)?))
+}
+
+fn print_swap_record(db: &Database, swap_id: Uuid) -> Result<()> {
+    let state = db.get_state(swap_id)?.try_into_bob()?.into();
+
+    print_json(&swap_record(db, swap_id, state)?)
+}
+
+fn print_json<T>(value: &T) -> Result<()>
+where
+    T: Serialize,
+{
+    println!("{}", serde_json::to_string(value)?);
+    Ok(())
+}
+
 async fn init_bitcoin_wallet(
     config: Config,
     bitcoin_network: bitcoin::Network,
@@ -271,6 +879,7 @@ async fn init_bitcoin_wallet(
         bitcoin_network,
         bitcoin_wallet_data_dir,
         seed.derive_extended_private_key(bitcoin_network)?,
+        config.bitcoin.fees,
     )
     .await?;
 
@@ -285,11 +894,12 @@ async fn init_bitcoin_wallet(
 async fn init_jude_wallet(
     jude_network: jude::Network,
     jude_wallet_rpc_url: Url,
+    wallet_name: &str,
 ) -> Result<jude::Wallet> {
     let jude_wallet = jude::Wallet::new(
         jude_wallet_rpc_url.clone(),
         jude_network,
-        jude_BLOCKCHAIN_MONITORING_WALLET_NAME.to_string(),
+        wallet_name.to_string(),
     );
 
     // Setup the temporary jude wallet necessary for monitoring the blockchain
@@ -303,7 +913,7 @@ async fn init_jude_wallet(
 
         debug!(
             "Created jude wallet for blockchain monitoring with name {}",
-            jude_BLOCKCHAIN_MONITORING_WALLET_NAME
+            wallet_name
         );
     }
 
@@ -1,6 +1,7 @@
 pub mod wallet;
 
 mod cancel;
+mod fees;
 mod lock;
 mod punish;
 mod redeem;
@@ -9,6 +10,7 @@ mod timelocks;
 
 pub use crate::bitcoin::{
     cancel::{CancelTimelock, PunishTimelock, TxCancel},
+    fees::{FeeConfig, TxFees},
     lock::TxLock,
     punish::TxPunish,
     redeem::TxRedeem,
@@ -17,7 +19,7 @@ pub use crate::bitcoin::{
 };
 pub use ::bitcoin::{util::amount::Amount, Address, Network, Transaction, Txid};
 pub use ecdsa_fun::{adaptor::EncryptedSignature, fun::Scalar, Signature};
-pub use wallet::Wallet;
+pub use wallet::{AddressNetworkMismatch, Balance, Wallet};
 
 use ::bitcoin::{
     hashes::{hex::ToHex, Hash},
@@ -36,15 +38,6 @@ use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 use std::str::FromStr;
 
-// TODO: Configurable tx-fee (note: parties have to agree prior to swapping)
-// Current reasoning:
-// tx with largest weight (as determined by get_weight() upon broadcast in e2e
-// test) = 609 assuming segwit and 60 sat/vB:
-// (609 / 4) * 60 (sat/vB) = 9135 sats
-// Recommended: Overpay a bit to ensure we don't have to wait too long for test
-// runs.
-pub const TX_FEE: u64 = 15_000;
-
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct SecretKey {
     inner: Scalar,
@@ -228,6 +221,23 @@ pub async fn current_epoch(
 ) -> Result<ExpiredTimelocks> {
     let current_block_height = bitcoin_wallet.get_block_height().await?;
     let lock_tx_height = bitcoin_wallet.transaction_block_height(lock_tx_id).await?;
+
+    Ok(epoch(
+        current_block_height,
+        lock_tx_height,
+        cancel_timelock,
+        punish_timelock,
+    ))
+}
+
+/// The timelocks that expired at `current_block_height` given the height at
+/// which the lock transaction was mined.
+pub fn epoch(
+    current_block_height: BlockHeight,
+    lock_tx_height: BlockHeight,
+    cancel_timelock: CancelTimelock,
+    punish_timelock: PunishTimelock,
+) -> ExpiredTimelocks {
     let cancel_timelock_height = lock_tx_height + cancel_timelock;
     let punish_timelock_height = cancel_timelock_height + punish_timelock;
 
@@ -235,9 +245,9 @@ pub async fn current_epoch(
         current_block_height < cancel_timelock_height,
         current_block_height < punish_timelock_height,
     ) {
-        (true, _) => Ok(ExpiredTimelocks::None),
-        (false, true) => Ok(ExpiredTimelocks::Cancel),
-        (false, false) => Ok(ExpiredTimelocks::Punish),
+        (true, _) => ExpiredTimelocks::None,
+        (false, true) => ExpiredTimelocks::Cancel,
+        (false, false) => ExpiredTimelocks::Punish,
     }
 }
 
@@ -0,0 +1,202 @@
+use crate::bitcoin::Amount;
+use bdk::FeeRate;
+use serde::{Deserialize, Serialize};
+
+/// Weight of the cancel transaction, a 2-of-2 spend of the lock output paying
+/// to another 2-of-2 output.
+const TX_CANCEL_WEIGHT: u16 = 596;
+
+/// Weight of the redeem, refund and punish transactions, 2-of-2 spends
+/// paying to a single P2WPKH output.
+const TX_SPEND_WEIGHT: u16 = 548;
+
+/// Fee of the transactions of swaps that were set up before fees were
+/// negotiated.
+const LEGACY_TX_FEE: u64 = 15_000;
+
+/// How the wallet picks the fee rate for the transactions it creates.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeeConfig {
+    /// The number of blocks within which transactions should confirm.
+    pub target_block: usize,
+    pub min_sat_per_vb: f32,
+    pub max_sat_per_vb: f32,
+    /// Used if the Electrum server cannot estimate a fee rate.
+    pub fallback_sat_per_vb: f32,
+}
+
+impl FeeConfig {
+    /// Always use the given fee rate, regardless of what the Electrum server
+    /// estimates.
+    pub fn fixed(sat_per_vb: f32) -> Self {
+        Self {
+            target_block: 1,
+            min_sat_per_vb: sat_per_vb,
+            max_sat_per_vb: sat_per_vb,
+            fallback_sat_per_vb: sat_per_vb,
+        }
+    }
+
+    /// Bounds the estimated fee rate by the configured min and max, falling
+    /// back to the configured fallback if there is no usable estimate.
+    pub fn select(&self, estimate: Option<FeeRate>) -> FeeRate {
+        let sat_per_vb = match estimate.map(|rate| rate.as_sat_vb()) {
+            Some(sat_per_vb) if sat_per_vb.is_finite() && sat_per_vb > 0.0 => sat_per_vb,
+            _ => self.fallback_sat_per_vb,
+        };
+
+        FeeRate::from_sat_per_vb(sat_per_vb.max(self.min_sat_per_vb).min(self.max_sat_per_vb))
+    }
+
+    pub fn max_fee_rate(&self) -> FeeRate {
+        FeeRate::from_sat_per_vb(self.max_sat_per_vb)
+    }
+}
+
+impl Default for FeeConfig {
+    fn default() -> Self {
+        Self {
+            target_block: 6,
+            min_sat_per_vb: 1.0,
+            max_sat_per_vb: 100.0,
+            fallback_sat_per_vb: 5.0,
+        }
+    }
+}
+
+/// The fees of the transactions spending the lock and the cancel output.
+///
+/// These transactions are signed by both parties during execution setup, hence
+/// Alice and Bob have to agree on the fees before any funds are locked.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TxFees {
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    pub redeem: Amount,
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    pub cancel: Amount,
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    pub refund: Amount,
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    pub punish: Amount,
+}
+
+impl TxFees {
+    pub fn from_fee_rate(fee_rate: FeeRate) -> Self {
+        let cancel = fee_for_weight(fee_rate, TX_CANCEL_WEIGHT);
+        let spend = fee_for_weight(fee_rate, TX_SPEND_WEIGHT);
+
+        Self {
+            redeem: spend,
+            cancel,
+            refund: spend,
+            punish: spend,
+        }
+    }
+
+    pub fn from_sat_per_vb(sat_per_vb: f32) -> Self {
+        Self::from_fee_rate(FeeRate::from_sat_per_vb(sat_per_vb))
+    }
+
+    /// Picks the higher fee of both proposals for each transaction, so that
+    /// neither party ends up with transactions that do not confirm in time.
+    pub fn agree(self, other: TxFees) -> Self {
+        Self {
+            redeem: self.redeem.max(other.redeem),
+            cancel: self.cancel.max(other.cancel),
+            refund: self.refund.max(other.refund),
+            punish: self.punish.max(other.punish),
+        }
+    }
+
+    /// Whether any of the fees is higher than the corresponding fee in
+    /// `limit`.
+    pub fn exceeds(&self, limit: &TxFees) -> bool {
+        self.redeem > limit.redeem
+            || self.cancel > limit.cancel
+            || self.refund > limit.refund
+            || self.punish > limit.punish
+    }
+}
+
+impl Default for TxFees {
+    fn default() -> Self {
+        let fee = Amount::from_sat(LEGACY_TX_FEE);
+
+        Self {
+            redeem: fee,
+            cancel: fee,
+            refund: fee,
+            punish: fee,
+        }
+    }
+}
+
+fn fee_for_weight(fee_rate: FeeRate, weight: u16) -> Amount {
+    let vsize = f32::from(weight) / 4.0;
+
+    // Fees are way below the range in which f32 loses precision and the fee
+    // rate is never negative.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let fee = (fee_rate.as_sat_vb() * vsize).ceil() as u64;
+
+    Amount::from_sat(fee)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn given_no_estimate_uses_fallback() {
+        let config = FeeConfig::default();
+
+        let fee_rate = config.select(None);
+
+        assert_eq!(fee_rate, FeeRate::from_sat_per_vb(5.0));
+    }
+
+    #[test]
+    fn given_unusable_estimate_uses_fallback() {
+        let config = FeeConfig::default();
+
+        // Electrum returns -1 if it cannot estimate the fee rate
+        let fee_rate = config.select(Some(FeeRate::from_btc_per_kvb(-1.0)));
+
+        assert_eq!(fee_rate, FeeRate::from_sat_per_vb(5.0));
+    }
+
+    #[test]
+    fn estimate_is_capped_by_min_and_max() {
+        let config = FeeConfig::default();
+
+        let too_low = config.select(Some(FeeRate::from_sat_per_vb(0.5)));
+        let too_high = config.select(Some(FeeRate::from_sat_per_vb(500.0)));
+        let in_range = config.select(Some(FeeRate::from_sat_per_vb(20.0)));
+
+        assert_eq!(too_low, FeeRate::from_sat_per_vb(1.0));
+        assert_eq!(too_high, FeeRate::from_sat_per_vb(100.0));
+        assert_eq!(in_range, FeeRate::from_sat_per_vb(20.0));
+    }
+
+    #[test]
+    fn fees_scale_with_weight() {
+        let fees = TxFees::from_sat_per_vb(10.0);
+
+        assert_eq!(fees.cancel, Amount::from_sat(1490));
+        assert_eq!(fees.redeem, Amount::from_sat(1370));
+        assert_eq!(fees.refund, fees.redeem);
+        assert_eq!(fees.punish, fees.redeem);
+    }
+
+    #[test]
+    fn agreed_fees_are_the_higher_of_both() {
+        let low = TxFees::from_sat_per_vb(2.0);
+        let high = TxFees::from_sat_per_vb(20.0);
+
+        assert_eq!(low.agree(high), high);
+        assert_eq!(high.agree(low), high);
+        assert!(high.exceeds(&low));
+        assert!(!low.exceeds(&high));
+    }
+}
@@ -1,5 +1,5 @@
 use crate::bitcoin::{
-    build_shared_output_descriptor, Address, Amount, PublicKey, Transaction, Wallet, TX_FEE,
+    build_shared_output_descriptor, Address, Amount, PublicKey, Transaction, Wallet,
 };
 use ::bitcoin::{util::psbt::PartiallySignedTransaction, OutPoint, TxIn, TxOut, Txid};
 use anyhow::Result;
@@ -70,6 +70,7 @@ impl TxLock {
         &self,
         spend_address: &Address,
         sequence: Option<u32>,
+        spending_fee: Amount,
     ) -> Transaction {
         let previous_output = self.as_outpoint();
 
@@ -81,7 +82,7 @@ impl TxLock {
         };
 
         let tx_out = TxOut {
-            value: self.inner.clone().extract_tx().output[self.lock_output_vout()].value - TX_FEE,
+            value: self.lock_amount().as_sat() - spending_fee.as_sat(),
             script_pubkey: spend_address.script_pubkey(),
         };
 
@@ -94,6 +95,35 @@ impl TxLock {
     }
 }
 
+#[cfg(test)]
+impl TxLock {
+    /// Builds the lock transaction without a wallet, spending a made up input.
+    pub fn new_for_tests(amount: Amount, A: PublicKey, B: PublicKey) -> Self {
+        let output_descriptor = build_shared_output_descriptor(A.0, B.0);
+
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Default::default(),
+                sequence: 0xFFFF_FFFF,
+                witness: Vec::new(),
+            }],
+            output: vec![TxOut {
+                value: amount.as_sat(),
+                script_pubkey: output_descriptor.script_pubkey(),
+            }],
+        };
+
+        Self {
+            inner: PartiallySignedTransaction::from_unsigned_tx(tx)
+                .expect("transaction is unsigned"),
+            output_descriptor,
+        }
+    }
+}
+
 impl From<TxLock> for PartiallySignedTransaction {
     fn from(from: TxLock) -> Self {
         from.inner
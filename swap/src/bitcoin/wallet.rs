@@ -1,12 +1,13 @@
 use crate::{
-    bitcoin::{timelocks::BlockHeight, Address, Amount, Transaction},
+    bitcoin::{timelocks::BlockHeight, Address, Amount, FeeConfig, Transaction, TxFees},
     execution_params::ExecutionParams,
 };
-use ::bitcoin::{util::psbt::PartiallySignedTransaction, Txid};
+use ::bitcoin::{util::psbt::PartiallySignedTransaction, OutPoint, Txid};
 use anyhow::{anyhow, bail, Context, Result};
 use backoff::{backoff::Constant as ConstantBackoff, future::retry};
 use bdk::{
     blockchain::{noop_progress, Blockchain, ElectrumBlockchain},
+    database::{BatchDatabase, BatchOperations, Database},
     descriptor::Segwitv0,
     electrum_client::{self, Client, ElectrumApi},
     keys::DerivableKey,
@@ -15,7 +16,7 @@ use bdk::{
 use bitcoin::Script;
 use reqwest::{Method, Url};
 use serde::{Deserialize, Serialize};
-use std::{path::Path, sync::Arc, time::Duration};
+use std::{collections::HashSet, path::Path, sync::Arc, time::Duration};
 use tokio::{sync::Mutex, time::interval};
 
 const SLED_TREE_NAME: &str = "default_tree";
@@ -34,10 +35,38 @@ enum Error {
     ElectrumClient(electrum_client::Error),
 }
 
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("The address {address} is not valid on {network}")]
+pub struct AddressNetworkMismatch {
+    pub address: Address,
+    pub network: bitcoin::Network,
+}
+
+/// Regtest shares its base58 address prefixes with testnet, hence testnet
+/// addresses are also valid on regtest.
+fn is_address_on_network(address: &Address, network: bitcoin::Network) -> bool {
+    match (address.network, network) {
+        (bitcoin::Network::Testnet, bitcoin::Network::Regtest) => true,
+        (address_network, network) => address_network == network,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Balance {
+    pub confirmed: Amount,
+    pub unconfirmed: Amount,
+}
+
 pub struct Wallet {
     inner: Arc<Mutex<bdk::Wallet<ElectrumBlockchain, bdk::sled::Tree>>>,
+    db: bdk::sled::Tree,
     http_url: Url,
     rpc_url: Url,
+    fee_config: FeeConfig,
+    /// Outputs spent by transactions that were built but not published yet.
+    /// They are not selected again so that the lock transactions of swaps
+    /// running at the same time do not spend the same outputs.
+    reserved: Mutex<HashSet<OutPoint>>,
 }
 
 impl Wallet {
@@ -47,6 +76,7 @@ impl Wallet {
         network: bitcoin::Network,
         wallet_dir: &Path,
         key: impl DerivableKey<Segwitv0> + Clone,
+        fee_config: FeeConfig,
     ) -> Result<Self> {
         // Workaround for https://github.com/bitcoindevkit/rust-electrum-client/issues/47.
         let config = electrum_client::ConfigBuilder::default().retry(2).build();
@@ -60,14 +90,17 @@ impl Wallet {
             bdk::template::BIP84(key.clone(), KeychainKind::External),
             Some(bdk::template::BIP84(key, KeychainKind::Internal)),
             network,
-            db,
+            db.clone(),
             ElectrumBlockchain::from(client),
         )?;
 
         Ok(Self {
             inner: Arc::new(Mutex::new(bdk_wallet)),
+            db,
             http_url: electrum_http_url,
             rpc_url: electrum_rpc_url,
+            fee_config,
+            reserved: Mutex::new(HashSet::new()),
         })
     }
 
@@ -76,6 +109,31 @@ impl Wallet {
         Ok(Amount::from_sat(balance))
     }
 
+    /// Splits the balance into the funds in confirmed and in unconfirmed
+    /// transactions.
+    pub async fn detailed_balance(&self) -> Result<Balance> {
+        let wallet = self.inner.lock().await;
+
+        let confirmed_txids = wallet
+            .list_transactions(false)?
+            .into_iter()
+            .filter(|tx| tx.height.is_some())
+            .map(|tx| tx.txid)
+            .collect::<HashSet<_>>();
+
+        let mut balance = Balance::default();
+        for utxo in wallet.list_unspent()? {
+            let value = Amount::from_sat(utxo.txout.value);
+            if confirmed_txids.contains(&utxo.outpoint.txid) {
+                balance.confirmed += value;
+            } else {
+                balance.unconfirmed += value;
+            }
+        }
+
+        Ok(balance)
+    }
+
     pub async fn new_address(&self) -> Result<Address> {
         let address = self.inner.lock().await.get_new_address()?;
 
@@ -104,7 +162,37 @@ impl Wallet {
     }
 
     pub async fn sync_wallet(&self) -> Result<()> {
-        self.inner.lock().await.sync(noop_progress(), None)?;
+        let wallet = self.inner.lock().await;
+        wallet.sync(noop_progress(), None)?;
+
+        // Outputs spent by a published transaction are no longer unspent
+        let unspent = wallet
+            .list_unspent()?
+            .into_iter()
+            .map(|utxo| utxo.outpoint)
+            .collect::<HashSet<_>>();
+        self.reserved
+            .lock()
+            .await
+            .retain(|outpoint| unspent.contains(outpoint));
+
+        Ok(())
+    }
+
+    /// Forgets the unconfirmed transactions and the ones confirmed from
+    /// `height` on, then fetches them again while syncing.
+    pub async fn rescan(&self, height: BlockHeight) -> Result<()> {
+        let wallet = self.inner.lock().await;
+
+        let forgotten = forget_transactions_from(&mut self.db.clone(), height)?;
+
+        tracing::info!(
+            "Rescanning {} transaction(s) from block height {}",
+            forgotten,
+            u32::from(height)
+        );
+        wallet.sync(noop_progress(), None)?;
+
         Ok(())
     }
 
@@ -113,31 +201,100 @@ impl Wallet {
         address: Address,
         amount: Amount,
     ) -> Result<PartiallySignedTransaction> {
+        let fee_rate = self.select_feerate().await;
+        let wallet = self.inner.lock().await;
+        let mut reserved = self.reserved.lock().await;
+
+        let psbt = send_reserving_inputs(
+            &wallet,
+            &mut reserved,
+            address.script_pubkey(),
+            amount,
+            fee_rate,
+        )?;
+
+        Ok(psbt)
+    }
+
+    /// Makes the outputs spent by a transaction built with
+    /// [`Wallet::send_to_address`] available again, for transactions that
+    /// will not be published.
+    pub async fn release(&self, psbt: PartiallySignedTransaction) {
+        let mut reserved = self.reserved.lock().await;
+
+        for input in psbt.global.unsigned_tx.input {
+            reserved.remove(&input.previous_output);
+        }
+    }
+
+    /// Spends all funds of the wallet to a single output paying `address`,
+    /// the fee is deducted from the output.
+    pub async fn drain_to_address(&self, address: Address) -> Result<PartiallySignedTransaction> {
+        let fee_rate = self.select_feerate().await;
         let wallet = self.inner.lock().await;
+        let reserved = self.reserved.lock().await;
 
         let mut tx_builder = wallet.build_tx();
-        tx_builder.add_recipient(address.script_pubkey(), amount.as_sat());
-        tx_builder.fee_rate(self.select_feerate());
+        tx_builder.set_single_recipient(address.script_pubkey());
+        tx_builder.drain_wallet();
+        tx_builder.unspendable(reserved.iter().copied().collect());
+        tx_builder.fee_rate(fee_rate);
         let (psbt, _details) = tx_builder.finish()?;
 
         Ok(psbt)
     }
 
+    /// Sends `amount`, or everything the wallet holds if `None`, to an
+    /// address outside of the swap.
+    pub async fn withdraw(&self, address: Address, amount: Option<Amount>) -> Result<Txid> {
+        let network = self.get_network().await;
+        if !is_address_on_network(&address, network) {
+            bail!(AddressNetworkMismatch {
+                address: address.clone(),
+                network
+            })
+        }
+
+        let psbt = match amount {
+            Some(amount) => self.send_to_address(address, amount).await?,
+            None => self.drain_to_address(address).await?,
+        };
+        let transaction = self.sign_and_finalize(psbt).await?;
+
+        self.broadcast(transaction, "withdraw").await
+    }
+
     /// Calculates the maximum "giveable" amount of this wallet.
     ///
     /// We define this as the maximum amount we can pay to a single output,
     /// already accounting for the fees we need to spend to get the
-    /// transaction confirmed.
+    /// transaction confirmed. It is zero if the balance does not cover the
+    /// fees or what remains after them is dust. Outputs reserved by other
+    /// swaps are not included.
     pub async fn max_giveable(&self, locking_script_size: usize) -> Result<Amount> {
+        let fee_rate = self.select_feerate().await;
         let wallet = self.inner.lock().await;
+        let reserved = self.reserved.lock().await;
+
+        // The wallet cannot build a transaction without any funds
+        if wallet.get_balance()? == 0 {
+            return Ok(Amount::ZERO);
+        }
 
         let mut tx_builder = wallet.build_tx();
 
         let dummy_script = Script::from(vec![0u8; locking_script_size]);
         tx_builder.set_single_recipient(dummy_script);
         tx_builder.drain_wallet();
-        tx_builder.fee_rate(self.select_feerate());
-        let (_, details) = tx_builder.finish()?;
+        tx_builder.unspendable(reserved.iter().copied().collect());
+        tx_builder.fee_rate(fee_rate);
+        let (_, details) = match tx_builder.finish() {
+            Ok(tx) => tx,
+            Err(bdk::Error::InsufficientFunds) | Err(bdk::Error::OutputBelowDustLimit(_)) => {
+                return Ok(Amount::ZERO)
+            }
+            Err(error) => return Err(error.into()),
+        };
 
         let max_giveable = details.sent - details.fees;
 
@@ -229,11 +386,6 @@ impl Wallet {
 
     pub async fn transaction_block_height(&self, txid: Txid) -> Result<BlockHeight> {
         let url = tx_status_url(txid, &self.http_url)?;
-        #[derive(Serialize, Deserialize, Debug, Clone)]
-        struct TransactionStatus {
-            block_height: Option<u32>,
-            confirmed: bool,
-        }
         let height = retry(ConstantBackoff::new(Duration::from_secs(1)), || async {
             let resp = reqwest::Client::new()
                 .request(Method::GET, url.clone())
@@ -258,6 +410,27 @@ impl Wallet {
         Ok(BlockHeight::new(height))
     }
 
+    /// Returns the height of the block the transaction was included in, or
+    /// `None` if it is not mined (yet), without waiting for it.
+    pub async fn transaction_block_height_if_mined(
+        &self,
+        txid: Txid,
+    ) -> Result<Option<BlockHeight>> {
+        let url = tx_status_url(txid, &self.http_url)?;
+        let response = reqwest::Client::new()
+            .request(Method::GET, url)
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let tx_status: TransactionStatus = response.error_for_status()?.json().await?;
+
+        Ok(tx_status.block_height.map(BlockHeight::new))
+    }
+
     pub async fn wait_for_transaction_finality(
         &self,
         txid: Txid,
@@ -289,14 +462,58 @@ impl Wallet {
         Ok(())
     }
 
+    /// The fees we propose for the transactions spending the lock and cancel
+    /// output, based on the current fee rate.
+    pub async fn estimate_tx_fees(&self) -> TxFees {
+        TxFees::from_fee_rate(self.select_feerate().await)
+    }
+
+    /// The highest fees we accept for the transactions spending the lock and
+    /// cancel output.
+    pub fn max_tx_fees(&self) -> TxFees {
+        TxFees::from_fee_rate(self.fee_config.max_fee_rate())
+    }
+
     /// Selects an appropriate [`FeeRate`] to be used for getting transactions
-    /// confirmed within a reasonable amount of time.
-    fn select_feerate(&self) -> FeeRate {
-        // TODO: This should obviously not be a const :)
-        FeeRate::from_sat_per_vb(5.0)
+    /// confirmed within the configured number of blocks.
+    ///
+    /// The estimate is requested through a connection of its own on a blocking
+    /// thread, so that neither the runtime nor the other users of the wallet
+    /// wait for Electrum.
+    async fn select_feerate(&self) -> FeeRate {
+        let rpc_url = self.rpc_url.clone();
+        let target_block = self.fee_config.target_block;
+
+        let estimate = tokio::task::spawn_blocking(move || -> Result<FeeRate> {
+            let client = Client::new(rpc_url.as_str())?;
+
+            Ok(ElectrumBlockchain::from(client).estimate_fee(target_block)?)
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|estimate| estimate);
+
+        let estimate = match estimate {
+            Ok(fee_rate) => Some(fee_rate),
+            Err(e) => {
+                tracing::warn!("Failed to estimate fee rate, using fallback: {:#}", e);
+                None
+            }
+        };
+
+        let fee_rate = self.fee_config.select(estimate);
+        tracing::debug!("Selected fee rate of {} sat/vB", fee_rate.as_sat_vb());
+
+        fee_rate
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct TransactionStatus {
+    block_height: Option<u32>,
+    confirmed: bool,
+}
+
 fn tx_status_url(txid: Txid, base_url: &Url) -> Result<Url> {
     let url = base_url.join(&format!("tx/{}/status", txid))?;
     Ok(url)
@@ -307,21 +524,91 @@ fn blocks_tip_height_url(base_url: &Url) -> Result<Url> {
     Ok(url)
 }
 
+/// Builds a transaction paying `amount` to `script_pubkey` from outputs that
+/// are not reserved yet and reserves the outputs it spends.
+fn send_reserving_inputs<B, D>(
+    wallet: &bdk::Wallet<B, D>,
+    reserved: &mut HashSet<OutPoint>,
+    script_pubkey: Script,
+    amount: Amount,
+    fee_rate: FeeRate,
+) -> Result<PartiallySignedTransaction>
+where
+    B: Blockchain,
+    D: BatchDatabase,
+{
+    let mut tx_builder = wallet.build_tx();
+    tx_builder.add_recipient(script_pubkey, amount.as_sat());
+    tx_builder.unspendable(reserved.iter().copied().collect());
+    tx_builder.fee_rate(fee_rate);
+    let (psbt, _details) = tx_builder.finish()?;
+
+    reserved.extend(
+        psbt.global
+            .unsigned_tx
+            .input
+            .iter()
+            .map(|input| input.previous_output),
+    );
+
+    Ok(psbt)
+}
+
+/// Removes the unconfirmed transactions and the ones confirmed from `height`
+/// on together with their outputs, returns how many were removed.
+fn forget_transactions_from<D>(db: &mut D, height: BlockHeight) -> Result<usize>
+where
+    D: Database + BatchOperations,
+{
+    let forgotten = db
+        .iter_txs(false)?
+        .into_iter()
+        .filter(|tx| {
+            tx.height
+                .map_or(true, |tx_height| BlockHeight::new(tx_height) >= height)
+        })
+        .map(|tx| tx.txid)
+        .collect::<HashSet<_>>();
+
+    for utxo in db.iter_utxos()? {
+        if forgotten.contains(&utxo.outpoint.txid) {
+            db.del_utxo(&utxo.outpoint)?;
+        }
+    }
+    for txid in &forgotten {
+        db.del_tx(txid, true)?;
+    }
+
+    Ok(forgotten.len())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
         bitcoin::{
-            wallet::{blocks_tip_height_url, tx_status_url},
-            Txid,
+            timelocks::BlockHeight,
+            wallet::{
+                blocks_tip_height_url, forget_transactions_from, is_address_on_network,
+                send_reserving_inputs, tx_status_url,
+            },
+            Address, Amount, Network, Txid,
         },
-        cli::config::DEFAULT_ELECTRUM_HTTP_URL,
+        env::Env,
+    };
+    use ::bitcoin::{util::psbt::PartiallySignedTransaction, OutPoint, Script, TxOut};
+    use bdk::{
+        database::{BatchOperations, Database, MemoryDatabase},
+        FeeRate, KeychainKind, TransactionDetails, UTXO,
     };
-    use reqwest::Url;
+    use std::collections::HashSet;
+
+    const DESCRIPTOR: &str = "wpkh(tprv8ZgxMBicQKsPd3krDUsBAmtnRsK3rb8u5yi1zhQgMhF1tR8MW7xfE4rnrbbsrbPR52e7rKapu6ztw1jXveJSCGHEriUGZV7mCe88duLp5pj/84'/1'/0'/0/*)";
+    const CHANGE_DESCRIPTOR: &str = "wpkh(tprv8ZgxMBicQKsPd3krDUsBAmtnRsK3rb8u5yi1zhQgMhF1tR8MW7xfE4rnrbbsrbPR52e7rKapu6ztw1jXveJSCGHEriUGZV7mCe88duLp5pj/84'/1'/0'/1/*)";
 
     #[test]
     fn create_tx_status_url_from_default_base_url_success() {
         let txid: Txid = Txid::default();
-        let base_url = Url::parse(DEFAULT_ELECTRUM_HTTP_URL).expect("Could not parse url");
+        let base_url = Env::Testnet.default_electrum_http_url();
         let url = tx_status_url(txid, &base_url).expect("Could not create url");
         let expected = format!("https://blockstream.info/testnet/api/tx/{}/status", txid);
         assert_eq!(url.as_str(), expected);
@@ -329,9 +616,148 @@ mod tests {
 
     #[test]
     fn create_block_tip_height_url_from_default_base_url_success() {
-        let base_url = Url::parse(DEFAULT_ELECTRUM_HTTP_URL).expect("Could not parse url");
+        let base_url = Env::Testnet.default_electrum_http_url();
         let url = blocks_tip_height_url(&base_url).expect("Could not create url");
         let expected = "https://blockstream.info/testnet/api/blocks/tip/height";
         assert_eq!(url.as_str(), expected);
     }
+
+    #[test]
+    fn address_must_match_network() {
+        let mainnet: Address = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+            .parse()
+            .unwrap();
+        let testnet: Address = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx"
+            .parse()
+            .unwrap();
+
+        assert!(is_address_on_network(&mainnet, Network::Bitcoin));
+        assert!(!is_address_on_network(&mainnet, Network::Testnet));
+        assert!(!is_address_on_network(&testnet, Network::Bitcoin));
+        assert!(is_address_on_network(&testnet, Network::Regtest));
+    }
+
+    #[test]
+    fn rescan_forgets_transactions_from_the_given_height() {
+        let mut db = MemoryDatabase::new();
+
+        let heights = vec![Some(90), Some(100), Some(110), None];
+        let txids = (1..=heights.len())
+            .map(|n| format!("{:064x}", n).parse::<Txid>().unwrap())
+            .collect::<Vec<_>>();
+
+        for (txid, height) in txids.iter().zip(heights) {
+            db.set_tx(&TransactionDetails {
+                transaction: None,
+                txid: *txid,
+                timestamp: 0,
+                received: 10_000,
+                sent: 0,
+                fees: 0,
+                height,
+            })
+            .unwrap();
+            db.set_utxo(&UTXO {
+                outpoint: OutPoint::new(*txid, 0),
+                txout: TxOut {
+                    value: 10_000,
+                    script_pubkey: Script::new(),
+                },
+                keychain: KeychainKind::External,
+            })
+            .unwrap();
+        }
+
+        let forgotten = forget_transactions_from(&mut db, BlockHeight::new(100)).unwrap();
+
+        assert_eq!(forgotten, 3);
+        let remaining_txs = db
+            .iter_txs(false)
+            .unwrap()
+            .into_iter()
+            .map(|tx| tx.txid)
+            .collect::<Vec<_>>();
+        assert_eq!(remaining_txs, vec![txids[0]]);
+        let remaining_utxos = db
+            .iter_utxos()
+            .unwrap()
+            .into_iter()
+            .map(|utxo| utxo.outpoint.txid)
+            .collect::<Vec<_>>();
+        assert_eq!(remaining_utxos, vec![txids[0]]);
+    }
+
+    #[test]
+    fn swaps_funded_from_one_wallet_do_not_spend_the_same_outputs() {
+        let scripts = {
+            let wallet = bdk::Wallet::new_offline(
+                DESCRIPTOR,
+                Some(CHANGE_DESCRIPTOR),
+                Network::Testnet,
+                MemoryDatabase::new(),
+            )
+            .unwrap();
+            (0..2)
+                .map(|_| wallet.get_new_address().unwrap().script_pubkey())
+                .collect::<Vec<_>>()
+        };
+
+        let mut db = MemoryDatabase::new();
+        for (index, script) in scripts.iter().enumerate() {
+            let txid = format!("{:064x}", index + 1).parse::<Txid>().unwrap();
+            db.set_script_pubkey(script, KeychainKind::External, index as u32)
+                .unwrap();
+            db.set_utxo(&UTXO {
+                outpoint: OutPoint::new(txid, 0),
+                txout: TxOut {
+                    value: 100_000,
+                    script_pubkey: script.clone(),
+                },
+                keychain: KeychainKind::External,
+            })
+            .unwrap();
+        }
+        db.set_last_index(KeychainKind::External, 1).unwrap();
+        let wallet =
+            bdk::Wallet::new_offline(DESCRIPTOR, Some(CHANGE_DESCRIPTOR), Network::Testnet, db)
+                .unwrap();
+
+        let lock_script = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx"
+            .parse::<Address>()
+            .unwrap()
+            .script_pubkey();
+        let amount = Amount::from_sat(60_000);
+        let fee_rate = FeeRate::from_sat_per_vb(1.0);
+        let mut reserved = HashSet::new();
+
+        let first = send_reserving_inputs(
+            &wallet,
+            &mut reserved,
+            lock_script.clone(),
+            amount,
+            fee_rate,
+        )
+        .unwrap();
+        let second = send_reserving_inputs(
+            &wallet,
+            &mut reserved,
+            lock_script.clone(),
+            amount,
+            fee_rate,
+        )
+        .unwrap();
+
+        let inputs = |psbt: &PartiallySignedTransaction| {
+            psbt.global
+                .unsigned_tx
+                .input
+                .iter()
+                .map(|input| input.previous_output)
+                .collect::<HashSet<_>>()
+        };
+        assert!(inputs(&first).is_disjoint(&inputs(&second)));
+        assert!(
+            send_reserving_inputs(&wallet, &mut reserved, lock_script, amount, fee_rate).is_err()
+        );
+    }
 }
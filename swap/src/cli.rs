@@ -1,2 +1,4 @@
 pub mod command;
 pub mod config;
+pub mod output;
+pub mod progress;
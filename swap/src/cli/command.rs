@@ -1,5 +1,7 @@
+use crate::{bitcoin, env::Env, jude, network::rendezvous::RendezvousPoint, profile::Profile};
+use anyhow::{bail, Context, Result};
 use libp2p::{core::Multiaddr, PeerId};
-use std::path::PathBuf;
+use std::{path::PathBuf, str::FromStr};
 use uuid::Uuid;
 
 pub const DEFAULT_ALICE_MULTIADDR: &str = "/dns4/jude-btc-asb.coblox.tech/tcp/9876";
@@ -14,9 +16,34 @@ pub struct Arguments {
     )]
     pub config: Option<PathBuf>,
 
+    #[structopt(
+        long = "env",
+        help = "The network to swap on, one of mainnet, testnet or regtest. Must match the network of an existing configuration file."
+    )]
+    pub env: Option<Env>,
+
+    #[structopt(
+        long = "profile",
+        help = "The profile to use, each profile has its own seed, database and wallets.",
+        default_value = "default"
+    )]
+    pub profile: Profile,
+
     #[structopt(long, help = "Activate debug logging.")]
     pub debug: bool,
 
+    #[structopt(
+        long = "tor-socks5-port",
+        help = "Dial all addresses, including onion addresses, through the SOCKS5 proxy of the local Tor daemon on this port, usually 9050."
+    )]
+    pub tor_socks5_port: Option<u16>,
+
+    #[structopt(
+        long,
+        help = "Print the result as JSON to stdout, logs are still written to stderr."
+    )]
+    pub json: bool,
+
     #[structopt(subcommand)]
     pub cmd: Option<Command>,
 }
@@ -33,22 +60,79 @@ pub enum Command {
         default_value = DEFAULT_ALICE_MULTIADDR
         )]
         alice_addr: Multiaddr,
+
+        #[structopt(
+            long = "send-btc",
+            help = "The amount of BTC to swap. Swaps the whole balance of the internal wallet if not given, waits for a deposit if the balance is not sufficient.",
+            parse(try_from_str = parse_btc)
+        )]
+        send_btc: Option<bitcoin::Amount>,
+
+        #[structopt(
+            long = "min-jude",
+            help = "Abort the swap if Alice offers less jude than this. Skips the interactive confirmation of the quote.",
+            parse(try_from_str = parse_jude)
+        )]
+        min_jude: Option<jude::Amount>,
+
+        #[structopt(
+            long = "max-rate",
+            help = "Abort the swap if Alice asks for more BTC than this per jude. Skips the interactive confirmation of the quote.",
+            parse(try_from_str = parse_btc)
+        )]
+        max_rate: Option<bitcoin::Amount>,
+    },
+    #[structopt(
+        about = "Runs several swaps at the same time, with the same or with different sellers, and shows the progress of all of them in one table."
+    )]
+    BuyjudeMany {
+        #[structopt(
+            long = "swap",
+            help = "A swap to start, given as <btc-amount>,<peer-id>,<multiaddr>. Can be given several times.",
+            number_of_values = 1
+        )]
+        swaps: Vec<SwapParams>,
+
+        #[structopt(
+            long = "resume",
+            help = "The id of an unfinished swap to resume alongside the new ones. Can be given several times.",
+            number_of_values = 1
+        )]
+        resume: Vec<Uuid>,
+
+        #[structopt(
+            long = "max-rate",
+            help = "Abort a swap if Alice asks for more BTC than this per jude.",
+            parse(try_from_str = parse_btc)
+        )]
+        max_rate: bitcoin::Amount,
+    },
+    #[structopt(
+        about = "Asks a seller for the current price and the amounts it trades, without starting a swap."
+    )]
+    Quote {
+        #[structopt(long = "connect-peer-id", default_value = DEFAULT_ALICE_PEER_ID)]
+        alice_peer_id: PeerId,
+
+        #[structopt(long = "connect-addr", default_value = DEFAULT_ALICE_MULTIADDR)]
+        alice_addr: Multiaddr,
     },
     History,
     Resume {
         #[structopt(long = "swap-id")]
         swap_id: Uuid,
 
-        // TODO: Remove Alice peer-id/address, it should be saved in the database when running swap
-        // and loaded from the database when running resume/cancel/refund
-        #[structopt(long = "counterpart-peer-id", default_value = DEFAULT_ALICE_PEER_ID)]
-        alice_peer_id: PeerId,
+        #[structopt(
+            long = "counterpart-peer-id",
+            help = "Only needed for swaps that were started without recording the counterparty."
+        )]
+        alice_peer_id: Option<PeerId>,
 
         #[structopt(
-        long = "counterpart-addr",
-        default_value = DEFAULT_ALICE_MULTIADDR
+            long = "counterpart-addr",
+            help = "Overrides the address the counterparty was reached on when the swap was started."
         )]
-        alice_addr: Multiaddr,
+        alice_addr: Option<Multiaddr>,
     },
     Cancel {
         #[structopt(long = "swap-id")]
@@ -64,6 +148,128 @@ pub enum Command {
         #[structopt(short, long)]
         force: bool,
     },
+    SwapDetails {
+        #[structopt(long = "swap-id")]
+        swap_id: Uuid,
+    },
+    Balance,
+    DepositAddress,
+    WithdrawBtc {
+        #[structopt(long = "address", help = "The address to send the BTC to.")]
+        address: bitcoin::Address,
+
+        #[structopt(
+            long = "amount",
+            help = "The amount of BTC to withdraw. Withdraws the whole balance of the internal wallet if not given.",
+            parse(try_from_str = parse_btc)
+        )]
+        amount: Option<bitcoin::Amount>,
+    },
+    Export {
+        #[structopt(
+            long = "swap-id",
+            help = "The swap to export. Exports all swaps if not given."
+        )]
+        swap_id: Option<Uuid>,
+
+        #[structopt(
+            long = "file",
+            help = "The file to write the swaps to, it must not exist yet.",
+            parse(from_os_str)
+        )]
+        file: PathBuf,
+
+        #[structopt(
+            long = "encrypt",
+            help = "Encrypt the file with a passphrase that is asked for. Exports of an encrypted database are encrypted with its passphrase otherwise."
+        )]
+        encrypt: bool,
+    },
+    #[structopt(
+        about = "Lists the sellers registered at a rendezvous point for the network we swap on, cheapest first."
+    )]
+    ListSellers {
+        #[structopt(
+            long = "rendezvous-point",
+            help = "The multiaddress of the rendezvous point including its peer id, e.g. /ip4/1.2.3.4/tcp/8888/p2p/12D3KooW..."
+        )]
+        rendezvous_point: RendezvousPoint,
+    },
+    Import {
+        #[structopt(
+            long = "file",
+            help = "A file written by the export command. Swaps already known are skipped.",
+            parse(from_os_str)
+        )]
+        file: PathBuf,
+    },
+    #[structopt(
+        about = "Encrypts the seed and the database with a new passphrase, removes the encryption if the passphrase is empty. The new passphrase is read from SWAP_NEW_PASSPHRASE if set."
+    )]
+    ChangePassword,
+    #[structopt(
+        about = "Prints the seed as a mnemonic of 24 words. Everything needed to restore the Bitcoin wallet and the peer id can be restored from it."
+    )]
+    ExportSeed,
+    #[structopt(
+        about = "Replaces the seed with one restored from a mnemonic and rescans the Bitcoin wallet. The mnemonic is asked for, or read from stdin if it is not a terminal."
+    )]
+    RestoreSeed {
+        #[structopt(
+            long = "rescan-from-height",
+            help = "The block height from which the Bitcoin wallet transactions are fetched again.",
+            default_value = "0"
+        )]
+        rescan_from_height: u32,
+
+        #[structopt(
+            long = "force",
+            help = "Replace a different seed even if swaps or a Bitcoin wallet depend on it."
+        )]
+        force: bool,
+    },
+    Profiles {
+        #[structopt(subcommand)]
+        cmd: ProfilesCommand,
+    },
+}
+
+#[derive(structopt::StructOpt, Debug)]
+pub enum ProfilesCommand {
+    #[structopt(about = "Lists the default profile and every profile that has been used.")]
+    List,
+}
+
+/// A swap started by [`Command::BuyjudeMany`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwapParams {
+    pub send_btc: bitcoin::Amount,
+    pub alice_peer_id: PeerId,
+    pub alice_addr: Multiaddr,
+}
+
+impl FromStr for SwapParams {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(3, ',');
+        let (send_btc, alice_peer_id, alice_addr) = match (parts.next(), parts.next(), parts.next())
+        {
+            (Some(send_btc), Some(alice_peer_id), Some(alice_addr)) => {
+                (send_btc, alice_peer_id, alice_addr)
+            }
+            _ => bail!(
+                "Expected <btc-amount>,<peer-id>,<multiaddr> but got {:?}",
+                s
+            ),
+        };
+
+        Ok(Self {
+            send_btc: parse_btc(send_btc).context("Invalid BTC amount")?,
+            alice_peer_id: alice_peer_id.parse().context("Invalid peer id")?,
+            alice_addr: alice_addr.parse().context("Invalid multiaddr")?,
+        })
+    }
 }
 
 impl Default for Command {
@@ -75,13 +281,29 @@ impl Default for Command {
             alice_addr: DEFAULT_ALICE_MULTIADDR
                 .parse()
                 .expect("default alice multiaddr str is a valid PeerId"),
+            send_btc: None,
+            min_jude: None,
+            max_rate: None,
         }
     }
 }
 
+fn parse_jude(str: &str) -> Result<jude::Amount> {
+    let amount = jude::Amount::parse_jude(str)?;
+    Ok(amount)
+}
+
+fn parse_btc(str: &str) -> Result<bitcoin::Amount> {
+    let amount = bitcoin::Amount::from_str_in(str, ::bitcoin::Denomination::Bitcoin)?;
+    Ok(amount)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::cli::command::{Command, DEFAULT_ALICE_MULTIADDR, DEFAULT_ALICE_PEER_ID};
+    use crate::{
+        bitcoin,
+        cli::command::{Command, SwapParams, DEFAULT_ALICE_MULTIADDR, DEFAULT_ALICE_PEER_ID},
+    };
     use libp2p::{core::Multiaddr, PeerId};
 
     #[test]
@@ -102,4 +324,20 @@ mod tests {
     fn default_command_success() {
         Command::default();
     }
+
+    #[test]
+    fn parse_swap_params() {
+        let params = format!("0.01,{},{}", DEFAULT_ALICE_PEER_ID, DEFAULT_ALICE_MULTIADDR)
+            .parse::<SwapParams>()
+            .unwrap();
+
+        assert_eq!(params, SwapParams {
+            send_btc: bitcoin::Amount::from_sat(1_000_000),
+            alice_peer_id: DEFAULT_ALICE_PEER_ID.parse().unwrap(),
+            alice_addr: DEFAULT_ALICE_MULTIADDR.parse().unwrap(),
+        });
+        assert!(format!("0.01,{}", DEFAULT_ALICE_PEER_ID)
+            .parse::<SwapParams>()
+            .is_err());
+    }
 }
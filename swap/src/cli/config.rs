@@ -1,4 +1,4 @@
-use crate::fs::default_data_dir;
+use crate::{bitcoin::FeeConfig, env::Env, fs::default_data_dir};
 use anyhow::{Context, Result};
 use config::ConfigError;
 use serde::{Deserialize, Serialize};
@@ -9,11 +9,10 @@ use std::{
 use tracing::debug;
 use url::Url;
 
-pub const DEFAULT_ELECTRUM_HTTP_URL: &str = "https://blockstream.info/testnet/api/";
-const DEFAULT_ELECTRUM_RPC_URL: &str = "ssl://electrum.blockstream.info:60002";
-
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, PartialEq)]
 pub struct Config {
+    #[serde(default)]
+    pub env: Env,
     pub data: Data,
     pub bitcoin: Bitcoin,
 }
@@ -30,21 +29,25 @@ impl Config {
         config.try_into()
     }
 
-    pub fn testnet() -> Self {
+    pub fn for_env(env: Env) -> Self {
         Self {
+            env,
             data: Data {
                 dir: default_data_dir().expect("computed valid path for data dir"),
             },
             bitcoin: Bitcoin {
-                electrum_http_url: DEFAULT_ELECTRUM_HTTP_URL
-                    .parse()
-                    .expect("default electrum http str is a valid url"),
-                electrum_rpc_url: DEFAULT_ELECTRUM_RPC_URL
-                    .parse()
-                    .expect("default electrum rpc str is a valid url"),
+                electrum_http_url: env.default_electrum_http_url(),
+                electrum_rpc_url: env.default_electrum_rpc_url(),
+                fees: FeeConfig::default(),
             },
         }
     }
+
+    /// The directory holding the seed, database and wallets of the configured
+    /// environment.
+    pub fn env_data_dir(&self) -> PathBuf {
+        self.env.data_dir(&self.data.dir)
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -58,6 +61,8 @@ pub struct Data {
 pub struct Bitcoin {
     pub electrum_http_url: Url,
     pub electrum_rpc_url: Url,
+    #[serde(default)]
+    pub fees: FeeConfig,
 }
 
 #[derive(thiserror::Error, Debug, Clone, Copy)]
@@ -84,7 +89,7 @@ pub fn read_config(config_path: PathBuf) -> Result<Result<Config, ConfigNotIniti
 mod tests {
     use super::*;
     use crate::fs::ensure_directory_exists;
-    use std::{fs, str::FromStr};
+    use std::fs;
     use tempfile::tempdir;
 
     pub fn initial_setup(config_path: PathBuf, config: Config) -> Result<()> {
@@ -102,12 +107,14 @@ mod tests {
         let config_path = Path::join(&temp_dir, "config.toml");
 
         let expected = Config {
+            env: Env::Testnet,
             data: Data {
                 dir: Default::default(),
             },
             bitcoin: Bitcoin {
-                electrum_http_url: Url::from_str(DEFAULT_ELECTRUM_HTTP_URL).unwrap(),
-                electrum_rpc_url: Url::from_str(DEFAULT_ELECTRUM_RPC_URL).unwrap(),
+                electrum_http_url: Env::Testnet.default_electrum_http_url(),
+                electrum_rpc_url: Env::Testnet.default_electrum_rpc_url(),
+                fees: FeeConfig::default(),
             },
         };
 
@@ -116,4 +123,29 @@ mod tests {
 
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn config_without_env_defaults_to_testnet() {
+        let temp_dir = tempdir().unwrap().path().to_path_buf();
+        let config_path = Path::join(&temp_dir, "config.toml");
+
+        ensure_directory_exists(config_path.as_path()).unwrap();
+        fs::write(
+            &config_path,
+            r#"
+[data]
+dir = "/tmp"
+
+[bitcoin]
+electrum_http_url = "https://blockstream.info/testnet/api/"
+electrum_rpc_url = "ssl://electrum.blockstream.info:60002"
+"#,
+        )
+        .unwrap();
+
+        let actual = read_config(config_path).unwrap().unwrap();
+
+        assert_eq!(actual.env, Env::Testnet);
+        assert_eq!(actual.bitcoin.fees, FeeConfig::default());
+    }
 }
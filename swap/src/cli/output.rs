@@ -0,0 +1,195 @@
+//! Machine-readable output of the swap_cli, enabled with `--json`.
+//!
+//! Amounts are given in satoshi and piconero so that the records can be
+//! processed without having to parse decimal strings.
+
+use crate::{
+    bitcoin::Txid,
+    database::{Peer, SwapHistory},
+    network::rendezvous::Seller,
+    output::unix_timestamp,
+    protocol::{alice::BidQuote, bob::BobState},
+};
+use libp2p::{Multiaddr, PeerId};
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SwapRecord {
+    pub swap_id: Uuid,
+    /// The name of the state, e.g. `BtcLocked`.
+    pub state: &'static str,
+    /// A human readable description of the state.
+    pub description: String,
+    pub btc_amount_sat: Option<u64>,
+    pub jude_amount_piconero: Option<u64>,
+    pub tx_lock_id: Option<Txid>,
+    pub tx_cancel_id: Option<Txid>,
+    pub tx_refund_id: Option<Txid>,
+    /// `None` for swaps that were started before the counterparty was
+    /// recorded.
+    pub counterparty: Option<Peer>,
+    /// Seconds since the unix epoch at which the swap last changed state,
+    /// `None` for swaps started before the history was recorded.
+    pub timestamp: Option<u64>,
+    /// Seconds since the unix epoch at which the swap was started, `None`
+    /// for swaps started before the history was recorded.
+    pub started_at: Option<u64>,
+    /// The states the swap went through, oldest first.
+    pub transitions: Vec<Transition>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Transition {
+    pub state: String,
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+}
+
+impl SwapRecord {
+    pub fn new(swap_id: Uuid, state: &BobState, counterparty: Option<Peer>) -> Self {
+        Self {
+            swap_id,
+            state: state.name(),
+            description: state.to_string(),
+            btc_amount_sat: state.btc_amount().map(|amount| amount.as_sat()),
+            jude_amount_piconero: state.jude_amount().map(|amount| amount.as_piconero()),
+            tx_lock_id: state.tx_lock_id(),
+            tx_cancel_id: state.tx_cancel_id(),
+            tx_refund_id: state.tx_refund_id(),
+            counterparty,
+            timestamp: None,
+            started_at: None,
+            transitions: vec![],
+        }
+    }
+
+    pub fn with_history(mut self, history: SwapHistory) -> Self {
+        self.timestamp = history.last_updated_at().map(unix_timestamp);
+        self.started_at = history.started_at().map(unix_timestamp);
+        self.transitions = history
+            .0
+            .into_iter()
+            .map(|entry| Transition {
+                state: entry.state,
+                timestamp: unix_timestamp(entry.timestamp),
+            })
+            .collect();
+
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BidQuoteRecord {
+    /// The price of 1 jude for a swap of `min_buy_sat`.
+    pub price_sat: u64,
+    pub min_buy_sat: u64,
+    pub max_sell_piconero: u64,
+}
+
+impl From<BidQuote> for BidQuoteRecord {
+    fn from(quote: BidQuote) -> Self {
+        Self {
+            price_sat: quote.price.as_sat(),
+            min_buy_sat: quote.min_buy.as_sat(),
+            max_sell_piconero: quote.max_sell.as_piconero(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SellerRecord {
+    #[serde(with = "crate::serde_peer_id")]
+    pub peer_id: PeerId,
+    pub addresses: Vec<Multiaddr>,
+    #[serde(flatten)]
+    pub quote: BidQuoteRecord,
+}
+
+impl From<Seller> for SellerRecord {
+    fn from(seller: Seller) -> Self {
+        Self {
+            peer_id: seller.peer_id,
+            addresses: seller.addresses,
+            quote: seller.quote.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bitcoin, database::HistoryEntry};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn started_swap_record_contains_amount_and_counterparty() {
+        let swap_id = Uuid::new_v4();
+        let peer = Peer {
+            peer_id: libp2p::PeerId::random(),
+            address: "/ip4/127.0.0.1/tcp/9939".parse().unwrap(),
+        };
+        let state = BobState::Started {
+            btc_amount: bitcoin::Amount::from_sat(100_000),
+        };
+
+        let record = SwapRecord::new(swap_id, &state, Some(peer.clone()));
+        let json = serde_json::to_value(&record).unwrap();
+
+        assert_eq!(json["swap_id"], swap_id.to_string());
+        assert_eq!(json["state"], "Started");
+        assert_eq!(json["btc_amount_sat"], 100_000);
+        assert!(json["jude_amount_piconero"].is_null());
+        assert!(json["tx_lock_id"].is_null());
+        assert_eq!(json["counterparty"]["peer_id"], peer.peer_id.to_string());
+        assert_eq!(json["counterparty"]["address"], "/ip4/127.0.0.1/tcp/9939");
+    }
+
+    #[test]
+    fn swap_record_is_timestamped_with_the_latest_state() {
+        let started_at = UNIX_EPOCH + Duration::from_secs(1_000);
+        let aborted_at = UNIX_EPOCH + Duration::from_secs(1_060);
+        let history = SwapHistory(vec![
+            HistoryEntry {
+                timestamp: started_at,
+                state: "Started".to_string(),
+            },
+            HistoryEntry {
+                timestamp: aborted_at,
+                state: "SafelyAborted".to_string(),
+            },
+        ]);
+
+        let record = SwapRecord::new(Uuid::new_v4(), &BobState::SafelyAborted, None);
+        assert_eq!(record.timestamp, None);
+
+        let record = record.with_history(history);
+        assert_eq!(record.started_at, Some(1_000));
+        assert_eq!(record.timestamp, Some(1_060));
+        assert_eq!(record.transitions.len(), 2);
+        assert_eq!(record.transitions[1].state, "SafelyAborted");
+    }
+
+    #[test]
+    fn seller_record_contains_quote_amounts() {
+        let peer_id = PeerId::random();
+        let seller = Seller {
+            peer_id,
+            addresses: vec!["/ip4/127.0.0.1/tcp/9939".parse().unwrap()],
+            quote: BidQuote {
+                price: bitcoin::Amount::from_sat(500_000),
+                min_buy: bitcoin::Amount::from_sat(100_000),
+                max_sell: crate::jude::Amount::from_piconero(1_000_000),
+            },
+        };
+
+        let json = serde_json::to_value(&SellerRecord::from(seller)).unwrap();
+
+        assert_eq!(json["peer_id"], peer_id.to_string());
+        assert_eq!(json["addresses"][0], "/ip4/127.0.0.1/tcp/9939");
+        assert_eq!(json["price_sat"], 500_000);
+        assert_eq!(json["min_buy_sat"], 100_000);
+        assert_eq!(json["max_sell_piconero"], 1_000_000);
+    }
+}
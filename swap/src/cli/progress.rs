@@ -0,0 +1,136 @@
+//! Combined view of the progress of swaps that run at the same time.
+
+use crate::{database::Database, protocol::bob::BobState};
+use anyhow::{Error, Result};
+use libp2p::PeerId;
+use prettytable::{Cell, Row, Table};
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct Progress {
+    swaps: Vec<SwapProgress>,
+}
+
+#[derive(Debug)]
+struct SwapProgress {
+    swap_id: Uuid,
+    peer_id: PeerId,
+    /// The description of the latest state, `None` until the swap stored its
+    /// first state.
+    state: Option<String>,
+    error: Option<String>,
+}
+
+impl Progress {
+    pub fn new(swaps: impl IntoIterator<Item = (Uuid, PeerId)>) -> Self {
+        Self {
+            swaps: swaps
+                .into_iter()
+                .map(|(swap_id, peer_id)| SwapProgress {
+                    swap_id,
+                    peer_id,
+                    state: None,
+                    error: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Reads the latest state of every swap from the database. Returns whether
+    /// the state of any swap changed since the last refresh.
+    pub fn refresh(&mut self, db: &Database) -> Result<bool> {
+        let mut changed = false;
+
+        for swap in self.swaps.iter_mut() {
+            // Fails if the swap did not store a state yet.
+            let state = match db.get_state(swap.swap_id) {
+                Ok(state) => BobState::from(state.try_into_bob()?).to_string(),
+                Err(_) => continue,
+            };
+
+            if swap.state.as_ref() != Some(&state) {
+                swap.state = Some(state);
+                changed = true;
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// The swaps that stored at least one state.
+    pub fn started(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.swaps
+            .iter()
+            .filter(|swap| swap.state.is_some())
+            .map(|swap| swap.swap_id)
+    }
+
+    pub fn failed(&mut self, swap_id: Uuid, error: &Error) {
+        if let Some(swap) = self.swaps.iter_mut().find(|swap| swap.swap_id == swap_id) {
+            swap.error = Some(format!("{:#}", error));
+        }
+    }
+
+    pub fn table(&self) -> Table {
+        let mut table = Table::new();
+
+        table.add_row(Row::new(vec![
+            Cell::new("SWAP ID"),
+            Cell::new("PEER ID"),
+            Cell::new("STATE"),
+            Cell::new("ERROR"),
+        ]));
+
+        for swap in &self.swaps {
+            table.add_row(Row::new(vec![
+                Cell::new(&swap.swap_id.to_string()),
+                Cell::new(&swap.peer_id.to_string()),
+                Cell::new(swap.state.as_deref().unwrap_or("not started")),
+                Cell::new(swap.error.as_deref().unwrap_or("")),
+            ]));
+        }
+
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Bob, Swap};
+    use anyhow::anyhow;
+
+    #[tokio::test]
+    async fn progress_follows_the_stored_states() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+
+        let peer_id: PeerId = "12D3KooWCdMKjesXMJz1SiZ7HgotrxuqhQJbP5sgBm2BwP1cqThi"
+            .parse()
+            .unwrap();
+        let started = Uuid::new_v4();
+        let not_started = Uuid::new_v4();
+        let mut progress = Progress::new(vec![(started, peer_id), (not_started, peer_id)]);
+
+        assert!(!progress.refresh(&db).unwrap());
+
+        db.insert_latest_state(
+            started,
+            Swap::Bob(Bob::Started {
+                btc_amount: ::bitcoin::Amount::from_sat(100_000),
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert!(progress.refresh(&db).unwrap());
+        assert!(!progress.refresh(&db).unwrap());
+        assert_eq!(progress.started().collect::<Vec<_>>(), vec![started]);
+
+        progress.failed(not_started, &anyhow!("connection refused"));
+        let table = progress.table();
+
+        assert_eq!(table.len(), 3);
+        assert_eq!(table[2][3].get_content(), "connection refused");
+    }
+}
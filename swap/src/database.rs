@@ -1,13 +1,29 @@
 pub use alice::Alice;
+pub use backup::{Backup, SwapExport, UnsupportedBackupVersion};
 pub use bob::Bob;
-
+pub use migrations::{SchemaTooNew, UnexpectedSchemaVersion, SCHEMA_VERSION};
+
+use crate::{
+    encryption::{Encrypted, Key, Sealed},
+    env::{Env, EnvMismatch},
+    protocol::{alice::AliceState, bob::BobState},
+    serde_peer_id,
+};
 use anyhow::{anyhow, bail, Context, Result};
+use libp2p::{Multiaddr, PeerId};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::{fmt::Display, path::Path};
+use sled::{transaction::TransactionError, Transactional};
+use std::{
+    fmt::Display,
+    path::Path,
+    time::{Duration, SystemTime},
+};
 use uuid::Uuid;
 
 mod alice;
+mod backup;
 mod bob;
+mod migrations;
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub enum Swap {
@@ -43,56 +59,371 @@ impl Swap {
             Swap::Alice(_) => bail!("Swap instance is not Bob"),
         }
     }
+
+    pub fn try_into_alice(self) -> Result<Alice> {
+        match self {
+            Swap::Alice(alice) => Ok(alice),
+            Swap::Bob(_) => bail!("Swap instance is not Alice"),
+        }
+    }
+
+    /// The name of the state, see [`AliceState::name`] and
+    /// [`BobState::name`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            Swap::Alice(alice) => AliceState::from(alice.clone()).name(),
+            Swap::Bob(bob) => BobState::from(bob.clone()).name(),
+        }
+    }
+}
+
+/// Name of the sled tree holding data about the database itself rather than
+/// about individual swaps. Its values are not versioned as it holds the schema
+/// version itself.
+const METADATA_TREE: &str = "metadata";
+const ENV_KEY: &str = "env";
+
+/// Metadata key of the key encrypting the values, itself encrypted with the
+/// passphrase. Absent if the database is not encrypted.
+const ENCRYPTION_KEY: &str = "encryption_key";
+
+/// Name of the sled tree holding the counterparty of each swap.
+const PEERS_TREE: &str = "peers";
+
+/// Name of the sled tree holding every state each swap went through.
+const HISTORY_TREE: &str = "history";
+
+/// The peer we are swapping with and the address we reached it on.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Peer {
+    #[serde(with = "serde_peer_id")]
+    pub peer_id: PeerId,
+    pub address: Multiaddr,
 }
 
-pub struct Database(sled::Db);
+/// A state of a swap and when it was reached.
+///
+/// Only the name of the state is kept, the state itself is superseded by the
+/// next one.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct HistoryEntry {
+    pub timestamp: SystemTime,
+    pub state: String,
+}
+
+/// The states a swap went through, oldest first.
+///
+/// Empty for swaps that were started before the history was recorded.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SwapHistory(pub Vec<HistoryEntry>);
+
+impl SwapHistory {
+    pub fn started_at(&self) -> Option<SystemTime> {
+        self.0.first().map(|entry| entry.timestamp)
+    }
+
+    pub fn last_updated_at(&self) -> Option<SystemTime> {
+        self.0.last().map(|entry| entry.timestamp)
+    }
+
+    /// The time from the first to the latest recorded state.
+    pub fn duration(&self) -> Option<Duration> {
+        let started_at = self.started_at()?;
+        let last_updated_at = self.last_updated_at()?;
+
+        Some(
+            last_updated_at
+                .duration_since(started_at)
+                .unwrap_or_default(),
+        )
+    }
+}
+
+/// The envelope every value about a swap is stored in, so that values written
+/// with an older schema can be told apart and migrated.
+#[derive(Debug, Deserialize, Serialize)]
+struct Versioned<T> {
+    version: u32,
+    value: T,
+}
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("Database is encrypted, a passphrase is needed to open it")]
+pub struct PassphraseRequired;
+
+/// The values about swaps are encrypted if the database was given a
+/// passphrase, the swap ids and the metadata are not.
+#[derive(Clone)]
+pub struct Database {
+    db: sled::Db,
+    key: Option<Key>,
+}
 
 impl Database {
+    /// Opens an unencrypted database, fails with [`PassphraseRequired`] if
+    /// the database is encrypted.
     pub fn open(path: &Path) -> Result<Self> {
+        Self::open_with_passphrase(path, || bail!(PassphraseRequired))
+    }
+
+    /// Opens a database, the passphrase is only asked for if the database is
+    /// encrypted.
+    pub fn open_with_passphrase<F>(path: &Path, passphrase: F) -> Result<Self>
+    where
+        F: FnOnce() -> Result<String>,
+    {
         let db =
             sled::open(path).with_context(|| format!("Could not open the DB at {:?}", path))?;
 
-        Ok(Database(db))
+        migrations::migrate(&db)?;
+
+        let key = match db.open_tree(METADATA_TREE)?.get(ENCRYPTION_KEY)? {
+            Some(encoded) => {
+                let encrypted = deserialize::<Encrypted>(&encoded)
+                    .context("Could not deserialize encryption key")?;
+                let key = encrypted
+                    .decrypt(&passphrase()?)
+                    .context("Could not decrypt the database")?;
+
+                Some(Key::from_slice(&key)?)
+            }
+            None => None,
+        };
+
+        Ok(Database { db, key })
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.key.is_some()
     }
 
+    /// Encrypts the database with the given passphrase, or decrypts it if
+    /// no passphrase is given.
+    ///
+    /// Changing the passphrase of an encrypted database only re-encrypts the
+    /// key of the values. Otherwise all values are rewritten at once.
+    pub fn change_passphrase(&mut self, passphrase: Option<&str>) -> Result<()> {
+        let metadata = self.db.open_tree(METADATA_TREE)?;
+
+        let new_key = match (&self.key, passphrase) {
+            (Some(key), Some(passphrase)) => {
+                let encrypted = Encrypted::new(passphrase, key.as_bytes())?;
+                metadata.insert(ENCRYPTION_KEY, serialize(&encrypted)?)?;
+                metadata.flush()?;
+
+                return Ok(());
+            }
+            (None, None) => return Ok(()),
+            (None, Some(_)) => Some(Key::random()),
+            (Some(_), None) => None,
+        };
+        let encrypted_key = match (&new_key, passphrase) {
+            (Some(key), Some(passphrase)) => {
+                Some(serialize(&Encrypted::new(passphrase, key.as_bytes())?)?)
+            }
+            _ => None,
+        };
+
+        let swaps = (*self.db).clone();
+        let peers = self.db.open_tree(PEERS_TREE)?;
+        let history = self.db.open_tree(HISTORY_TREE)?;
+
+        let mut rewritten = Vec::new();
+        for (index, tree) in [&swaps, &peers, &history].iter().enumerate() {
+            for item in tree.iter() {
+                let (key, value) = item.context("Could not retrieve value from DB")?;
+
+                let value = decode::<serde_cbor::Value>(&value, self.key.as_ref())?;
+                rewritten.push((index, key, encode(&value, new_key.as_ref())?));
+            }
+        }
+
+        (&swaps, &peers, &history, &metadata)
+            .transaction(|(swaps, peers, history, metadata)| {
+                for (index, key, value) in &rewritten {
+                    let tree = match index {
+                        0 => swaps,
+                        1 => peers,
+                        _ => history,
+                    };
+                    tree.insert(key.clone(), value.clone())?;
+                }
+
+                match &encrypted_key {
+                    Some(encrypted_key) => {
+                        metadata.insert(ENCRYPTION_KEY, encrypted_key.clone())?;
+                    }
+                    None => {
+                        metadata.remove(ENCRYPTION_KEY)?;
+                    }
+                }
+
+                Ok(())
+            })
+            .map_err(|e: TransactionError<()>| {
+                anyhow!("Could not change the passphrase of the DB: {:?}", e)
+            })?;
+        self.db.flush()?;
+
+        self.key = new_key;
+
+        Ok(())
+    }
+
+    /// Records the environment of a newly created database, or fails if the
+    /// database was created for a different environment.
+    pub fn ensure_env(&self, env: Env) -> Result<()> {
+        let metadata = self.db.open_tree(METADATA_TREE)?;
+
+        match metadata.get(ENV_KEY)? {
+            Some(encoded) => {
+                let actual =
+                    deserialize::<Env>(&encoded).context("Could not deserialize database env")?;
+
+                if actual != env {
+                    bail!(EnvMismatch {
+                        what: "The database",
+                        expected: env,
+                        actual
+                    })
+                }
+            }
+            None => {
+                metadata.insert(ENV_KEY, serialize(&env)?)?;
+                metadata.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stores the new state of the swap and records it in the history of the
+    /// swap, either both are written or neither is.
     pub async fn insert_latest_state(&self, swap_id: Uuid, state: Swap) -> Result<()> {
         let key = serialize(&swap_id)?;
-        let new_value = serialize(&state).context("Could not serialize new state value")?;
+        let new_value =
+            encode(&state, self.key.as_ref()).context("Could not serialize new state value")?;
+
+        let entry = HistoryEntry {
+            timestamp: SystemTime::now(),
+            state: state.name().to_string(),
+        };
+        let history_key = self.history_key(swap_id)?;
+        let history_value =
+            encode(&entry, self.key.as_ref()).context("Could not serialize history entry")?;
+
+        let swaps = (*self.db).clone();
+        let history = self.db.open_tree(HISTORY_TREE)?;
+
+        (&swaps, &history)
+            .transaction(|(swaps, history)| {
+                swaps.insert(key.as_slice(), new_value.as_slice())?;
+                history.insert(history_key.as_slice(), history_value.as_slice())?;
+
+                Ok(())
+            })
+            .map_err(|e: TransactionError<()>| anyhow!("Could not write in the DB: {:?}", e))?;
 
-        let old_value = self.0.get(&key)?;
+        // TODO: see if this can be done through sled config
+        self.db
+            .flush_async()
+            .await
+            .map(|_| ())
+            .context("Could not flush db")
+    }
 
-        self.0
-            .compare_and_swap(key, old_value, Some(new_value))
-            .context("Could not write in the DB")?
-            .context("Stored swap somehow changed, aborting saving")?;
+    /// History entries are keyed by the swap id followed by an id that
+    /// increases with every entry, hence iterating over the swap id prefix
+    /// yields them in the order they were inserted.
+    fn history_key(&self, swap_id: Uuid) -> Result<Vec<u8>> {
+        let mut key = serialize(&swap_id)?;
+        key.extend_from_slice(&self.db.generate_id()?.to_be_bytes());
 
-        // TODO: see if this can be done through sled config
-        self.0
+        Ok(key)
+    }
+
+    pub fn get_history(&self, swap_id: Uuid) -> Result<SwapHistory> {
+        let history = self.db.open_tree(HISTORY_TREE)?;
+
+        let prefix = serialize(&swap_id)?;
+
+        let entries = history
+            .scan_prefix(prefix)
+            .map(|item| {
+                let (_, value) = item.context("failed to retrieve history entry from DB")?;
+                decode::<HistoryEntry>(&value, self.key.as_ref())
+                    .context("Could not deserialize history entry")
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(SwapHistory(entries))
+    }
+
+    pub async fn insert_peer(&self, swap_id: Uuid, peer: Peer) -> Result<()> {
+        let peers = self.db.open_tree(PEERS_TREE)?;
+
+        let key = serialize(&swap_id)?;
+        let value = encode(&peer, self.key.as_ref()).context("Could not serialize peer")?;
+
+        peers
+            .insert(key, value)
+            .context("Could not write in the DB")?;
+
+        peers
             .flush_async()
             .await
             .map(|_| ())
             .context("Could not flush db")
     }
 
+    /// Returns the counterparty of the swap, `None` for swaps that were
+    /// started before the counterparty was recorded.
+    pub fn get_peer(&self, swap_id: Uuid) -> Result<Option<Peer>> {
+        let peers = self.db.open_tree(PEERS_TREE)?;
+
+        let key = serialize(&swap_id)?;
+
+        peers
+            .get(&key)?
+            .map(|encoded| {
+                decode(&encoded, self.key.as_ref()).context("Could not deserialize peer")
+            })
+            .transpose()
+    }
+
     pub fn get_state(&self, swap_id: Uuid) -> Result<Swap> {
         let key = serialize(&swap_id)?;
 
         let encoded = self
-            .0
+            .db
             .get(&key)?
             .ok_or_else(|| anyhow!("Swap with id {} not found in database", swap_id))?;
 
-        let state = deserialize(&encoded).context("Could not deserialize state")?;
+        let state = decode(&encoded, self.key.as_ref()).context("Could not deserialize state")?;
         Ok(state)
     }
 
+    /// All swaps with their history, the oldest first. Swaps that were
+    /// started before the history was recorded come first.
+    pub fn all_by_start_time(&self) -> Result<Vec<(Uuid, Swap, SwapHistory)>> {
+        let mut swaps = self
+            .all()?
+            .into_iter()
+            .map(|(swap_id, state)| Ok((swap_id, state, self.get_history(swap_id)?)))
+            .collect::<Result<Vec<_>>>()?;
+        swaps.sort_by_key(|(_, _, history)| history.started_at());
+
+        Ok(swaps)
+    }
+
     pub fn all(&self) -> Result<Vec<(Uuid, Swap)>> {
-        self.0
+        self.db
             .iter()
             .map(|item| match item {
                 Ok((key, value)) => {
                     let swap_id = deserialize::<Uuid>(&key);
-                    let swap = deserialize::<Swap>(&value).context("failed to deserialize swap");
+                    let swap = decode::<Swap>(&value, self.key.as_ref())
+                        .context("failed to deserialize swap");
 
                     match (swap_id, swap) {
                         (Ok(swap_id), Ok(swap)) => Ok((swap_id, swap)),
@@ -120,6 +451,91 @@ where
     Ok(serde_cbor::from_slice(&v)?)
 }
 
+/// Serializes a value wrapped in the current schema version, encrypted if a
+/// key is given.
+fn encode<T>(t: &T, key: Option<&Key>) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    match key {
+        Some(key) => serialize(&Versioned {
+            version: SCHEMA_VERSION,
+            value: key.seal(&serialize(t)?)?,
+        }),
+        None => serialize(&Versioned {
+            version: SCHEMA_VERSION,
+            value: t,
+        }),
+    }
+}
+
+/// Deserializes a value written with the current schema version, older
+/// values are migrated when the database is opened.
+fn decode<T>(v: &[u8], key: Option<&Key>) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let versioned = deserialize::<Versioned<serde_cbor::Value>>(v)?;
+
+    if versioned.version != SCHEMA_VERSION {
+        bail!(UnexpectedSchemaVersion {
+            found: versioned.version,
+            expected: SCHEMA_VERSION
+        })
+    }
+
+    match key {
+        Some(key) => {
+            let sealed = serde_cbor::value::from_value::<Sealed>(versioned.value)?;
+            deserialize(&key.open(&sealed)?)
+        }
+        None => Ok(serde_cbor::value::from_value(versioned.value)?),
+    }
+}
+
+/// Asserts that a state is encoded to exactly the bytes of the fixture of the
+/// given name, and that the fixture decodes to the state.
+///
+/// A missing fixture fails the test. Fixtures for new variants are written
+/// from the current encoding if `UPDATE_FIXTURES` is set and have to be
+/// committed. A fixture that no longer matches must not be updated, the change
+/// requires a new schema version and migration instead.
+#[cfg(test)]
+fn assert_matches_fixture(name: &str, state: Swap) {
+    use ::bitcoin::hashes::hex::{FromHex, ToHex};
+
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/database")
+        .join(format!("{}.hex", name));
+    let encoded = encode(&state, None).unwrap();
+
+    if !path.exists() {
+        assert!(
+            std::env::var_os("UPDATE_FIXTURES").is_some(),
+            "fixture {} is missing, run the tests with UPDATE_FIXTURES=1 to write it",
+            name
+        );
+
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, format!("{}\n", encoded.to_hex())).unwrap();
+    }
+
+    let fixture = Vec::<u8>::from_hex(std::fs::read_to_string(&path).unwrap().trim()).unwrap();
+
+    assert_eq!(
+        decode::<Swap>(&fixture, None).unwrap(),
+        state,
+        "fixture {} decodes to a different state",
+        name
+    );
+    assert_eq!(
+        encoded.to_hex(),
+        fixture.to_hex(),
+        "encoding of {} changed",
+        name
+    );
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -207,4 +623,125 @@ mod tests {
         assert!(swaps.contains(&(swap_id_1, state_1)));
         assert!(swaps.contains(&(swap_id_2, state_2)));
     }
+
+    #[tokio::test]
+    async fn can_write_and_read_peer() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+
+        let swap_id = Uuid::new_v4();
+        let peer = Peer {
+            peer_id: "12D3KooWCdMKjesXMJz1SiZ7HgotrxuqhQJbP5sgBm2BwP1cqThi"
+                .parse()
+                .unwrap(),
+            address: "/ip4/127.0.0.1/tcp/9939".parse().unwrap(),
+        };
+
+        assert_eq!(db.get_peer(swap_id).unwrap(), None);
+
+        db.insert_peer(swap_id, peer.clone())
+            .await
+            .expect("Failed to save peer");
+
+        assert_eq!(db.get_peer(swap_id).unwrap(), Some(peer));
+        assert!(db.all().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn records_history_of_every_state_in_order() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+
+        let swap_id = Uuid::new_v4();
+        let other_swap_id = Uuid::new_v4();
+        let started = Swap::Bob(Bob::Started {
+            btc_amount: ::bitcoin::Amount::from_sat(100_000),
+        });
+        let aborted = Swap::Bob(Bob::Done(BobEndState::SafelyAborted));
+
+        assert_eq!(db.get_history(swap_id).unwrap(), SwapHistory::default());
+
+        db.insert_latest_state(swap_id, started.clone())
+            .await
+            .unwrap();
+        db.insert_latest_state(other_swap_id, started.clone())
+            .await
+            .unwrap();
+        db.insert_latest_state(swap_id, aborted.clone())
+            .await
+            .unwrap();
+
+        let history = db.get_history(swap_id).unwrap();
+        let states = history
+            .0
+            .iter()
+            .map(|entry| entry.state.clone())
+            .collect::<Vec<_>>();
+
+        assert_eq!(states, vec!["Started", "SafelyAborted"]);
+        assert!(history.started_at() <= history.last_updated_at());
+        assert!(history.duration().is_some());
+        assert_eq!(db.get_history(other_swap_id).unwrap().0.len(), 1);
+        assert_eq!(db.get_state(swap_id).unwrap(), aborted);
+
+        let swaps = db.all_by_start_time().unwrap();
+        assert_eq!(swaps.len(), 2);
+        assert!(swaps[0].2.started_at() <= swaps[1].2.started_at());
+    }
+
+    #[tokio::test]
+    async fn values_are_readable_with_the_passphrase_only() {
+        let db_dir = tempfile::tempdir().unwrap();
+
+        let swap_id = Uuid::new_v4();
+        let state = Swap::Bob(Bob::Done(BobEndState::SafelyAborted));
+
+        let mut db = Database::open(db_dir.path()).unwrap();
+        db.insert_latest_state(swap_id, state.clone())
+            .await
+            .unwrap();
+        db.change_passphrase(Some("correct horse")).unwrap();
+        db.insert_latest_state(swap_id, state.clone())
+            .await
+            .unwrap();
+        drop(db);
+
+        let error = Database::open(db_dir.path()).err().unwrap();
+        assert!(error.downcast_ref::<PassphraseRequired>().is_some());
+        assert!(
+            Database::open_with_passphrase(db_dir.path(), || Ok("battery staple".to_string()))
+                .is_err()
+        );
+
+        let mut db =
+            Database::open_with_passphrase(db_dir.path(), || Ok("correct horse".to_string()))
+                .unwrap();
+        assert_eq!(db.get_state(swap_id).unwrap(), state);
+        assert_eq!(db.get_history(swap_id).unwrap().0.len(), 2);
+
+        db.change_passphrase(Some("battery staple")).unwrap();
+        drop(db);
+        let mut db =
+            Database::open_with_passphrase(db_dir.path(), || Ok("battery staple".to_string()))
+                .unwrap();
+        assert_eq!(db.get_state(swap_id).unwrap(), state);
+
+        db.change_passphrase(None).unwrap();
+        drop(db);
+        let db = Database::open(db_dir.path()).unwrap();
+        assert_eq!(db.get_state(swap_id).unwrap(), state);
+        assert_eq!(db.get_history(swap_id).unwrap().0.len(), 2);
+    }
+
+    #[test]
+    fn database_of_other_env_is_rejected() {
+        let db_dir = tempfile::tempdir().unwrap();
+
+        let db = Database::open(db_dir.path()).unwrap();
+        db.ensure_env(Env::Testnet).unwrap();
+        db.ensure_env(Env::Testnet).unwrap();
+
+        let error = db.ensure_env(Env::Mainnet).unwrap_err();
+        assert!(error.downcast_ref::<EnvMismatch>().is_some());
+    }
 }
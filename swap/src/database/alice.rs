@@ -184,6 +184,7 @@ impl From<Alice> for AliceState {
                     state3.cancel_timelock,
                     state3.a.public(),
                     state3.B,
+                    state3.tx_fees.cancel,
                 );
 
                 AliceState::BtcCancelled {
@@ -201,8 +202,10 @@ impl From<Alice> for AliceState {
                     state3.cancel_timelock,
                     state3.a.public(),
                     state3.B,
+                    state3.tx_fees.cancel,
                 );
-                let tx_refund = TxRefund::new(&tx_cancel, &state3.refund_address);
+                let tx_refund =
+                    TxRefund::new(&tx_cancel, &state3.refund_address, state3.tx_fees.refund);
                 AliceState::BtcPunishable {
                     jude_wallet_restore_blockheight,
                     tx_refund: Box::new(tx_refund),
@@ -243,3 +246,100 @@ impl Display for Alice {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bitcoin::TxRedeem,
+        database::{assert_matches_fixture, serialize, Swap},
+        protocol::alice::state::fixtures,
+    };
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    const BOB_PEER_ID: &str = "12D3KooWCdMKjesXMJz1SiZ7HgotrxuqhQJbP5sgBm2BwP1cqThi";
+
+    #[test]
+    fn fixture_state_is_deterministic() {
+        assert_eq!(
+            serialize(&fixtures::state3()).unwrap(),
+            serialize(&fixtures::state3()).unwrap()
+        );
+    }
+
+    #[test]
+    fn every_variant_matches_its_fixture() {
+        let state3 = fixtures::state3();
+        let bob_peer_id = BOB_PEER_ID.parse::<PeerId>().unwrap();
+        let jude_wallet_restore_blockheight = BlockHeight { height: 1000 };
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let tx_redeem = TxRedeem::new(
+            &state3.tx_lock,
+            &state3.redeem_address,
+            state3.tx_fees.redeem,
+        );
+        let encrypted_signature = crate::bitcoin::SecretKey::new_random(&mut rng)
+            .encsign(state3.a.public(), tx_redeem.digest());
+
+        let variants = vec![
+            ("alice_started", Alice::Started {
+                state3: state3.clone(),
+                bob_peer_id,
+            }),
+            ("alice_btc_locked", Alice::BtcLocked {
+                state3: state3.clone(),
+                bob_peer_id,
+            }),
+            ("alice_jude_locked", Alice::judeLocked {
+                jude_wallet_restore_blockheight,
+                state3: state3.clone(),
+            }),
+            ("alice_enc_sig_learned", Alice::EncSigLearned {
+                jude_wallet_restore_blockheight,
+                encrypted_signature,
+                state3: state3.clone(),
+            }),
+            (
+                "alice_cancel_timelock_expired",
+                Alice::CancelTimelockExpired {
+                    jude_wallet_restore_blockheight,
+                    state3: state3.clone(),
+                },
+            ),
+            ("alice_btc_cancelled", Alice::BtcCancelled {
+                jude_wallet_restore_blockheight,
+                state3: state3.clone(),
+            }),
+            ("alice_btc_punishable", Alice::BtcPunishable {
+                jude_wallet_restore_blockheight,
+                state3: state3.clone(),
+            }),
+            ("alice_btc_refunded", Alice::BtcRefunded {
+                jude_wallet_restore_blockheight,
+                spend_key: jude::PrivateKey::from_scalar(jude::Scalar::random(&mut rng)),
+                state3,
+            }),
+            (
+                "alice_done_safely_aborted",
+                Alice::Done(AliceEndState::SafelyAborted),
+            ),
+            (
+                "alice_done_btc_redeemed",
+                Alice::Done(AliceEndState::BtcRedeemed),
+            ),
+            (
+                "alice_done_jude_refunded",
+                Alice::Done(AliceEndState::judeRefunded),
+            ),
+            (
+                "alice_done_btc_punished",
+                Alice::Done(AliceEndState::BtcPunished),
+            ),
+        ];
+
+        for (name, alice) in variants {
+            assert_matches_fixture(name, Swap::Alice(alice));
+        }
+    }
+}
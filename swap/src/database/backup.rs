@@ -0,0 +1,266 @@
+//! Portable copies of swaps, to keep the secrets needed to finish a swap safe
+//! or to move swaps to another data directory.
+
+use crate::{
+    database::{
+        deserialize, encode, serialize, Database, HistoryEntry, Peer, Swap, HISTORY_TREE,
+        PEERS_TREE,
+    },
+    encryption::Encrypted,
+    env::{Env, EnvMismatch},
+    fs::ensure_directory_exists,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sled::{transaction::TransactionError, Transactional};
+use std::{fs, fs::OpenOptions, io::Write, path::Path};
+use uuid::Uuid;
+
+/// The version backup files are written with.
+const BACKUP_VERSION: u32 = 1;
+
+/// Everything the database knows about a swap.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct SwapExport {
+    pub swap_id: Uuid,
+    pub state: Swap,
+    pub peer: Option<Peer>,
+    pub history: Vec<HistoryEntry>,
+}
+
+/// Swaps of one environment, as written to a backup file.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Backup {
+    pub env: Env,
+    pub swaps: Vec<SwapExport>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct BackupFile {
+    version: u32,
+    content: Content,
+}
+
+#[derive(Deserialize, Serialize)]
+enum Content {
+    Plain(Backup),
+    /// The serialized [`Backup`], encrypted with a passphrase.
+    Encrypted(Encrypted),
+}
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("Backup file has version {found} but this software only supports version {supported}, please upgrade")]
+pub struct UnsupportedBackupVersion {
+    pub found: u32,
+    pub supported: u32,
+}
+
+impl Backup {
+    /// Writes the backup to a new file, encrypted if a passphrase is given.
+    /// Fails if the file exists already rather than overwriting another backup.
+    pub fn write_to(&self, path: &Path, passphrase: Option<&str>) -> Result<()> {
+        let content = match passphrase {
+            Some(passphrase) => Content::Encrypted(Encrypted::new(passphrase, &serialize(self)?)?),
+            None => Content::Plain(self.clone()),
+        };
+        let encoded = serialize(&BackupFile {
+            version: BACKUP_VERSION,
+            content,
+        })?;
+
+        ensure_directory_exists(path)?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .with_context(|| format!("Could not create backup file {}", path.display()))?;
+        file.write_all(&encoded)?;
+        file.sync_all()?;
+
+        Ok(())
+    }
+
+    /// Reads a backup file, the passphrase is only asked for if the file is
+    /// encrypted.
+    pub fn read_from<F>(path: &Path, passphrase: F) -> Result<Self>
+    where
+        F: FnOnce() -> Result<String>,
+    {
+        let encoded = fs::read(path)
+            .with_context(|| format!("Could not read backup file {}", path.display()))?;
+        let file =
+            deserialize::<BackupFile>(&encoded).context("Could not deserialize backup file")?;
+
+        if file.version != BACKUP_VERSION {
+            bail!(UnsupportedBackupVersion {
+                found: file.version,
+                supported: BACKUP_VERSION
+            })
+        }
+
+        match file.content {
+            Content::Plain(backup) => Ok(backup),
+            Content::Encrypted(encrypted) => {
+                let decrypted = encrypted.decrypt(&passphrase()?)?;
+                deserialize(&decrypted).context("Could not deserialize decrypted backup")
+            }
+        }
+    }
+
+    /// Fails if the backup was taken on another environment than the one of
+    /// the database it is imported into.
+    pub fn ensure_env(&self, env: Env) -> Result<()> {
+        if self.env != env {
+            bail!(EnvMismatch {
+                what: "The backup",
+                expected: env,
+                actual: self.env
+            })
+        }
+
+        Ok(())
+    }
+}
+
+impl Database {
+    pub fn export_swap(&self, swap_id: Uuid) -> Result<SwapExport> {
+        Ok(SwapExport {
+            swap_id,
+            state: self.get_state(swap_id)?,
+            peer: self.get_peer(swap_id)?,
+            history: self.get_history(swap_id)?.0,
+        })
+    }
+
+    pub fn export_all(&self) -> Result<Vec<SwapExport>> {
+        self.all()?
+            .into_iter()
+            .map(|(swap_id, _)| self.export_swap(swap_id))
+            .collect()
+    }
+
+    /// Adds a swap together with its counterparty and history. Returns `false`
+    /// without changing anything if the database already holds the swap.
+    pub async fn import_swap(&self, swap: SwapExport) -> Result<bool> {
+        let key = serialize(&swap.swap_id)?;
+        let state = encode(&swap.state, self.key.as_ref()).context("Could not serialize state")?;
+        let peer = swap
+            .peer
+            .map(|peer| encode(&peer, self.key.as_ref()))
+            .transpose()
+            .context("Could not serialize peer")?;
+        let entries = swap
+            .history
+            .iter()
+            .map(|entry| {
+                let value = encode(entry, self.key.as_ref())
+                    .context("Could not serialize history entry")?;
+
+                Ok((self.history_key(swap.swap_id)?, value))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let swaps = (*self.db).clone();
+        let peers = self.db.open_tree(PEERS_TREE)?;
+        let history = self.db.open_tree(HISTORY_TREE)?;
+
+        let inserted = (&swaps, &peers, &history)
+            .transaction(|(swaps, peers, history)| {
+                if swaps.get(key.as_slice())?.is_some() {
+                    return Ok(false);
+                }
+
+                swaps.insert(key.as_slice(), state.as_slice())?;
+                if let Some(peer) = &peer {
+                    peers.insert(key.as_slice(), peer.as_slice())?;
+                }
+                for (history_key, value) in &entries {
+                    history.insert(history_key.as_slice(), value.as_slice())?;
+                }
+
+                Ok(true)
+            })
+            .map_err(|e: TransactionError<()>| anyhow!("Could not write in the DB: {:?}", e))?;
+
+        if inserted {
+            self.db.flush_async().await.context("Could not flush db")?;
+        }
+
+        Ok(inserted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{bob::BobEndState, Bob};
+
+    #[tokio::test]
+    async fn swaps_are_imported_into_another_database() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let source = Database::open(source_dir.path()).unwrap();
+
+        let swap_id = Uuid::new_v4();
+        let peer = Peer {
+            peer_id: "12D3KooWCdMKjesXMJz1SiZ7HgotrxuqhQJbP5sgBm2BwP1cqThi"
+                .parse()
+                .unwrap(),
+            address: "/ip4/127.0.0.1/tcp/9939".parse().unwrap(),
+        };
+        source
+            .insert_latest_state(
+                swap_id,
+                Swap::Bob(Bob::Started {
+                    btc_amount: ::bitcoin::Amount::from_sat(100_000),
+                }),
+            )
+            .await
+            .unwrap();
+        source
+            .insert_latest_state(swap_id, Swap::Bob(Bob::Done(BobEndState::SafelyAborted)))
+            .await
+            .unwrap();
+        source.insert_peer(swap_id, peer).await.unwrap();
+
+        let backup_dir = tempfile::tempdir().unwrap();
+        let backup_file = backup_dir.path().join("swaps.backup");
+        let backup = Backup {
+            env: Env::Testnet,
+            swaps: source.export_all().unwrap(),
+        };
+        backup
+            .write_to(&backup_file, Some("correct horse"))
+            .unwrap();
+
+        let target_dir = tempfile::tempdir().unwrap();
+        let target = Database::open(target_dir.path()).unwrap();
+
+        let read = Backup::read_from(&backup_file, || Ok("correct horse".to_string())).unwrap();
+        read.ensure_env(Env::Testnet).unwrap();
+
+        for swap in read.swaps {
+            assert!(target.import_swap(swap.clone()).await.unwrap());
+            assert!(!target.import_swap(swap).await.unwrap());
+        }
+
+        assert_eq!(target.export_swap(swap_id).unwrap(), backup.swaps[0]);
+        assert_eq!(target.get_history(swap_id).unwrap().0.len(), 2);
+    }
+
+    #[test]
+    fn plain_backup_is_read_without_passphrase() {
+        let backup_dir = tempfile::tempdir().unwrap();
+        let backup_file = backup_dir.path().join("swaps.backup");
+        let backup = Backup {
+            env: Env::Mainnet,
+            swaps: vec![],
+        };
+
+        backup.write_to(&backup_file, None).unwrap();
+        let read = Backup::read_from(&backup_file, || panic!("passphrase asked for")).unwrap();
+
+        assert_eq!(read, backup);
+        assert!(read.ensure_env(Env::Testnet).is_err());
+        assert!(backup.write_to(&backup_file, None).is_err());
+    }
+}
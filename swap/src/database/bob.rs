@@ -123,3 +123,92 @@ impl Display for Bob {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::{assert_matches_fixture, serialize, Swap},
+        jude::TxHash,
+        protocol::bob::state::fixtures,
+    };
+
+    #[test]
+    fn fixture_states_are_deterministic() {
+        assert_eq!(
+            serialize(&fixtures::state2()).unwrap(),
+            serialize(&fixtures::state2()).unwrap()
+        );
+        assert_eq!(
+            serialize(&fixtures::state3()).unwrap(),
+            serialize(&fixtures::state3()).unwrap()
+        );
+        assert_eq!(
+            serialize(&fixtures::state4()).unwrap(),
+            serialize(&fixtures::state4()).unwrap()
+        );
+        assert_eq!(
+            serialize(&fixtures::state5()).unwrap(),
+            serialize(&fixtures::state5()).unwrap()
+        );
+    }
+
+    #[test]
+    fn every_variant_matches_its_fixture() {
+        let state4 = fixtures::state4();
+        let tx_lock_id = state4.tx_lock_id();
+        let lock_transfer_proof = TransferProof::new(
+            TxHash("0".repeat(64)),
+            crate::jude::PrivateKey::from_scalar(crate::jude::Scalar::one()),
+        );
+
+        let variants = vec![
+            ("bob_started", Bob::Started {
+                btc_amount: bitcoin::Amount::from_sat(100_000),
+            }),
+            ("bob_execution_setup_done", Bob::ExecutionSetupDone {
+                state2: fixtures::state2(),
+            }),
+            ("bob_btc_locked", Bob::BtcLocked {
+                state3: fixtures::state3(),
+            }),
+            ("bob_jude_lock_proof_received", Bob::judeLockProofReceived {
+                state: fixtures::state3(),
+                lock_transfer_proof,
+                jude_wallet_restore_blockheight: BlockHeight { height: 1000 },
+            }),
+            ("bob_jude_locked", Bob::judeLocked {
+                state4: state4.clone(),
+            }),
+            ("bob_enc_sig_sent", Bob::EncSigSent {
+                state4: state4.clone(),
+            }),
+            ("bob_btc_redeemed", Bob::BtcRedeemed(fixtures::state5())),
+            (
+                "bob_cancel_timelock_expired",
+                Bob::CancelTimelockExpired(state4.clone()),
+            ),
+            ("bob_btc_cancelled", Bob::BtcCancelled(state4.clone())),
+            (
+                "bob_done_safely_aborted",
+                Bob::Done(BobEndState::SafelyAborted),
+            ),
+            (
+                "bob_done_jude_redeemed",
+                Bob::Done(BobEndState::judeRedeemed { tx_lock_id }),
+            ),
+            (
+                "bob_done_btc_refunded",
+                Bob::Done(BobEndState::BtcRefunded(Box::new(state4))),
+            ),
+            (
+                "bob_done_btc_punished",
+                Bob::Done(BobEndState::BtcPunished { tx_lock_id }),
+            ),
+        ];
+
+        for (name, bob) in variants {
+            assert_matches_fixture(name, Swap::Bob(bob));
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! Upgrades databases written by older versions of the software.
+//!
+//! Every migration takes the database from one schema version to the next.
+//! To change how values are stored, bump [`SCHEMA_VERSION`] and append a
+//! migration rewriting the values of the previous version to [`MIGRATIONS`].
+
+use crate::database::{deserialize, serialize, Versioned, HISTORY_TREE, METADATA_TREE, PEERS_TREE};
+use anyhow::{anyhow, bail, Context, Result};
+use sled::{transaction::TransactionError, IVec, Transactional};
+
+/// The schema version values are written with.
+pub const SCHEMA_VERSION: u32 = 1;
+
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// `MIGRATIONS[n]` upgrades a database from schema version `n` to `n + 1`.
+///
+/// A migration only reads the database and returns the values to rewrite,
+/// they are written together with the new schema version in one transaction
+/// so that an interrupted migration is simply run again.
+const MIGRATIONS: &[fn(&sled::Db) -> Result<Vec<Rewrite>>] = &[wrap_values_in_version];
+
+/// A value to replace, in the swaps (0), peers (1) or history (2) tree.
+type Rewrite = (usize, IVec, Vec<u8>);
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("Database has schema version {found} but this software only supports up to version {supported}, please upgrade")]
+pub struct SchemaTooNew {
+    pub found: u32,
+    pub supported: u32,
+}
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("Value has schema version {found} but version {expected} was expected")]
+pub struct UnexpectedSchemaVersion {
+    pub found: u32,
+    pub expected: u32,
+}
+
+/// Runs the migrations needed to bring the database to [`SCHEMA_VERSION`].
+pub fn migrate(db: &sled::Db) -> Result<()> {
+    let metadata = db.open_tree(METADATA_TREE)?;
+
+    let version = match metadata.get(SCHEMA_VERSION_KEY)? {
+        Some(encoded) => {
+            deserialize::<u32>(&encoded).context("Could not deserialize schema version")?
+        }
+        None if holds_swaps(db)? => 0,
+        None => SCHEMA_VERSION,
+    };
+
+    if version > SCHEMA_VERSION {
+        bail!(SchemaTooNew {
+            found: version,
+            supported: SCHEMA_VERSION
+        })
+    }
+
+    let swaps = (**db).clone();
+    let peers = db.open_tree(PEERS_TREE)?;
+    let history = db.open_tree(HISTORY_TREE)?;
+
+    for from in version..SCHEMA_VERSION {
+        let to = from + 1;
+        tracing::info!("Migrating database from schema version {} to {}", from, to);
+
+        let rewritten = MIGRATIONS[from as usize](db).with_context(|| {
+            format!(
+                "Could not migrate database from schema version {} to {}",
+                from, to
+            )
+        })?;
+        let encoded_version = serialize(&to)?;
+
+        (&swaps, &peers, &history, &metadata)
+            .transaction(|(swaps, peers, history, metadata)| {
+                for (index, key, value) in &rewritten {
+                    let tree = match index {
+                        0 => swaps,
+                        1 => peers,
+                        _ => history,
+                    };
+                    tree.insert(key.clone(), value.clone())?;
+                }
+                metadata.insert(SCHEMA_VERSION_KEY, encoded_version.clone())?;
+
+                Ok(())
+            })
+            .map_err(|e: TransactionError<()>| {
+                anyhow!(
+                    "Could not write the database migrated to schema version {}: {:?}",
+                    to,
+                    e
+                )
+            })?;
+    }
+
+    metadata.insert(SCHEMA_VERSION_KEY, serialize(&SCHEMA_VERSION)?)?;
+    db.flush()?;
+
+    Ok(())
+}
+
+/// Databases created before the schema was versioned do not have a version
+/// key. An empty one is simply created with the latest schema.
+fn holds_swaps(db: &sled::Db) -> Result<bool> {
+    let peers = db.open_tree(PEERS_TREE)?;
+    let history = db.open_tree(HISTORY_TREE)?;
+
+    Ok(!db.is_empty() || !peers.is_empty() || !history.is_empty())
+}
+
+/// Version 0 stored the latest states, peers and history entries as is.
+/// Version 1 wraps each of them in [`Versioned`].
+fn wrap_values_in_version(db: &sled::Db) -> Result<Vec<Rewrite>> {
+    let trees = vec![
+        (**db).clone(),
+        db.open_tree(PEERS_TREE)?,
+        db.open_tree(HISTORY_TREE)?,
+    ];
+
+    let mut rewritten = Vec::new();
+    for (index, tree) in trees.iter().enumerate() {
+        for item in tree.iter() {
+            let (key, value) = item.context("Could not retrieve value from DB")?;
+
+            let value = deserialize::<serde_cbor::Value>(&value)
+                .context("Could not deserialize unversioned value")?;
+            let versioned = serialize(&Versioned { version: 1, value })?;
+
+            rewritten.push((index, key, versioned));
+        }
+    }
+
+    Ok(rewritten)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{
+        alice::{Alice, AliceEndState},
+        bob::{Bob, BobEndState},
+        Database, HistoryEntry, Peer, Swap,
+    };
+    use std::time::SystemTime;
+    use uuid::Uuid;
+
+    #[test]
+    fn there_is_a_migration_to_every_schema_version() {
+        assert_eq!(MIGRATIONS.len(), SCHEMA_VERSION as usize);
+    }
+
+    #[test]
+    fn unversioned_database_is_migrated_on_open() {
+        let db_dir = tempfile::tempdir().unwrap();
+
+        let swap_id = Uuid::new_v4();
+        let state = Swap::Bob(Bob::Done(BobEndState::SafelyAborted));
+        let peer = Peer {
+            peer_id: "12D3KooWCdMKjesXMJz1SiZ7HgotrxuqhQJbP5sgBm2BwP1cqThi"
+                .parse()
+                .unwrap(),
+            address: "/ip4/127.0.0.1/tcp/9939".parse().unwrap(),
+        };
+        let entry = HistoryEntry {
+            timestamp: SystemTime::now(),
+            state: state.name().to_string(),
+        };
+
+        {
+            let db = sled::open(db_dir.path()).unwrap();
+            let key = serialize(&swap_id).unwrap();
+            let mut history_key = key.clone();
+            history_key.extend_from_slice(&0u64.to_be_bytes());
+
+            db.insert(&key, serialize(&state).unwrap()).unwrap();
+            db.open_tree(PEERS_TREE)
+                .unwrap()
+                .insert(&key, serialize(&peer).unwrap())
+                .unwrap();
+            db.open_tree(HISTORY_TREE)
+                .unwrap()
+                .insert(history_key, serialize(&entry).unwrap())
+                .unwrap();
+            db.flush().unwrap();
+        }
+
+        let db = Database::open(db_dir.path()).unwrap();
+
+        assert_eq!(db.get_state(swap_id).unwrap(), state);
+        assert_eq!(db.all().unwrap(), vec![(swap_id, state)]);
+        assert_eq!(db.get_peer(swap_id).unwrap(), Some(peer));
+        assert_eq!(db.get_history(swap_id).unwrap().0, vec![entry]);
+    }
+
+    #[tokio::test]
+    async fn reopening_does_not_migrate_again() {
+        let db_dir = tempfile::tempdir().unwrap();
+
+        let swap_id = Uuid::new_v4();
+        let state = Swap::Alice(Alice::Done(AliceEndState::BtcRedeemed));
+
+        let db = Database::open(db_dir.path()).unwrap();
+        db.insert_latest_state(swap_id, state.clone())
+            .await
+            .unwrap();
+        drop(db);
+
+        let db = Database::open(db_dir.path()).unwrap();
+
+        assert_eq!(db.get_state(swap_id).unwrap(), state);
+    }
+
+    #[test]
+    fn failed_migration_leaves_the_database_untouched() {
+        let db_dir = tempfile::tempdir().unwrap();
+
+        let swap_id = Uuid::new_v4();
+        let state = Swap::Bob(Bob::Done(BobEndState::SafelyAborted));
+        let key = serialize(&swap_id).unwrap();
+
+        {
+            let db = sled::open(db_dir.path()).unwrap();
+            db.insert(&key, serialize(&state).unwrap()).unwrap();
+            // Not CBOR, the migration fails once it reaches the history
+            db.open_tree(HISTORY_TREE)
+                .unwrap()
+                .insert(&key, vec![0xff])
+                .unwrap();
+            db.flush().unwrap();
+        }
+
+        assert!(Database::open(db_dir.path()).is_err());
+
+        let db = sled::open(db_dir.path()).unwrap();
+        assert_eq!(
+            deserialize::<Swap>(&db.get(&key).unwrap().unwrap()).unwrap(),
+            state
+        );
+        assert!(db
+            .open_tree(METADATA_TREE)
+            .unwrap()
+            .get(SCHEMA_VERSION_KEY)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn database_with_newer_schema_is_rejected() {
+        let db_dir = tempfile::tempdir().unwrap();
+
+        {
+            let db = sled::open(db_dir.path()).unwrap();
+            db.open_tree(METADATA_TREE)
+                .unwrap()
+                .insert(
+                    SCHEMA_VERSION_KEY,
+                    serialize(&(SCHEMA_VERSION + 1)).unwrap(),
+                )
+                .unwrap();
+            db.flush().unwrap();
+        }
+
+        let error = Database::open(db_dir.path()).err().unwrap();
+
+        assert!(error.downcast_ref::<SchemaTooNew>().is_some());
+    }
+}
@@ -0,0 +1,257 @@
+//! Passphrase based encryption of data at rest.
+//!
+//! A key is derived from the passphrase with Argon2id and a random salt, the
+//! data is then sealed with ChaCha20-Poly1305 under a random nonce.
+
+use anyhow::{anyhow, bail, Context, Result};
+use chacha20poly1305::{
+    aead::{generic_array::GenericArray, Aead, NewAead},
+    ChaCha20Poly1305,
+};
+use dialoguer::{theme::ColorfulTheme, Password};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Environment variable the passphrase is read from instead of asking for it.
+pub const PASSPHRASE_ENV_VAR: &str = "SWAP_PASSPHRASE";
+
+/// Environment variable a new passphrase is read from instead of asking for
+/// it, an empty one removes the encryption.
+pub const NEW_PASSPHRASE_ENV_VAR: &str = "SWAP_NEW_PASSPHRASE";
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const KEY_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("Could not decrypt, the passphrase is wrong or the data is corrupted")]
+pub struct DecryptionFailed;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Salt([u8; SALT_LENGTH]);
+
+impl Salt {
+    pub fn random() -> Self {
+        let mut bytes = [0u8; SALT_LENGTH];
+        rand::thread_rng().fill_bytes(&mut bytes);
+
+        Self(bytes)
+    }
+}
+
+/// A symmetric key, either random or derived from a passphrase.
+#[derive(Clone)]
+pub struct Key([u8; KEY_LENGTH]);
+
+impl Key {
+    pub fn random() -> Self {
+        let mut bytes = [0u8; KEY_LENGTH];
+        rand::thread_rng().fill_bytes(&mut bytes);
+
+        Self(bytes)
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != KEY_LENGTH {
+            bail!(
+                "Expected a key of {} bytes, got {}",
+                KEY_LENGTH,
+                bytes.len()
+            )
+        }
+
+        let mut key = [0u8; KEY_LENGTH];
+        key.copy_from_slice(bytes);
+
+        Ok(Self(key))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn derive(passphrase: &str, salt: &Salt) -> Result<Self> {
+        let config = argon2::Config {
+            variant: argon2::Variant::Argon2id,
+            mem_cost: 65536,
+            time_cost: 3,
+            hash_length: 32,
+            ..argon2::Config::default()
+        };
+
+        let hash = argon2::hash_raw(passphrase.as_bytes(), &salt.0, &config)
+            .map_err(|e| anyhow!("Could not derive key from passphrase: {}", e))?;
+
+        Self::from_slice(&hash)
+    }
+
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Sealed> {
+        let mut nonce = [0u8; NONCE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let ciphertext = self
+            .cipher()
+            .encrypt(GenericArray::from_slice(&nonce), plaintext)
+            .map_err(|_| anyhow!("Could not encrypt"))?;
+
+        Ok(Sealed { nonce, ciphertext })
+    }
+
+    pub fn open(&self, sealed: &Sealed) -> Result<Vec<u8>, DecryptionFailed> {
+        self.cipher()
+            .decrypt(
+                GenericArray::from_slice(&sealed.nonce),
+                sealed.ciphertext.as_slice(),
+            )
+            .map_err(|_| DecryptionFailed)
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(GenericArray::from_slice(&self.0))
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Key([*****])")
+    }
+}
+
+/// Data encrypted under a [`Key`].
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Sealed {
+    nonce: [u8; NONCE_LENGTH],
+    ciphertext: Vec<u8>,
+}
+
+/// Data encrypted under a key derived from a passphrase, together with the
+/// salt needed to derive the key again.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Encrypted {
+    salt: Salt,
+    sealed: Sealed,
+}
+
+impl Encrypted {
+    pub fn new(passphrase: &str, plaintext: &[u8]) -> Result<Self> {
+        let salt = Salt::random();
+        let sealed = Key::derive(passphrase, &salt)?.seal(plaintext)?;
+
+        Ok(Self { salt, sealed })
+    }
+
+    pub fn decrypt(&self, passphrase: &str) -> Result<Vec<u8>> {
+        let plaintext = Key::derive(passphrase, &self.salt)?.open(&self.sealed)?;
+
+        Ok(plaintext)
+    }
+}
+
+/// The passphrase protecting the seed and the database, asked for at most
+/// once and only if something is actually encrypted.
+#[derive(Debug, Default)]
+pub struct PassphraseSource {
+    passphrase: Option<String>,
+}
+
+impl PassphraseSource {
+    /// Reads the passphrase from [`PASSPHRASE_ENV_VAR`] or asks for it.
+    pub fn get(&mut self) -> Result<String> {
+        if let Some(passphrase) = &self.passphrase {
+            return Ok(passphrase.clone());
+        }
+
+        let passphrase = match std::env::var(PASSPHRASE_ENV_VAR) {
+            Ok(passphrase) => passphrase,
+            Err(_) => {
+                prompt_passphrase().with_context(|| format!("{} is not set", PASSPHRASE_ENV_VAR))?
+            }
+        };
+        self.passphrase = Some(passphrase.clone());
+
+        Ok(passphrase)
+    }
+}
+
+/// Reads a new passphrase from [`NEW_PASSPHRASE_ENV_VAR`] or asks for it
+/// twice. Returns `None` if the passphrase is empty.
+pub fn new_passphrase() -> Result<Option<String>> {
+    let passphrase = match std::env::var(NEW_PASSPHRASE_ENV_VAR) {
+        Ok(passphrase) => passphrase,
+        Err(_) => {
+            ensure_terminal().with_context(|| format!("{} is not set", NEW_PASSPHRASE_ENV_VAR))?;
+
+            Password::with_theme(&ColorfulTheme::default())
+                .with_prompt("New passphrase (leave empty to disable encryption)")
+                .with_confirmation("Repeat passphrase", "The passphrases do not match")
+                .allow_empty_password(true)
+                .interact()?
+        }
+    };
+
+    if passphrase.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(passphrase))
+}
+
+pub fn prompt_passphrase() -> Result<String> {
+    ensure_terminal()?;
+
+    let passphrase = Password::with_theme(&ColorfulTheme::default())
+        .with_prompt("Passphrase")
+        .interact()?;
+
+    Ok(passphrase)
+}
+
+pub fn prompt_new_passphrase() -> Result<String> {
+    ensure_terminal()?;
+
+    let passphrase = Password::with_theme(&ColorfulTheme::default())
+        .with_prompt("Passphrase")
+        .with_confirmation("Repeat passphrase", "The passphrases do not match")
+        .interact()?;
+
+    Ok(passphrase)
+}
+
+fn ensure_terminal() -> Result<()> {
+    if !atty::is(atty::Stream::Stdin) {
+        bail!("Cannot ask for a passphrase without a terminal")
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decrypts_with_the_same_passphrase() {
+        let encrypted = Encrypted::new("correct horse", b"secret").unwrap();
+
+        assert_eq!(encrypted.decrypt("correct horse").unwrap(), b"secret");
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let encrypted = Encrypted::new("correct horse", b"secret").unwrap();
+
+        let error = encrypted.decrypt("battery staple").unwrap_err();
+
+        assert!(error.downcast_ref::<DecryptionFailed>().is_some());
+    }
+
+    #[test]
+    fn tampered_ciphertext_is_rejected() {
+        let key = Key::derive("correct horse", &Salt::random()).unwrap();
+        let mut sealed = key.seal(b"secret").unwrap();
+        sealed.ciphertext[0] ^= 1;
+
+        assert!(key.open(&sealed).is_err());
+    }
+}
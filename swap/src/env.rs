@@ -0,0 +1,275 @@
+//! The environment a swap is executed in.
+//!
+//! Picking an environment decides on the Bitcoin and jude networks, the
+//! execution parameters and the default endpoints both binaries connect to.
+//! Each environment also gets its own sub-directory in the data directory so
+//! that seeds, databases and wallets of different networks are never mixed.
+use crate::{
+    bitcoin,
+    execution_params::{self, ExecutionParams, GetExecutionParams},
+    jude,
+};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use url::Url;
+
+const MAINNET_ELECTRUM_HTTP_URL: &str = "https://blockstream.info/api/";
+const MAINNET_ELECTRUM_RPC_URL: &str = "ssl://electrum.blockstream.info:50002";
+const MAINNET_jude_WALLET_RPC_URL: &str = "http://127.0.0.1:18083/json_rpc";
+const MAINNET_jude_DAEMON_HOST: &str = "node.community.jude.to";
+
+const TESTNET_ELECTRUM_HTTP_URL: &str = "https://blockstream.info/testnet/api/";
+const TESTNET_ELECTRUM_RPC_URL: &str = "ssl://electrum.blockstream.info:60002";
+const TESTNET_jude_WALLET_RPC_URL: &str = "http://127.0.0.1:38083/json_rpc";
+const TESTNET_jude_DAEMON_HOST: &str = "stagenet.community.jude.to";
+
+const REGTEST_ELECTRUM_HTTP_URL: &str = "http://127.0.0.1:3002/";
+const REGTEST_ELECTRUM_RPC_URL: &str = "tcp://127.0.0.1:60401";
+const REGTEST_jude_WALLET_RPC_URL: &str = "http://127.0.0.1:18083/json_rpc";
+const REGTEST_jude_DAEMON_HOST: &str = "127.0.0.1:18081";
+
+/// What versions before the environment selection stored directly in the data
+/// directory. These versions only ran on testnet.
+const LEGACY_DATA_ENTRIES: &[&str] = &["seed.pem", "database", "wallet", "jude"];
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    strum::Display,
+    strum::EnumString,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Env {
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Env::Testnet
+    }
+}
+
+impl Env {
+    pub fn bitcoin_network(&self) -> bitcoin::Network {
+        match self {
+            Env::Mainnet => bitcoin::Network::Bitcoin,
+            Env::Testnet => bitcoin::Network::Testnet,
+            Env::Regtest => bitcoin::Network::Regtest,
+        }
+    }
+
+    /// jude has no regtest network, a jude daemon started with `--regtest`
+    /// uses mainnet addresses.
+    pub fn jude_network(&self) -> jude::Network {
+        match self {
+            Env::Mainnet => jude::Network::Mainnet,
+            Env::Testnet => jude::Network::Stagenet,
+            Env::Regtest => jude::Network::Mainnet,
+        }
+    }
+
+    pub fn execution_params(&self) -> ExecutionParams {
+        match self {
+            Env::Mainnet => execution_params::Mainnet::get_execution_params(),
+            Env::Testnet => execution_params::Testnet::get_execution_params(),
+            Env::Regtest => execution_params::Regtest::get_execution_params(),
+        }
+    }
+
+    pub fn default_electrum_http_url(&self) -> Url {
+        let url = match self {
+            Env::Mainnet => MAINNET_ELECTRUM_HTTP_URL,
+            Env::Testnet => TESTNET_ELECTRUM_HTTP_URL,
+            Env::Regtest => REGTEST_ELECTRUM_HTTP_URL,
+        };
+
+        url.parse()
+            .expect("default electrum http str is a valid url")
+    }
+
+    pub fn default_electrum_rpc_url(&self) -> Url {
+        let url = match self {
+            Env::Mainnet => MAINNET_ELECTRUM_RPC_URL,
+            Env::Testnet => TESTNET_ELECTRUM_RPC_URL,
+            Env::Regtest => REGTEST_ELECTRUM_RPC_URL,
+        };
+
+        url.parse()
+            .expect("default electrum rpc str is a valid url")
+    }
+
+    pub fn default_jude_wallet_rpc_url(&self) -> Url {
+        let url = match self {
+            Env::Mainnet => MAINNET_jude_WALLET_RPC_URL,
+            Env::Testnet => TESTNET_jude_WALLET_RPC_URL,
+            Env::Regtest => REGTEST_jude_WALLET_RPC_URL,
+        };
+
+        url.parse()
+            .expect("default jude wallet rpc str is a valid url")
+    }
+
+    pub fn default_jude_daemon_host(&self) -> &'static str {
+        match self {
+            Env::Mainnet => MAINNET_jude_DAEMON_HOST,
+            Env::Testnet => TESTNET_jude_DAEMON_HOST,
+            Env::Regtest => REGTEST_jude_DAEMON_HOST,
+        }
+    }
+
+    /// The directory in which the seed, database and wallets of this
+    /// environment are stored.
+    pub fn data_dir(&self, base_data_dir: &Path) -> PathBuf {
+        base_data_dir.join(self.to_string())
+    }
+
+    /// Returns the configured environment unless a different one was passed
+    /// on the command line, in which case we refuse to continue.
+    pub fn ensure_matches(self, requested: Option<Env>) -> Result<Env> {
+        match requested {
+            Some(requested) if requested != self => bail!(EnvMismatch {
+                what: "The configuration",
+                expected: requested,
+                actual: self
+            }),
+            _ => Ok(self),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("{what} is for {actual} and cannot be used on {expected}")]
+pub struct EnvMismatch {
+    pub what: &'static str,
+    pub expected: Env,
+    pub actual: Env,
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Found {legacy} of a previous version, which belongs to testnet, but {existing} exists already. Move one of them out of the way to continue")]
+pub struct LegacyDataConflict {
+    pub legacy: PathBuf,
+    pub existing: PathBuf,
+}
+
+/// Moves the data of versions before the environment selection from the data
+/// directory to the directory of testnet, the only network these versions
+/// ran on. Nothing is moved if any of it conflicts with data of the testnet
+/// directory.
+pub fn migrate_legacy_data_dir(base_data_dir: &Path) -> Result<()> {
+    let testnet_data_dir = Env::Testnet.data_dir(base_data_dir);
+
+    let moves = LEGACY_DATA_ENTRIES
+        .iter()
+        .map(|entry| (base_data_dir.join(entry), testnet_data_dir.join(entry)))
+        .filter(|(legacy, _)| legacy.exists())
+        .collect::<Vec<_>>();
+
+    if let Some((legacy, existing)) = moves.iter().find(|(_, target)| target.exists()) {
+        bail!(LegacyDataConflict {
+            legacy: legacy.clone(),
+            existing: existing.clone()
+        })
+    }
+    if moves.is_empty() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(&testnet_data_dir)
+        .with_context(|| format!("Could not create {}", testnet_data_dir.display()))?;
+    for (legacy, target) in moves {
+        fs::rename(&legacy, &target).with_context(|| {
+            format!(
+                "Could not move {} to {}",
+                legacy.display(),
+                target.display()
+            )
+        })?;
+
+        tracing::info!("Moved {} to {}", legacy.display(), target.display());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn env_roundtrips_through_str() {
+        for env in &[Env::Mainnet, Env::Testnet, Env::Regtest] {
+            assert_eq!(Env::from_str(&env.to_string()).unwrap(), *env);
+        }
+    }
+
+    #[test]
+    fn every_env_has_its_own_data_dir() {
+        let base = Path::new("/data");
+
+        assert_eq!(Env::Mainnet.data_dir(base), Path::new("/data/mainnet"));
+        assert_eq!(Env::Testnet.data_dir(base), Path::new("/data/testnet"));
+        assert_eq!(Env::Regtest.data_dir(base), Path::new("/data/regtest"));
+    }
+
+    #[test]
+    fn given_different_requested_env_then_mismatch() {
+        assert!(Env::Testnet.ensure_matches(None).is_ok());
+        assert!(Env::Testnet.ensure_matches(Some(Env::Testnet)).is_ok());
+        assert!(Env::Testnet.ensure_matches(Some(Env::Mainnet)).is_err());
+    }
+
+    #[test]
+    fn legacy_data_is_moved_to_testnet() {
+        let base = tempfile::tempdir().unwrap();
+        fs::write(base.path().join("seed.pem"), "seed").unwrap();
+        fs::create_dir(base.path().join("database")).unwrap();
+
+        migrate_legacy_data_dir(base.path()).unwrap();
+
+        let testnet = Env::Testnet.data_dir(base.path());
+        assert!(!base.path().join("seed.pem").exists());
+        assert!(!base.path().join("database").exists());
+        assert_eq!(
+            fs::read_to_string(testnet.join("seed.pem")).unwrap(),
+            "seed"
+        );
+        assert!(testnet.join("database").is_dir());
+
+        // Nothing left to migrate
+        migrate_legacy_data_dir(base.path()).unwrap();
+    }
+
+    #[test]
+    fn given_testnet_data_then_legacy_data_is_not_moved() {
+        let base = tempfile::tempdir().unwrap();
+        let testnet = Env::Testnet.data_dir(base.path());
+        fs::create_dir_all(&testnet).unwrap();
+        fs::write(base.path().join("seed.pem"), "legacy").unwrap();
+        fs::create_dir(base.path().join("database")).unwrap();
+        fs::write(testnet.join("seed.pem"), "testnet").unwrap();
+
+        let error = migrate_legacy_data_dir(base.path()).unwrap_err();
+
+        assert!(error.downcast_ref::<LegacyDataConflict>().is_some());
+        assert!(base.path().join("database").exists());
+        assert_eq!(
+            fs::read_to_string(testnet.join("seed.pem")).unwrap(),
+            "testnet"
+        );
+    }
+}
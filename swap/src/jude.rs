@@ -3,7 +3,7 @@ mod wallet_rpc;
 
 pub use ::jude::{Network, PrivateKey, PublicKey};
 pub use curve25519_dalek::scalar::Scalar;
-pub use wallet::Wallet;
+pub use wallet::{Balance, Wallet};
 pub use wallet_rpc::{WalletRpc, WalletRpcProcess};
 
 use crate::bitcoin;
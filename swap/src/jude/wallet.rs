@@ -17,6 +17,12 @@ use tokio::sync::Mutex;
 use tracing::info;
 use url::Url;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Balance {
+    pub unlocked: Amount,
+    pub locked: Amount,
+}
+
 #[derive(Debug)]
 pub struct Wallet {
     inner: Mutex<wallet::Client>,
@@ -47,9 +53,20 @@ impl Wallet {
 
     /// Get the balance of the primary account.
     pub async fn get_balance(&self) -> Result<Amount> {
-        let amount = self.inner.lock().await.get_balance(0).await?;
+        let balance = self.inner.lock().await.get_balance(0).await?;
+
+        Ok(Amount::from_piconero(balance.balance))
+    }
+
+    /// Get the balance of the primary account split into the part that can
+    /// be spent and the part that is still locked.
+    pub async fn get_detailed_balance(&self) -> Result<Balance> {
+        let balance = self.inner.lock().await.get_balance(0).await?;
 
-        Ok(Amount::from_piconero(amount))
+        Ok(Balance {
+            unlocked: Amount::from_piconero(balance.unlocked_balance),
+            locked: Amount::from_piconero(balance.balance.saturating_sub(balance.unlocked_balance)),
+        })
     }
 
     pub async fn block_height(&self) -> Result<BlockHeight> {
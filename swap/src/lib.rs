@@ -20,13 +20,17 @@ pub mod asb;
 pub mod bitcoin;
 pub mod cli;
 pub mod database;
+pub mod encryption;
+pub mod env;
 pub mod execution_params;
 pub mod fs;
 pub mod jude;
+pub mod output;
+pub mod profile;
 pub mod protocol;
 pub mod seed;
 pub mod trace;
 
 mod jude_ext;
-mod network;
+pub mod network;
 mod serde_peer_id;
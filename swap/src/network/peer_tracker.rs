@@ -15,15 +15,16 @@ use std::{
 #[derive(Debug, Copy, Clone)]
 pub enum OutEvent {
     ConnectionEstablished(PeerId),
+    /// All connections to the peer are closed.
+    Disconnected(PeerId),
 }
 
-/// A NetworkBehaviour that tracks connections to the counterparty. Although the
-/// libp2p `NetworkBehaviour` abstraction encompasses connections to multiple
-/// peers we only ever connect to a single counterparty. Peer Tracker tracks
-/// that connection.
+/// A NetworkBehaviour that tracks connections to our counterparties. Bob can
+/// swap with several makers at the same time, hence the connection to each of
+/// them is tracked.
 #[derive(Default, Debug)]
 pub struct PeerTracker {
-    connected: Option<(PeerId, Multiaddr)>,
+    connected: HashMap<PeerId, Multiaddr>,
     address_of_peer: HashMap<PeerId, Multiaddr>,
     events: VecDeque<OutEvent>,
 }
@@ -31,28 +32,7 @@ pub struct PeerTracker {
 impl PeerTracker {
     /// Return whether we are connected to the given peer.
     pub fn is_connected(&self, peer_id: &PeerId) -> bool {
-        if let Some((connected_peer_id, _)) = &self.connected {
-            if connected_peer_id == peer_id {
-                return true;
-            }
-        }
-        false
-    }
-
-    /// Returns the peer id of counterparty if we are connected.
-    pub fn counterparty_peer_id(&self) -> Option<PeerId> {
-        if let Some((id, _)) = &self.connected {
-            return Some(*id);
-        }
-        None
-    }
-
-    /// Returns the peer_id and multiaddr of counterparty if we are connected.
-    pub fn counterparty(&self) -> Option<(PeerId, Multiaddr)> {
-        if let Some((peer_id, addr)) = &self.connected {
-            return Some((*peer_id, addr.clone()));
-        }
-        None
+        self.connected.contains_key(peer_id)
     }
 
     /// Add an address for a given peer. We only store one address per peer.
@@ -72,10 +52,8 @@ impl NetworkBehaviour for PeerTracker {
     fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
         let mut addresses: Vec<Multiaddr> = vec![];
 
-        if let Some((counterparty_peer_id, addr)) = self.counterparty() {
-            if counterparty_peer_id == *peer_id {
-                addresses.push(addr)
-            }
+        if let Some(addr) = self.connected.get(peer_id) {
+            addresses.push(addr.clone());
         }
 
         if let Some(addr) = self.address_of_peer.get(peer_id) {
@@ -87,7 +65,11 @@ impl NetworkBehaviour for PeerTracker {
 
     fn inject_connected(&mut self, _: &PeerId) {}
 
-    fn inject_disconnected(&mut self, _: &PeerId) {}
+    fn inject_disconnected(&mut self, peer: &PeerId) {
+        self.connected.remove(peer);
+
+        self.events.push_back(OutEvent::Disconnected(*peer));
+    }
 
     fn inject_connection_established(
         &mut self,
@@ -97,13 +79,13 @@ impl NetworkBehaviour for PeerTracker {
     ) {
         match point {
             ConnectedPoint::Dialer { address } => {
-                self.connected = Some((*peer, address.clone()));
+                self.connected.insert(*peer, address.clone());
             }
             ConnectedPoint::Listener {
                 local_addr: _,
                 send_back_addr,
             } => {
-                self.connected = Some((*peer, send_back_addr.clone()));
+                self.connected.insert(*peer, send_back_addr.clone());
             }
         }
 
@@ -111,10 +93,6 @@ impl NetworkBehaviour for PeerTracker {
             .push_back(OutEvent::ConnectionEstablished(*peer));
     }
 
-    fn inject_connection_closed(&mut self, _: &PeerId, _: &ConnectionId, _: &ConnectedPoint) {
-        self.connected = None;
-    }
-
     fn inject_event(&mut self, _: PeerId, _: ConnectionId, _: void::Void) {}
 
     fn poll(
@@ -0,0 +1,566 @@
+//! Discovery of sellers through a rendezvous point.
+//!
+//! Sellers register their addresses and current bid quote with a
+//! rendezvous point, buyers ask it for the sellers that registered for the
+//! environment they run on. A registration expires after [`REGISTRATION_TTL`]
+//! unless the seller registers again, so sellers that went offline disappear
+//! from the list eventually.
+
+use crate::{
+    env::Env,
+    network::{request_response::CborCodec, transport, TokioExecutor},
+    protocol::alice::BidQuote,
+};
+use anyhow::{anyhow, bail, Context, Error, Result};
+use libp2p::{
+    core::{identity, multiaddr::Protocol},
+    request_response::{
+        ProtocolName, ProtocolSupport, RequestResponse, RequestResponseConfig,
+        RequestResponseEvent, RequestResponseMessage,
+    },
+    swarm::SwarmBuilder,
+    Multiaddr, NetworkBehaviour, PeerId, Swarm,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fmt,
+    net::SocketAddr,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use tracing::{debug, info, warn};
+
+/// How long a registration is kept by the rendezvous point.
+pub const REGISTRATION_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Time to wait for the rendezvous point to answer.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The maximum number of addresses a seller can register.
+const MAX_ADDRESSES: usize = 16;
+
+/// The maximum number of registrations a rendezvous point keeps, across all
+/// environments.
+const MAX_REGISTRATIONS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RendezvousProtocol;
+
+impl ProtocolName for RendezvousProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        b"/comit/jude/btc/rendezvous/1.0.0"
+    }
+}
+
+type Codec = CborCodec<RendezvousProtocol, Request, Response>;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Request {
+    /// Registers the sending peer as a seller, replacing its previous
+    /// registration for the environment.
+    Register {
+        env: Env,
+        addresses: Vec<Multiaddr>,
+        quote: BidQuote,
+    },
+    Discover {
+        env: Env,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Response {
+    Registered { ttl_secs: u64 },
+    Rejected { reason: String },
+    Sellers(Vec<Seller>),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Seller {
+    #[serde(with = "crate::serde_peer_id")]
+    pub peer_id: PeerId,
+    pub addresses: Vec<Multiaddr>,
+    pub quote: BidQuote,
+}
+
+/// The address of a rendezvous point including its peer id, e.g.
+/// `/dns4/rendezvous.example.com/tcp/8888/p2p/12D3KooW...`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RendezvousPoint {
+    pub peer_id: PeerId,
+    pub address: Multiaddr,
+}
+
+impl TryFrom<Multiaddr> for RendezvousPoint {
+    type Error = Error;
+
+    fn try_from(mut address: Multiaddr) -> Result<Self> {
+        let peer_id = match address.pop() {
+            Some(Protocol::P2p(multihash)) => PeerId::from_multihash(multihash)
+                .map_err(|_| anyhow!("Invalid peer id in {}", address))?,
+            _ => bail!("The address {} does not end with /p2p/<peer-id>", address),
+        };
+
+        Ok(Self { peer_id, address })
+    }
+}
+
+impl FromStr for RendezvousPoint {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::try_from(Multiaddr::from_str(s)?)
+    }
+}
+
+impl fmt::Display for RendezvousPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.address
+                .clone()
+                .with(Protocol::P2p(self.peer_id.into()))
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum OutEvent {
+    Registered {
+        rendezvous_node: PeerId,
+        ttl: Duration,
+    },
+    Discovered(Vec<Seller>),
+    Failure(Error),
+}
+
+/// A `NetworkBehaviour` that registers with and discovers sellers at a
+/// rendezvous point.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "OutEvent", event_process = false)]
+#[allow(missing_debug_implementations)]
+pub struct Behaviour {
+    rr: RequestResponse<Codec>,
+}
+
+impl Behaviour {
+    pub fn add_address(&mut self, rendezvous_point: &RendezvousPoint) {
+        self.rr
+            .add_address(&rendezvous_point.peer_id, rendezvous_point.address.clone());
+    }
+
+    pub fn register(
+        &mut self,
+        rendezvous_node: &PeerId,
+        env: Env,
+        addresses: Vec<Multiaddr>,
+        quote: BidQuote,
+    ) {
+        self.rr.send_request(rendezvous_node, Request::Register {
+            env,
+            addresses,
+            quote,
+        });
+    }
+
+    pub fn discover(&mut self, rendezvous_node: &PeerId, env: Env) {
+        self.rr
+            .send_request(rendezvous_node, Request::Discover { env });
+    }
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        Self {
+            rr: new_request_response(ProtocolSupport::Outbound),
+        }
+    }
+}
+
+impl From<RequestResponseEvent<Request, Response>> for OutEvent {
+    fn from(event: RequestResponseEvent<Request, Response>) -> Self {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message: RequestResponseMessage::Response { response, .. },
+            } => match response {
+                Response::Registered { ttl_secs } => OutEvent::Registered {
+                    rendezvous_node: peer,
+                    ttl: Duration::from_secs(ttl_secs),
+                },
+                Response::Rejected { reason } => {
+                    OutEvent::Failure(anyhow!("Registration rejected: {}", reason))
+                }
+                Response::Sellers(sellers) => OutEvent::Discovered(sellers),
+            },
+            RequestResponseEvent::Message {
+                message: RequestResponseMessage::Request { .. },
+                ..
+            } => OutEvent::Failure(anyhow!("Only the rendezvous point handles requests")),
+            RequestResponseEvent::InboundFailure { error, .. } => {
+                OutEvent::Failure(anyhow!("Inbound failure: {:?}", error))
+            }
+            RequestResponseEvent::OutboundFailure { error, .. } => {
+                OutEvent::Failure(anyhow!("Outbound failure: {:?}", error))
+            }
+            RequestResponseEvent::ResponseSent { .. } => {
+                OutEvent::Failure(anyhow!("Only the rendezvous point sends responses"))
+            }
+        }
+    }
+}
+
+fn new_request_response(support: ProtocolSupport) -> RequestResponse<Codec> {
+    let mut config = RequestResponseConfig::default();
+    config.set_request_timeout(TIMEOUT);
+
+    RequestResponse::new(
+        CborCodec::default(),
+        vec![(RendezvousProtocol, support)],
+        config,
+    )
+}
+
+/// The sellers registered with a rendezvous point.
+#[derive(Debug, Default)]
+pub struct Registrations {
+    sellers: HashMap<(Env, PeerId), (Seller, Instant)>,
+}
+
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("The rendezvous point does not accept more than {max} registrations")]
+pub struct TooManyRegistrations {
+    pub max: usize,
+}
+
+impl Registrations {
+    /// Registers the seller, replacing its previous registration. Expired
+    /// registrations are removed first, new sellers are rejected once
+    /// [`MAX_REGISTRATIONS`] sellers are registered.
+    pub fn register(
+        &mut self,
+        env: Env,
+        seller: Seller,
+        now: Instant,
+    ) -> Result<(), TooManyRegistrations> {
+        self.sellers.retain(|_, (_, expires_at)| *expires_at > now);
+
+        let key = (env, seller.peer_id);
+        if self.sellers.len() >= MAX_REGISTRATIONS && !self.sellers.contains_key(&key) {
+            return Err(TooManyRegistrations {
+                max: MAX_REGISTRATIONS,
+            });
+        }
+
+        self.sellers.insert(key, (seller, now + REGISTRATION_TTL));
+
+        Ok(())
+    }
+
+    /// The sellers registered for the environment, cheapest first. Expired
+    /// registrations are removed.
+    pub fn sellers(&mut self, env: Env, now: Instant) -> Vec<Seller> {
+        self.sellers.retain(|_, (_, expires_at)| *expires_at > now);
+
+        let mut sellers = self
+            .sellers
+            .iter()
+            .filter(|((seller_env, _), _)| *seller_env == env)
+            .map(|(_, (seller, _))| seller.clone())
+            .collect::<Vec<_>>();
+        sellers.sort_by_key(|seller| seller.quote.price);
+
+        sellers
+    }
+}
+
+/// A rendezvous point, keeping the registrations of sellers in memory.
+#[allow(missing_debug_implementations)]
+pub struct Server {
+    swarm: Swarm<RequestResponse<Codec>>,
+    registrations: Registrations,
+}
+
+impl Server {
+    pub fn new(identity: &identity::Keypair, listen_address: Multiaddr) -> Result<Self> {
+        let transport = transport::build(identity, None)?;
+        let peer_id = PeerId::from(identity.public());
+
+        let mut swarm = SwarmBuilder::new(
+            transport,
+            new_request_response(ProtocolSupport::Inbound),
+            peer_id,
+        )
+        .executor(Box::new(TokioExecutor {
+            handle: tokio::runtime::Handle::current(),
+        }))
+        .build();
+
+        Swarm::listen_on(&mut swarm, listen_address.clone())
+            .with_context(|| format!("Address is not supported: {:#}", listen_address))?;
+
+        Ok(Self {
+            swarm,
+            registrations: Registrations::default(),
+        })
+    }
+
+    pub fn peer_id(&self) -> PeerId {
+        *Swarm::local_peer_id(&self.swarm)
+    }
+
+    pub async fn run(mut self) {
+        loop {
+            match self.swarm.next().await {
+                RequestResponseEvent::Message {
+                    peer,
+                    message:
+                        RequestResponseMessage::Request {
+                            request, channel, ..
+                        },
+                } => {
+                    let response = self.handle_request(peer, request);
+
+                    if self.swarm.send_response(channel, response).is_err() {
+                        warn!("Failed to respond to {}, connection closed", peer);
+                    }
+                }
+                RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                    debug!("Failed to handle request of {}: {:?}", peer, error);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn handle_request(&mut self, peer: PeerId, request: Request) -> Response {
+        match request {
+            Request::Register {
+                env,
+                addresses,
+                quote,
+            } => {
+                if addresses.is_empty() || addresses.len() > MAX_ADDRESSES {
+                    return Response::Rejected {
+                        reason: format!("Expected 1 to {} addresses", MAX_ADDRESSES),
+                    };
+                }
+
+                let registered = self.registrations.register(
+                    env,
+                    Seller {
+                        peer_id: peer,
+                        addresses,
+                        quote,
+                    },
+                    Instant::now(),
+                );
+                if let Err(error) = registered {
+                    warn!("Rejected seller {} on {}: {}", peer, env, error);
+                    return Response::Rejected {
+                        reason: error.to_string(),
+                    };
+                }
+
+                info!("Registered seller {} on {}", peer, env);
+                Response::Registered {
+                    ttl_secs: REGISTRATION_TTL.as_secs(),
+                }
+            }
+            Request::Discover { env } => {
+                let sellers = self.registrations.sellers(env, Instant::now());
+                debug!("Sending {} sellers on {} to {}", sellers.len(), env, peer);
+
+                Response::Sellers(sellers)
+            }
+        }
+    }
+}
+
+/// Asks the rendezvous point for the sellers registered for the environment.
+pub async fn list_sellers(
+    identity: &identity::Keypair,
+    tor_socks5_proxy: Option<SocketAddr>,
+    rendezvous_point: &RendezvousPoint,
+    env: Env,
+) -> Result<Vec<Seller>> {
+    let transport = transport::build(identity, tor_socks5_proxy)?;
+    let peer_id = PeerId::from(identity.public());
+
+    let mut swarm = SwarmBuilder::new(transport, Behaviour::default(), peer_id)
+        .executor(Box::new(TokioExecutor {
+            handle: tokio::runtime::Handle::current(),
+        }))
+        .build();
+
+    swarm.add_address(rendezvous_point);
+    swarm.discover(&rendezvous_point.peer_id, env);
+
+    let sellers = async {
+        loop {
+            match swarm.next().await {
+                OutEvent::Discovered(sellers) => return Ok(sellers),
+                OutEvent::Failure(error) => return Err(error),
+                OutEvent::Registered { .. } => {}
+            }
+        }
+    };
+
+    tokio::time::timeout(TIMEOUT, sellers)
+        .await
+        .with_context(|| format!("Rendezvous point {} did not respond", rendezvous_point))?
+        .with_context(|| format!("Failed to list sellers at {}", rendezvous_point))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jude;
+    use get_port::get_port;
+
+    fn quote(price: u64) -> BidQuote {
+        BidQuote {
+            price: bitcoin::Amount::from_sat(price),
+            min_buy: bitcoin::Amount::from_sat(100_000),
+            max_sell: jude::Amount::from_jude(10.0).unwrap(),
+        }
+    }
+
+    fn seller(price: u64) -> Seller {
+        Seller {
+            peer_id: PeerId::random(),
+            addresses: vec!["/ip4/127.0.0.1/tcp/9939".parse().unwrap()],
+            quote: quote(price),
+        }
+    }
+
+    #[test]
+    fn parses_rendezvous_point() {
+        let peer_id = PeerId::random();
+        let rendezvous_point: RendezvousPoint = format!("/ip4/127.0.0.1/tcp/8888/p2p/{}", peer_id)
+            .parse()
+            .unwrap();
+
+        assert_eq!(rendezvous_point.peer_id, peer_id);
+        assert_eq!(
+            rendezvous_point.address,
+            "/ip4/127.0.0.1/tcp/8888".parse().unwrap()
+        );
+        assert!(RendezvousPoint::from_str("/ip4/127.0.0.1/tcp/8888").is_err());
+    }
+
+    #[test]
+    fn registrations_expire_and_are_separated_by_env() {
+        let mut registrations = Registrations::default();
+        let now = Instant::now();
+        let cheap = seller(1_000);
+        let expensive = seller(2_000);
+
+        registrations
+            .register(Env::Testnet, expensive.clone(), now)
+            .unwrap();
+        registrations
+            .register(Env::Testnet, cheap.clone(), now + Duration::from_secs(60))
+            .unwrap();
+        registrations
+            .register(Env::Mainnet, seller(500), now)
+            .unwrap();
+
+        assert_eq!(registrations.sellers(Env::Testnet, now), vec![
+            cheap.clone(),
+            expensive
+        ]);
+        assert_eq!(
+            registrations.sellers(Env::Testnet, now + REGISTRATION_TTL),
+            vec![cheap]
+        );
+        assert!(registrations.sellers(Env::Regtest, now).is_empty());
+    }
+
+    #[test]
+    fn registrations_are_capped_until_they_expire() {
+        let mut registrations = Registrations::default();
+        let now = Instant::now();
+        let first = seller(1_000);
+
+        registrations
+            .register(Env::Testnet, first.clone(), now)
+            .unwrap();
+        for _ in 1..MAX_REGISTRATIONS {
+            registrations
+                .register(Env::Testnet, seller(1_000), now + Duration::from_secs(60))
+                .unwrap();
+        }
+
+        assert!(registrations
+            .register(Env::Testnet, seller(1_000), now)
+            .is_err());
+        registrations
+            .register(Env::Testnet, first, now)
+            .expect("registered sellers can register again");
+        assert!(registrations
+            .register(Env::Testnet, seller(1_000), now + REGISTRATION_TTL)
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn registered_seller_is_listed() {
+        let port = get_port().expect("Failed to find a free port");
+        let address: Multiaddr = format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap();
+        let server = Server::new(&identity::Keypair::generate_ed25519(), address.clone()).unwrap();
+        let rendezvous_point = RendezvousPoint {
+            peer_id: server.peer_id(),
+            address,
+        };
+        tokio::spawn(server.run());
+
+        let seller_identity = identity::Keypair::generate_ed25519();
+        let seller_peer_id = PeerId::from(seller_identity.public());
+        let seller_address: Multiaddr = "/ip4/127.0.0.1/tcp/9939".parse().unwrap();
+        let mut swarm = SwarmBuilder::new(
+            transport::build(&seller_identity, None).unwrap(),
+            Behaviour::default(),
+            seller_peer_id,
+        )
+        .executor(Box::new(TokioExecutor {
+            handle: tokio::runtime::Handle::current(),
+        }))
+        .build();
+        swarm.add_address(&rendezvous_point);
+        swarm.register(
+            &rendezvous_point.peer_id,
+            Env::Regtest,
+            vec![seller_address.clone()],
+            quote(1_000),
+        );
+
+        match swarm.next().await {
+            OutEvent::Registered {
+                rendezvous_node,
+                ttl,
+            } => {
+                assert_eq!(rendezvous_node, rendezvous_point.peer_id);
+                assert_eq!(ttl, REGISTRATION_TTL);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+
+        let buyer_identity = identity::Keypair::generate_ed25519();
+        let sellers = list_sellers(&buyer_identity, None, &rendezvous_point, Env::Regtest)
+            .await
+            .unwrap();
+        let other_env = list_sellers(&buyer_identity, None, &rendezvous_point, Env::Mainnet)
+            .await
+            .unwrap();
+
+        assert_eq!(sellers, vec![Seller {
+            peer_id: seller_peer_id,
+            addresses: vec![seller_address],
+            quote: quote(1_000),
+        }]);
+        assert!(other_env.is_empty());
+    }
+}
@@ -22,21 +22,30 @@ pub struct TransferProofProtocol;
 #[derive(Debug, Clone, Copy, Default)]
 pub struct EncryptedSignatureProtocol;
 
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BidQuoteProtocol;
+
 impl ProtocolName for Swap {
     fn protocol_name(&self) -> &[u8] {
-        b"/comit/jude/btc/swap/1.0.0"
+        b"/comit/jude/btc/swap/2.0.0"
     }
 }
 
 impl ProtocolName for TransferProofProtocol {
     fn protocol_name(&self) -> &[u8] {
-        b"/comit/jude/btc/transfer_proof/1.0.0"
+        b"/comit/jude/btc/transfer_proof/2.0.0"
     }
 }
 
 impl ProtocolName for EncryptedSignatureProtocol {
     fn protocol_name(&self) -> &[u8] {
-        b"/comit/jude/btc/encrypted_signature/1.0.0"
+        b"/comit/jude/btc/encrypted_signature/2.0.0"
+    }
+}
+
+impl ProtocolName for BidQuoteProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        b"/comit/jude/btc/bid_quote/1.0.0"
     }
 }
 
@@ -0,0 +1,561 @@
+//! Connections over Tor.
+//!
+//! Addresses are dialed through the SOCKS5 proxy of a local Tor daemon,
+//! listening is left to Tor: An onion service published through the control
+//! port of the daemon forwards to our regular TCP listen port.
+
+use anyhow::{bail, Context, Result};
+use futures::{
+    future::BoxFuture,
+    io::{AsyncRead, AsyncWrite},
+    ready,
+    stream::BoxStream,
+    FutureExt,
+};
+use libp2p::{
+    core::{
+        multiaddr::{Onion3Addr, Protocol},
+        transport::{ListenerEvent, TransportError},
+        Transport,
+    },
+    Multiaddr,
+};
+use std::{
+    convert::TryFrom,
+    fs, io,
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, ReadBuf},
+    net::TcpStream,
+};
+use tracing::debug;
+
+/// Dials `/onion3` addresses and TCP addresses with a `/dns*`, `/ip4` or
+/// `/ip6` host through a SOCKS5 proxy. Host names are resolved by the proxy,
+/// hence nothing is looked up or dialed outside of Tor.
+#[derive(Clone, Copy, Debug)]
+pub struct TorDialOnlyTransport {
+    socks5_proxy: SocketAddr,
+}
+
+impl TorDialOnlyTransport {
+    pub fn new(socks5_proxy: SocketAddr) -> Self {
+        Self { socks5_proxy }
+    }
+}
+
+impl Transport for TorDialOnlyTransport {
+    type Output = TorStream;
+    type Error = io::Error;
+    type Listener =
+        BoxStream<'static, Result<ListenerEvent<Self::ListenerUpgrade, io::Error>, io::Error>>;
+    type ListenerUpgrade = BoxFuture<'static, Result<TorStream, io::Error>>;
+    type Dial = BoxFuture<'static, Result<TorStream, io::Error>>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<io::Error>> {
+        Err(TransportError::MultiaddrNotSupported(addr))
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<io::Error>> {
+        let (host, port) = match host_and_port(&addr) {
+            Some(host_and_port) => host_and_port,
+            None => return Err(TransportError::MultiaddrNotSupported(addr)),
+        };
+
+        Ok(async move {
+            debug!(
+                "Dialing {} through SOCKS5 proxy {}",
+                addr, self.socks5_proxy
+            );
+
+            let mut stream = TcpStream::connect(self.socks5_proxy).await?;
+            socks5_connect(&mut stream, &host, port).await?;
+
+            Ok(TorStream(stream))
+        }
+        .boxed())
+    }
+}
+
+/// The host and port of an address of the form `/onion3/<id>:<port>` or
+/// `/<dns|dns4|dns6|ip4|ip6>/<host>/tcp/<port>`, optionally followed by the
+/// peer id.
+fn host_and_port(addr: &Multiaddr) -> Option<(String, u16)> {
+    let mut protocols = addr.iter();
+
+    let host = match protocols.next()? {
+        Protocol::Onion3(onion) => {
+            let host_and_port = (onion_host(&onion), onion.port());
+            return match protocols.next() {
+                None | Some(Protocol::P2p(_)) => Some(host_and_port),
+                _ => None,
+            };
+        }
+        Protocol::Dns(name) | Protocol::Dns4(name) | Protocol::Dns6(name) => name.to_string(),
+        Protocol::Ip4(ip) => ip.to_string(),
+        Protocol::Ip6(ip) => ip.to_string(),
+        _ => return None,
+    };
+    let port = match protocols.next()? {
+        Protocol::Tcp(port) => port,
+        _ => return None,
+    };
+    match protocols.next() {
+        None | Some(Protocol::P2p(_)) => {}
+        _ => return None,
+    }
+
+    Some((host, port))
+}
+
+fn onion_host(onion: &Onion3Addr<'_>) -> String {
+    format!("{}.onion", base32(onion.hash()))
+}
+
+/// Lower case base32 (RFC 4648) without padding, as used in onion addresses.
+fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+    let mut encoded = String::new();
+    let mut buffer = 0u16;
+    let mut bits = 0;
+
+    for byte in bytes {
+        buffer = (buffer << 8) | u16::from(*byte);
+        bits += 8;
+
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(char::from(ALPHABET[usize::from((buffer >> bits) & 0x1f)]));
+        }
+    }
+    if bits > 0 {
+        encoded.push(char::from(
+            ALPHABET[usize::from((buffer << (5 - bits)) & 0x1f)],
+        ));
+    }
+
+    encoded
+}
+
+/// Asks the SOCKS5 proxy to connect to the host, an IP address or a name the
+/// proxy resolves, without authentication.
+async fn socks5_connect(stream: &mut TcpStream, host: &str, port: u16) -> io::Result<()> {
+    const VERSION: u8 = 5;
+    const NO_AUTHENTICATION: u8 = 0;
+    const CONNECT: u8 = 1;
+    const IPV4: u8 = 1;
+    const DOMAIN_NAME: u8 = 3;
+    const IPV6: u8 = 4;
+
+    stream.write_all(&[VERSION, 1, NO_AUTHENTICATION]).await?;
+
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await?;
+    if method != [VERSION, NO_AUTHENTICATION] {
+        return Err(socks5_error("SOCKS5 proxy requires authentication"));
+    }
+
+    let mut request = vec![VERSION, CONNECT, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            let host_len =
+                u8::try_from(host.len()).map_err(|_| socks5_error("host name too long"))?;
+            request.extend_from_slice(&[DOMAIN_NAME, host_len]);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0 {
+        return Err(socks5_error(&format!(
+            "SOCKS5 proxy failed to connect to {}:{} with reply {}",
+            host, port, reply[1]
+        )));
+    }
+
+    // The address the proxy bound to is of no interest, but has to be read.
+    let bound_addr_len = match reply[3] {
+        1 => 4,
+        3 => usize::from(stream.read_u8().await?),
+        4 => 16,
+        atyp => return Err(socks5_error(&format!("unknown address type {}", atyp))),
+    };
+    let mut bound_addr = vec![0u8; bound_addr_len + 2];
+    stream.read_exact(&mut bound_addr).await?;
+
+    Ok(())
+}
+
+fn socks5_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, msg)
+}
+
+/// A connection through the SOCKS5 proxy.
+#[derive(Debug)]
+pub struct TorStream(TcpStream);
+
+impl AsyncRead for TorStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut read_buf = ReadBuf::new(buf);
+        ready!(tokio::io::AsyncRead::poll_read(
+            Pin::new(&mut self.0),
+            cx,
+            &mut read_buf
+        ))?;
+
+        Poll::Ready(Ok(read_buf.filled().len()))
+    }
+}
+
+impl AsyncWrite for TorStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        tokio::io::AsyncWrite::poll_write(Pin::new(&mut self.0), cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        tokio::io::AsyncWrite::poll_flush(Pin::new(&mut self.0), cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        tokio::io::AsyncWrite::poll_shutdown(Pin::new(&mut self.0), cx)
+    }
+}
+
+/// An onion service that forwards to our listen port. Tor removes the
+/// service once this is dropped.
+#[derive(Debug)]
+pub struct OnionService {
+    pub address: Multiaddr,
+    _control: ControlConnection,
+}
+
+impl OnionService {
+    /// Publishes an onion service through the control port of the local Tor
+    /// daemon. The key of the service is kept in the given file so that the
+    /// onion address stays the same across restarts.
+    pub async fn publish(
+        control_port: u16,
+        listen_address: &Multiaddr,
+        key_file: &Path,
+    ) -> Result<Self> {
+        let port = listen_address
+            .iter()
+            .find_map(|protocol| match protocol {
+                Protocol::Tcp(port) => Some(port),
+                _ => None,
+            })
+            .with_context(|| format!("No TCP port to forward to in {}", listen_address))?;
+
+        let mut control =
+            ControlConnection::connect(SocketAddr::from(([127, 0, 0, 1], control_port)))
+                .await
+                .context("Failed to connect to Tor control port")?;
+        control.authenticate().await?;
+
+        let key = match fs::read_to_string(key_file) {
+            Ok(key) => key.trim().to_string(),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => "NEW:ED25519-V3".to_string(),
+            Err(error) => {
+                return Err(error).with_context(|| format!("Failed to read {}", key_file.display()))
+            }
+        };
+
+        let reply = control
+            .command(&format!(
+                "ADD_ONION {} Port={},127.0.0.1:{}",
+                key, port, port
+            ))
+            .await
+            .context("Failed to add onion service")?;
+
+        let service_id = reply_value(&reply, "ServiceID").context("No service id in reply")?;
+        if let Some(private_key) = reply_value(&reply, "PrivateKey") {
+            write_private_file(key_file, private_key.as_bytes())
+                .with_context(|| format!("Failed to write {}", key_file.display()))?;
+        }
+
+        Ok(Self {
+            address: format!("/onion3/{}:{}", service_id, port).parse()?,
+            _control: control,
+        })
+    }
+}
+
+/// Creates the file readable and writable by the owner only, on unix.
+fn write_private_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    use std::io::Write;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)?.write_all(contents)
+}
+
+/// A connection to the control port of a Tor daemon.
+#[derive(Debug)]
+struct ControlConnection {
+    stream: BufReader<TcpStream>,
+}
+
+impl ControlConnection {
+    async fn connect(addr: SocketAddr) -> Result<Self> {
+        Ok(Self {
+            stream: BufReader::new(TcpStream::connect(addr).await?),
+        })
+    }
+
+    /// Authenticates with the first method the daemon supports of: none, or
+    /// the cookie file.
+    async fn authenticate(&mut self) -> Result<()> {
+        let protocol_info = self.command("PROTOCOLINFO 1").await?;
+        let auth = protocol_info
+            .iter()
+            .find(|line| line.starts_with("AUTH "))
+            .context("No authentication methods in PROTOCOLINFO reply")?;
+        let methods = auth
+            .split(' ')
+            .find_map(|field| field.strip_prefix("METHODS="))
+            .unwrap_or_default();
+
+        let command = if methods.split(',').any(|method| method == "NULL") {
+            "AUTHENTICATE".to_string()
+        } else if methods.split(',').any(|method| method == "COOKIE") {
+            let cookie_file = auth
+                .split("COOKIEFILE=")
+                .nth(1)
+                .map(|path| path.trim_matches('"'))
+                .context("No cookie file in PROTOCOLINFO reply")?;
+            let cookie = fs::read(cookie_file)
+                .with_context(|| format!("Failed to read cookie file {}", cookie_file))?;
+
+            let cookie = cookie
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>();
+
+            format!("AUTHENTICATE {}", cookie)
+        } else {
+            bail!(
+                "Tor control port requires unsupported authentication {}",
+                methods
+            )
+        };
+
+        self.command(&command)
+            .await
+            .context("Failed to authenticate with Tor control port")?;
+
+        Ok(())
+    }
+
+    /// Sends a command and returns the lines of a successful reply, without
+    /// status code.
+    async fn command(&mut self, command: &str) -> Result<Vec<String>> {
+        self.stream
+            .get_mut()
+            .write_all(format!("{}\r\n", command).as_bytes())
+            .await?;
+
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                bail!("Tor control port closed the connection");
+            }
+            let line = line.trim_end();
+
+            if !line.starts_with("250") || line.len() < 4 {
+                bail!("Tor control port replied: {}", line);
+            }
+            lines.push(line[4..].to_string());
+
+            // A space after the status code marks the last line.
+            if line.as_bytes()[3] == b' ' {
+                return Ok(lines);
+            }
+        }
+    }
+}
+
+fn reply_value<'a>(reply: &'a [String], key: &str) -> Option<&'a str> {
+    reply
+        .iter()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{AsyncReadExt as _, AsyncWriteExt as _};
+    use tokio::net::TcpListener;
+
+    const ONION: &str = "vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd";
+
+    /// Accepts a single SOCKS5 connection, returns the requested host and port
+    /// and echoes what is sent afterwards.
+    async fn socks5_stand_in(listener: TcpListener) -> (String, u16) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+
+        let mut greeting = [0u8; 3];
+        stream.read_exact(&mut greeting).await.unwrap();
+        stream.write_all(&[5, 0]).await.unwrap();
+
+        let mut request = [0u8; 4];
+        stream.read_exact(&mut request).await.unwrap();
+        let host = match request[3] {
+            1 => {
+                let mut ip = [0u8; 4];
+                stream.read_exact(&mut ip).await.unwrap();
+                IpAddr::from(ip).to_string()
+            }
+            3 => {
+                let mut host = vec![0u8; usize::from(stream.read_u8().await.unwrap())];
+                stream.read_exact(&mut host).await.unwrap();
+                String::from_utf8(host).unwrap()
+            }
+            atyp => panic!("Unexpected address type {}", atyp),
+        };
+        let port = stream.read_u16().await.unwrap();
+        stream
+            .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0])
+            .await
+            .unwrap();
+
+        let mut msg = [0u8; 4];
+        stream.read_exact(&mut msg).await.unwrap();
+        stream.write_all(&msg).await.unwrap();
+
+        (host, port)
+    }
+
+    async fn dial_through_stand_in(addr: Multiaddr) -> (String, u16) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = listener.local_addr().unwrap();
+        let stand_in = tokio::spawn(socks5_stand_in(listener));
+
+        let transport = TorDialOnlyTransport::new(proxy);
+        let mut stream = transport.dial(addr).unwrap().await.unwrap();
+        stream.write_all(b"ping").await.unwrap();
+        let mut echo = [0u8; 4];
+        stream.read_exact(&mut echo).await.unwrap();
+        assert_eq!(&echo, b"ping");
+
+        stand_in.await.unwrap()
+    }
+
+    #[test]
+    fn only_tcp_addresses_are_dialed() {
+        let onion: Multiaddr = format!("/onion3/{}:9939", ONION).parse().unwrap();
+        let dns: Multiaddr = "/dns4/example.com/tcp/9939".parse().unwrap();
+        let ip6: Multiaddr = "/ip6/::1/tcp/9939".parse().unwrap();
+        let udp: Multiaddr = "/ip4/127.0.0.1/udp/9939".parse().unwrap();
+
+        assert_eq!(
+            host_and_port(&onion),
+            Some((format!("{}.onion", ONION), 9939))
+        );
+        assert_eq!(host_and_port(&dns), Some(("example.com".to_string(), 9939)));
+        assert_eq!(host_and_port(&ip6), Some(("::1".to_string(), 9939)));
+        assert_eq!(host_and_port(&udp), None);
+    }
+
+    #[tokio::test]
+    async fn dials_onion_address_through_socks5_proxy() {
+        let requested =
+            dial_through_stand_in(format!("/onion3/{}:9939", ONION).parse().unwrap()).await;
+
+        assert_eq!(requested, (format!("{}.onion", ONION), 9939));
+    }
+
+    #[tokio::test]
+    async fn dials_dns_and_ip_addresses_through_socks5_proxy() {
+        let dns = dial_through_stand_in("/dns4/example.com/tcp/9939".parse().unwrap()).await;
+        let ip = dial_through_stand_in("/ip4/1.2.3.4/tcp/9939".parse().unwrap()).await;
+
+        assert_eq!(dns, ("example.com".to_string(), 9939));
+        assert_eq!(ip, ("1.2.3.4".to_string(), 9939));
+    }
+
+    #[tokio::test]
+    async fn publishes_onion_service_and_keeps_its_key() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let control_port = listener.local_addr().unwrap().port();
+        let stand_in = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut commands = Vec::new();
+
+            for reply in &[
+                "250-PROTOCOLINFO 1\r\n250-AUTH METHODS=NULL\r\n250 OK\r\n".to_string(),
+                "250 OK\r\n".to_string(),
+                format!(
+                    "250-ServiceID={}\r\n250-PrivateKey=ED25519-V3:secret\r\n250 OK\r\n",
+                    ONION
+                ),
+            ] {
+                let mut command = String::new();
+                stream.read_line(&mut command).await.unwrap();
+                commands.push(command.trim_end().to_string());
+                stream.get_mut().write_all(reply.as_bytes()).await.unwrap();
+            }
+
+            commands
+        });
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let key_file = data_dir.path().join("onion_service_key");
+        let service = OnionService::publish(
+            control_port,
+            &"/ip4/0.0.0.0/tcp/9939".parse().unwrap(),
+            &key_file,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            service.address,
+            format!("/onion3/{}:9939", ONION).parse().unwrap()
+        );
+        assert_eq!(fs::read_to_string(&key_file).unwrap(), "ED25519-V3:secret");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&key_file).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        assert_eq!(stand_in.await.unwrap(), vec![
+            "PROTOCOLINFO 1",
+            "AUTHENTICATE",
+            "ADD_ONION NEW:ED25519-V3 Port=9939,127.0.0.1:9939"
+        ]);
+    }
+}
@@ -1,9 +1,10 @@
+use crate::network::tor::TorDialOnlyTransport;
 use anyhow::Result;
 use libp2p::{
     core::{
         identity,
         muxing::StreamMuxerBox,
-        transport::Boxed,
+        transport::{Boxed, OptionalTransport},
         upgrade::{SelectUpgrade, Version},
         Transport,
     },
@@ -12,22 +13,40 @@ use libp2p::{
     noise::{self, NoiseConfig, X25519Spec},
     yamux, PeerId,
 };
+use std::net::SocketAddr;
 
 /// Builds a libp2p transport with the following features:
 /// - TcpConnection
 /// - DNS name resolution
+/// - dialing through the given Tor SOCKS5 proxy instead, if any, nothing is
+///   dialed directly then
 /// - authentication via noise
 /// - multiplexing via yamux or mplex
-pub fn build(id_keys: &identity::Keypair) -> Result<SwapTransport> {
+pub fn build(
+    id_keys: &identity::Keypair,
+    tor_socks5_proxy: Option<SocketAddr>,
+) -> Result<SwapTransport> {
     use libp2p::tcp::TokioTcpConfig;
 
     let dh_keys = noise::Keypair::<X25519Spec>::new().into_authentic(id_keys)?;
     let noise = NoiseConfig::xx(dh_keys).into_authenticated();
 
-    let tcp = TokioTcpConfig::new().nodelay(true);
-    let dns = DnsConfig::new(tcp)?;
+    let (tor, dns) = match tor_socks5_proxy {
+        Some(proxy) => (
+            OptionalTransport::some(TorDialOnlyTransport::new(proxy)),
+            OptionalTransport::none(),
+        ),
+        None => {
+            let tcp = TokioTcpConfig::new().nodelay(true);
+            (
+                OptionalTransport::none(),
+                OptionalTransport::some(DnsConfig::new(tcp)?),
+            )
+        }
+    };
 
-    let transport = dns
+    let transport = tor
+        .or_transport(dns)
         .upgrade(Version::V1)
         .authenticate(noise)
         .multiplex(SelectUpgrade::new(
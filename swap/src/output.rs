@@ -0,0 +1,63 @@
+//! Output shared by the asb and the swap_cli.
+
+use crate::{bitcoin, jude};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use time::OffsetDateTime;
+
+/// Prints the balances of the wallets.
+pub fn print_balance(bitcoin: bitcoin::Balance, jude: jude::Balance, json: bool) {
+    if json {
+        let balance = serde_json::json!({
+            "bitcoin": {
+                "confirmed_sat": bitcoin.confirmed.as_sat(),
+                "unconfirmed_sat": bitcoin.unconfirmed.as_sat(),
+            },
+            "jude": {
+                "unlocked_piconero": jude.unlocked.as_piconero(),
+                "locked_piconero": jude.locked.as_piconero(),
+            },
+        });
+        println!("{}", balance);
+        return;
+    }
+
+    println!(
+        "BTC: {} confirmed, {} unconfirmed",
+        bitcoin.confirmed, bitcoin.unconfirmed
+    );
+    println!("jude: {} unlocked, {} locked", jude.unlocked, jude.locked);
+}
+
+/// Prints the addresses to fund the wallets with.
+pub fn print_deposit_addresses(bitcoin: bitcoin::Address, jude: ::jude::Address, json: bool) {
+    if json {
+        let addresses = serde_json::json!({
+            "bitcoin": bitcoin.to_string(),
+            "jude": jude.to_string(),
+        });
+        println!("{}", addresses);
+        return;
+    }
+
+    println!("BTC: {}", bitcoin);
+    println!("jude: {}", jude);
+}
+
+/// Seconds since the unix epoch, the representation of points in time in
+/// the machine-readable output.
+pub fn unix_timestamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Formats a point in time for the tables of the `history` commands, in UTC.
+pub fn format_timestamp(timestamp: SystemTime) -> String {
+    OffsetDateTime::from(timestamp).format("%F %T")
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+
+    format!("{}h {:02}m {:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+}
@@ -0,0 +1,173 @@
+//! Independent identities sharing one data directory.
+//!
+//! Every profile has its own seed, and hence libp2p identity and Bitcoin
+//! wallet, its own database and its own jude wallet. The default profile uses
+//! the data directory of the environment as is, so data written before
+//! profiles existed belongs to it. Other profiles are stored in a
+//! sub-directory named after them.
+use anyhow::{Context, Result};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+const DEFAULT_PROFILE_NAME: &str = "default";
+
+/// Name of the directory holding the profiles other than the default one.
+const PROFILES_DIR_NAME: &str = "profiles";
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Profile(String);
+
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Profile name {0:?} is invalid, only letters, digits, '-' and '_' are allowed")]
+pub struct InvalidProfileName(String);
+
+impl Profile {
+    pub fn is_default(&self) -> bool {
+        self.0 == DEFAULT_PROFILE_NAME
+    }
+
+    /// The directory holding the seed, database and Bitcoin wallet of this
+    /// profile, given the data directory of the environment.
+    pub fn data_dir(&self, env_data_dir: &Path) -> PathBuf {
+        if self.is_default() {
+            return env_data_dir.to_path_buf();
+        }
+
+        env_data_dir.join(PROFILES_DIR_NAME).join(&self.0)
+    }
+
+    /// The name of the jude wallet of this profile, derived from the name the
+    /// wallet had before profiles existed.
+    pub fn wallet_name(&self, base_name: &str) -> String {
+        if self.is_default() {
+            return base_name.to_string();
+        }
+
+        format!("{}-{}", base_name, self.0)
+    }
+
+    /// The default profile and every profile that has been used in the data
+    /// directory of the environment, ordered by name.
+    pub fn list(env_data_dir: &Path) -> Result<Vec<Self>> {
+        let mut profiles = vec![Self::default()];
+
+        let profiles_dir = env_data_dir.join(PROFILES_DIR_NAME);
+        if !profiles_dir.exists() {
+            return Ok(profiles);
+        }
+
+        let mut named = Vec::new();
+        for entry in fs::read_dir(&profiles_dir)
+            .with_context(|| format!("Could not read {}", profiles_dir.display()))?
+        {
+            let entry = entry?;
+
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+
+            match entry.file_name().to_str().map(Self::from_str) {
+                Some(Ok(profile)) if !profile.is_default() => named.push(profile),
+                _ => tracing::debug!("Ignoring {} which is not a profile", entry.path().display()),
+            }
+        }
+        named.sort();
+        profiles.extend(named);
+
+        Ok(profiles)
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self(DEFAULT_PROFILE_NAME.to_string())
+    }
+}
+
+impl FromStr for Profile {
+    type Err = InvalidProfileName;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let is_valid = !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if !is_valid {
+            return Err(InvalidProfileName(s.to_string()));
+        }
+
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_profile_uses_the_env_data_dir() {
+        let env_data_dir = Path::new("/data/testnet");
+
+        assert_eq!(Profile::default().data_dir(env_data_dir), env_data_dir);
+        assert_eq!(
+            Profile::from_str("default").unwrap().data_dir(env_data_dir),
+            env_data_dir
+        );
+        assert_eq!(Profile::default().wallet_name("asb-wallet"), "asb-wallet");
+    }
+
+    #[test]
+    fn named_profiles_are_kept_apart() {
+        let env_data_dir = Path::new("/data/testnet");
+        let maker = Profile::from_str("maker-2").unwrap();
+
+        assert_eq!(
+            maker.data_dir(env_data_dir),
+            Path::new("/data/testnet/profiles/maker-2")
+        );
+        assert_eq!(maker.wallet_name("asb-wallet"), "asb-wallet-maker-2");
+    }
+
+    #[test]
+    fn names_that_are_not_a_single_path_component_are_rejected() {
+        for name in &["", "..", "a/b", "a b", "/abs"] {
+            assert!(Profile::from_str(name).is_err(), "{:?} was accepted", name);
+        }
+    }
+
+    #[test]
+    fn used_profiles_are_listed() {
+        let env_data_dir = tempfile::tempdir().unwrap();
+        assert_eq!(Profile::list(env_data_dir.path()).unwrap(), vec![
+            Profile::default()
+        ]);
+
+        for name in &["personal", "maker"] {
+            let profile = Profile::from_str(name).unwrap();
+            fs::create_dir_all(profile.data_dir(env_data_dir.path())).unwrap();
+        }
+        fs::write(
+            env_data_dir
+                .path()
+                .join(PROFILES_DIR_NAME)
+                .join("notes.txt"),
+            "not a profile",
+        )
+        .unwrap();
+
+        assert_eq!(Profile::list(env_data_dir.path()).unwrap(), vec![
+            Profile::default(),
+            Profile::from_str("maker").unwrap(),
+            Profile::from_str("personal").unwrap(),
+        ]);
+    }
+}
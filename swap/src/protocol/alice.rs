@@ -6,6 +6,7 @@ use uuid::Uuid;
 
 pub use self::{
     behaviour::{Behaviour, OutEvent},
+    bid_quote::{BidQuote, BidQuoteResponse},
     event_loop::{EventLoop, EventLoopHandle},
     execution_setup::Message1,
     quote_response::*,
@@ -16,6 +17,7 @@ pub use self::{
 pub use execution_setup::Message3;
 
 mod behaviour;
+mod bid_quote;
 mod encrypted_signature;
 pub mod event_loop;
 mod execution_setup;
@@ -1,34 +1,53 @@
 use crate::{
-    network::{peer_tracker, peer_tracker::PeerTracker},
+    env::Env,
+    network::{peer_tracker, peer_tracker::PeerTracker, rendezvous, rendezvous::RendezvousPoint},
     protocol::{
         alice::{
-            encrypted_signature, execution_setup, quote_response, transfer_proof, QuoteResponse,
-            State0, State3, TransferProof,
+            bid_quote, encrypted_signature, execution_setup, quote_response, transfer_proof,
+            BidQuote, BidQuoteResponse, QuoteResponse, State0, State3, TransferProof,
         },
         bob::{EncryptedSignature, QuoteRequest},
     },
 };
-use anyhow::{Error, Result};
-use libp2p::{request_response::ResponseChannel, NetworkBehaviour, PeerId};
+use anyhow::{anyhow, Error, Result};
+use libp2p::{request_response::ResponseChannel, Multiaddr, NetworkBehaviour, PeerId};
+use std::time::Duration;
 use tracing::debug;
+use uuid::Uuid;
 
 #[derive(Debug)]
 pub enum OutEvent {
     ConnectionEstablished(PeerId),
+    Disconnected(PeerId),
     QuoteRequest {
         msg: QuoteRequest,
         channel: ResponseChannel<QuoteResponse>,
         bob_peer_id: PeerId,
     },
+    BidQuoteRequest {
+        channel: ResponseChannel<BidQuoteResponse>,
+        bob_peer_id: PeerId,
+    },
     ExecutionSetupDone {
         bob_peer_id: PeerId,
+        swap_id: Uuid,
         state3: Box<State3>,
     },
+    ExecutionSetupFailed {
+        bob_peer_id: PeerId,
+        swap_id: Option<Uuid>,
+        error: Error,
+    },
     TransferProofAcknowledged,
     EncryptedSignature {
+        bob_peer_id: PeerId,
         msg: Box<EncryptedSignature>,
         channel: ResponseChannel<()>,
     },
+    Registered {
+        rendezvous_node: PeerId,
+        ttl: Duration,
+    },
     ResponseSent, // Same variant is used for all messages as no processing is done
     Failure(Error),
 }
@@ -39,6 +58,7 @@ impl From<peer_tracker::OutEvent> for OutEvent {
             peer_tracker::OutEvent::ConnectionEstablished(id) => {
                 OutEvent::ConnectionEstablished(id)
             }
+            peer_tracker::OutEvent::Disconnected(id) => OutEvent::Disconnected(id),
         }
     }
 }
@@ -62,18 +82,45 @@ impl From<quote_response::OutEvent> for OutEvent {
     }
 }
 
+impl From<bid_quote::OutEvent> for OutEvent {
+    fn from(event: bid_quote::OutEvent) -> Self {
+        use crate::protocol::alice::bid_quote::OutEvent::*;
+        match event {
+            MsgReceived {
+                channel,
+                bob_peer_id,
+            } => OutEvent::BidQuoteRequest {
+                channel,
+                bob_peer_id,
+            },
+            ResponseSent => OutEvent::ResponseSent,
+            Failure(err) => OutEvent::Failure(err.context("Bid quote failure")),
+        }
+    }
+}
+
 impl From<execution_setup::OutEvent> for OutEvent {
     fn from(event: execution_setup::OutEvent) -> Self {
         use crate::protocol::alice::execution_setup::OutEvent::*;
         match event {
             Done {
                 bob_peer_id,
+                swap_id,
                 state3,
             } => OutEvent::ExecutionSetupDone {
                 bob_peer_id,
+                swap_id,
                 state3: Box::new(state3),
             },
-            Failure(err) => OutEvent::Failure(err),
+            Failure {
+                bob_peer_id,
+                swap_id,
+                error,
+            } => OutEvent::ExecutionSetupFailed {
+                bob_peer_id,
+                swap_id,
+                error,
+            },
         }
     }
 }
@@ -92,7 +139,12 @@ impl From<encrypted_signature::OutEvent> for OutEvent {
     fn from(event: encrypted_signature::OutEvent) -> Self {
         use crate::protocol::alice::encrypted_signature::OutEvent::*;
         match event {
-            MsgReceived { msg, channel } => OutEvent::EncryptedSignature {
+            MsgReceived {
+                bob_peer_id,
+                msg,
+                channel,
+            } => OutEvent::EncryptedSignature {
+                bob_peer_id,
                 msg: Box::new(msg),
                 channel,
             },
@@ -102,6 +154,26 @@ impl From<encrypted_signature::OutEvent> for OutEvent {
     }
 }
 
+impl From<rendezvous::OutEvent> for OutEvent {
+    fn from(event: rendezvous::OutEvent) -> Self {
+        match event {
+            rendezvous::OutEvent::Registered {
+                rendezvous_node,
+                ttl,
+            } => OutEvent::Registered {
+                rendezvous_node,
+                ttl,
+            },
+            rendezvous::OutEvent::Discovered(_) => {
+                OutEvent::Failure(anyhow!("Alice does not discover sellers"))
+            }
+            rendezvous::OutEvent::Failure(err) => {
+                OutEvent::Failure(err.context("Failure with rendezvous point"))
+            }
+        }
+    }
+}
+
 /// A `NetworkBehaviour` that represents an jude/BTC swap node as Alice.
 #[derive(NetworkBehaviour, Default)]
 #[behaviour(out_event = "OutEvent", event_process = false)]
@@ -109,9 +181,11 @@ impl From<encrypted_signature::OutEvent> for OutEvent {
 pub struct Behaviour {
     pt: PeerTracker,
     quote_response: quote_response::Behaviour,
+    bid_quote: bid_quote::Behaviour,
     execution_setup: execution_setup::Behaviour,
     transfer_proof: transfer_proof::Behaviour,
     encrypted_signature: encrypted_signature::Behaviour,
+    rendezvous: rendezvous::Behaviour,
 }
 
 impl Behaviour {
@@ -124,8 +198,20 @@ impl Behaviour {
         Ok(())
     }
 
-    pub fn start_execution_setup(&mut self, bob_peer_id: PeerId, state0: State0) {
-        self.execution_setup.run(bob_peer_id, state0);
+    pub fn send_bid_quote_response(
+        &mut self,
+        channel: ResponseChannel<BidQuoteResponse>,
+        response: BidQuoteResponse,
+    ) -> Result<()> {
+        self.bid_quote.send(channel, response)
+    }
+
+    pub fn start_execution_setup(&mut self, bob_peer_id: PeerId, swap_id: Uuid, state0: State0) {
+        self.execution_setup.run(bob_peer_id, swap_id, state0);
+    }
+
+    pub fn abort_execution_setup(&mut self, bob_peer_id: PeerId, swap_id: Uuid) {
+        self.execution_setup.abort(bob_peer_id, swap_id);
     }
 
     /// Send Transfer Proof to Bob.
@@ -137,4 +223,21 @@ impl Behaviour {
     pub fn send_encrypted_signature_ack(&mut self, channel: ResponseChannel<()>) -> Result<()> {
         self.encrypted_signature.send_ack(channel)
     }
+
+    pub fn add_rendezvous_point(&mut self, rendezvous_point: &RendezvousPoint) {
+        self.rendezvous.add_address(rendezvous_point);
+    }
+
+    /// Advertise ourselves as a seller at the rendezvous point.
+    pub fn register(
+        &mut self,
+        rendezvous_node: &PeerId,
+        env: Env,
+        addresses: Vec<Multiaddr>,
+        quote: BidQuote,
+    ) {
+        self.rendezvous
+            .register(rendezvous_node, env, addresses, quote);
+        debug!("Sent registration to {}", rendezvous_node);
+    }
 }
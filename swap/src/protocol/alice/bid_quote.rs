@@ -0,0 +1,196 @@
+use crate::{
+    jude,
+    network::request_response::{BidQuoteProtocol, CborCodec, TIMEOUT},
+    protocol::{alice::QuoteRejection, bob::BidQuoteRequest},
+};
+use anyhow::{anyhow, Error, Result};
+use libp2p::{
+    request_response::{
+        ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+        RequestResponseMessage, ResponseChannel,
+    },
+    NetworkBehaviour, PeerId,
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::debug;
+
+#[derive(Debug)]
+pub enum OutEvent {
+    MsgReceived {
+        channel: ResponseChannel<BidQuoteResponse>,
+        bob_peer_id: PeerId,
+    },
+    ResponseSent,
+    Failure(Error),
+}
+
+/// What Alice currently offers. Unlike a quote response it does not commit
+/// Alice to anything.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BidQuote {
+    /// The price of 1 jude for a swap of `min_buy`, larger swaps may get a
+    /// better price.
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    pub price: bitcoin::Amount,
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    pub min_buy: bitcoin::Amount,
+    /// The most jude Alice sells in a single swap, limited by her balance.
+    pub max_sell: jude::Amount,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BidQuoteResponse {
+    Quote(BidQuote),
+    Unavailable(QuoteRejection),
+}
+
+impl From<RequestResponseEvent<BidQuoteRequest, BidQuoteResponse>> for OutEvent {
+    fn from(event: RequestResponseEvent<BidQuoteRequest, BidQuoteResponse>) -> Self {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message: RequestResponseMessage::Request { channel, .. },
+                ..
+            } => {
+                debug!("Received bid quote request from {}", peer);
+                OutEvent::MsgReceived {
+                    channel,
+                    bob_peer_id: peer,
+                }
+            }
+            RequestResponseEvent::Message {
+                message: RequestResponseMessage::Response { .. },
+                ..
+            } => OutEvent::Failure(anyhow!("Alice should not get a Response")),
+            RequestResponseEvent::InboundFailure { error, .. } => {
+                OutEvent::Failure(anyhow!("Inbound failure: {:?}", error))
+            }
+            RequestResponseEvent::OutboundFailure { error, .. } => {
+                OutEvent::Failure(anyhow!("Outbound failure: {:?}", error))
+            }
+            RequestResponseEvent::ResponseSent { .. } => OutEvent::ResponseSent,
+        }
+    }
+}
+
+/// A `NetworkBehaviour` that answers price queries of Bob without starting a
+/// swap.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "OutEvent", event_process = false)]
+#[allow(missing_debug_implementations)]
+pub struct Behaviour {
+    rr: RequestResponse<CborCodec<BidQuoteProtocol, BidQuoteRequest, BidQuoteResponse>>,
+}
+
+impl Behaviour {
+    pub fn send(
+        &mut self,
+        channel: ResponseChannel<BidQuoteResponse>,
+        msg: BidQuoteResponse,
+    ) -> Result<()> {
+        self.rr
+            .send_response(channel, msg)
+            .map_err(|_| anyhow!("failed to send bid quote response"))?;
+
+        Ok(())
+    }
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        let timeout = Duration::from_secs(TIMEOUT);
+
+        let mut config = RequestResponseConfig::default();
+        config.set_request_timeout(timeout);
+
+        Self {
+            rr: RequestResponse::new(
+                CborCodec::default(),
+                vec![(BidQuoteProtocol, ProtocolSupport::Inbound)],
+                config,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        network::{transport, TokioExecutor},
+        protocol::bob::request_bid_quote,
+    };
+    use get_port::get_port;
+    use libp2p::{core::identity, swarm::SwarmBuilder, Multiaddr, Swarm};
+
+    /// Runs Alice answering every bid quote request with `response`.
+    fn spawn_alice(response: BidQuoteResponse) -> (PeerId, Multiaddr) {
+        let identity = identity::Keypair::generate_ed25519();
+        let peer_id = PeerId::from(identity.public());
+        let port = get_port().expect("Failed to find a free port");
+        let address: Multiaddr = format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap();
+
+        let mut swarm = SwarmBuilder::new(
+            transport::build(&identity, None).unwrap(),
+            Behaviour::default(),
+            peer_id,
+        )
+        .executor(Box::new(TokioExecutor {
+            handle: tokio::runtime::Handle::current(),
+        }))
+        .build();
+        Swarm::listen_on(&mut swarm, address.clone()).unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                if let OutEvent::MsgReceived { channel, .. } = swarm.next().await {
+                    swarm.send(channel, response).unwrap();
+                }
+            }
+        });
+
+        (peer_id, address)
+    }
+
+    #[tokio::test]
+    async fn bob_receives_the_bid_quote_of_alice() {
+        let quote = BidQuote {
+            price: bitcoin::Amount::from_sat(1_000),
+            min_buy: bitcoin::Amount::from_sat(100_000),
+            max_sell: jude::Amount::from_jude(10.0).unwrap(),
+        };
+        let (alice_peer_id, alice_addr) = spawn_alice(BidQuoteResponse::Quote(quote));
+
+        let received = request_bid_quote(
+            &identity::Keypair::generate_ed25519(),
+            None,
+            alice_peer_id,
+            alice_addr,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(received, quote);
+    }
+
+    #[tokio::test]
+    async fn bob_is_told_why_no_bid_quote_is_available() {
+        let (alice_peer_id, alice_addr) =
+            spawn_alice(BidQuoteResponse::Unavailable(QuoteRejection::NoLiquidity));
+
+        let error = request_bid_quote(
+            &identity::Keypair::generate_ed25519(),
+            None,
+            alice_peer_id,
+            alice_addr,
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            error.downcast_ref::<QuoteRejection>(),
+            Some(&QuoteRejection::NoLiquidity)
+        );
+    }
+}
@@ -8,7 +8,7 @@ use libp2p::{
         ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
         RequestResponseMessage, ResponseChannel,
     },
-    NetworkBehaviour,
+    NetworkBehaviour, PeerId,
 };
 use std::time::Duration;
 use tracing::debug;
@@ -16,6 +16,7 @@ use tracing::debug;
 #[derive(Debug)]
 pub enum OutEvent {
     MsgReceived {
+        bob_peer_id: PeerId,
         msg: EncryptedSignature,
         channel: ResponseChannel<()>,
     },
@@ -69,6 +70,7 @@ impl From<RequestResponseEvent<EncryptedSignature, ()>> for OutEvent {
             } => {
                 debug!("Received encrypted signature from {}", peer);
                 OutEvent::MsgReceived {
+                    bob_peer_id: peer,
                     msg: request,
                     channel,
                 }
@@ -1,15 +1,17 @@
 use crate::{
-    asb::LatestRate,
+    asb::{LatestRate, PeerLimits, QuoteBelowFlatFee, Rate, Spread},
     bitcoin,
     database::Database,
+    env::Env,
     execution_params::ExecutionParams,
     jude,
-    jude::{Amount, BalanceTooLow},
-    network::{transport, TokioExecutor},
+    jude::Amount,
+    network::{rendezvous::RendezvousPoint, transport, TokioExecutor},
     protocol::{
         alice,
         alice::{
-            AliceState, Behaviour, OutEvent, QuoteResponse, State0, State3, Swap, TransferProof,
+            AliceState, Behaviour, BidQuote, BidQuoteResponse, OutEvent, QuoteRejection,
+            QuoteResponse, State0, State3, Swap, TransferProof,
         },
         bob::{EncryptedSignature, QuoteRequest},
     },
@@ -21,11 +23,30 @@ use libp2p::{
     core::Multiaddr, futures::FutureExt, request_response::ResponseChannel, PeerId, Swarm,
 };
 use rand::rngs::OsRng;
-use std::sync::Arc;
-use tokio::sync::{broadcast, mpsc, mpsc::error::SendError};
-use tracing::{debug, error, info, trace};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc, mpsc::error::SendError};
+use tracing::{debug, error, info, trace, warn};
 use uuid::Uuid;
 
+/// How long to wait before registering with the rendezvous point again if a
+/// registration failed.
+const REGISTRATION_RETRY: Duration = Duration::from_secs(10);
+
+/// How often to give back the slots of execution setups that were not done in
+/// time, e.g. because Bob declined the quote.
+const SETUP_EXPIRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often to refresh the jude balance bid quotes are limited by, it is
+/// also refreshed whenever a swap finishes.
+const BALANCE_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long a peer has to wait for another bid quote.
+const BID_QUOTE_INTERVAL: Duration = Duration::from_secs(1);
+
 #[allow(missing_debug_implementations)]
 pub struct MpscChannels<T> {
     sender: mpsc::Sender<T>,
@@ -39,27 +60,11 @@ impl<T> Default for MpscChannels<T> {
     }
 }
 
-#[allow(missing_debug_implementations)]
-pub struct BroadcastChannels<T>
-where
-    T: Clone,
-{
-    sender: broadcast::Sender<T>,
-}
-
-impl<T> Default for BroadcastChannels<T>
-where
-    T: Clone,
-{
-    fn default() -> Self {
-        let (sender, _receiver) = broadcast::channel(100);
-        BroadcastChannels { sender }
-    }
-}
-
+/// The connection of a single swap to the event loop.
 #[derive(Debug)]
 pub struct EventLoopHandle {
-    recv_encrypted_signature: broadcast::Receiver<EncryptedSignature>,
+    swap_id: Uuid,
+    recv_encrypted_signature: mpsc::Receiver<EncryptedSignature>,
     send_transfer_proof: mpsc::Sender<(PeerId, TransferProof)>,
 }
 
@@ -70,7 +75,16 @@ impl EventLoopHandle {
             .await
             .context("Failed to receive Bitcoin encrypted signature from Bob")
     }
-    pub async fn send_transfer_proof(&mut self, bob: PeerId, msg: TransferProof) -> Result<()> {
+
+    pub async fn send_transfer_proof(
+        &mut self,
+        bob: PeerId,
+        tx_lock_proof: jude::TransferProof,
+    ) -> Result<()> {
+        let msg = TransferProof {
+            swap_id: self.swap_id,
+            tx_lock_proof,
+        };
         let _ = self.send_transfer_proof.send((bob, msg)).await?;
 
         Ok(())
@@ -86,17 +100,42 @@ pub struct EventLoop<RS> {
     jude_wallet: Arc<jude::Wallet>,
     db: Arc<Database>,
     rate_service: RS,
+    spread: Spread,
+    min_buy: bitcoin::Amount,
     max_sell: Amount,
-
-    recv_encrypted_signature: broadcast::Sender<EncryptedSignature>,
+    peer_limits: PeerLimits,
+    /// Where we advertise ourselves as a seller, if at all.
+    rendezvous: Option<Rendezvous>,
+    /// The last known jude balance, so that bid quotes do not wait for the
+    /// wallet. Quote requests still check the current balance.
+    jude_balance: Option<Amount>,
+    /// When peers last got a bid quote.
+    bid_quotes_sent: HashMap<PeerId, Instant>,
+
+    /// The encrypted signatures of Bob are sent to the swap they are for, if
+    /// they come from the Bob of that swap. Swaps resumed past locking the
+    /// bitcoin no longer know their Bob.
+    recv_encrypted_signature: HashMap<Uuid, (Option<PeerId>, mpsc::Sender<EncryptedSignature>)>,
     send_transfer_proof: mpsc::Receiver<(PeerId, TransferProof)>,
+    /// The swaps that finished, with the peer for those that count towards
+    /// the limits of the peer.
+    swap_finished: mpsc::Receiver<(Uuid, Option<PeerId>)>,
 
     // Only used to produce new handles
     send_transfer_proof_sender: mpsc::Sender<(PeerId, TransferProof)>,
+    swap_finished_sender: mpsc::Sender<(Uuid, Option<PeerId>)>,
 
     swap_handle_sender: mpsc::Sender<RemoteHandle<Result<AliceState>>>,
 }
 
+#[derive(Debug)]
+struct Rendezvous {
+    point: RendezvousPoint,
+    env: Env,
+    /// The addresses to advertise, never empty.
+    external_addresses: Vec<Multiaddr>,
+}
+
 impl<RS> EventLoop<RS>
 where
     RS: LatestRate,
@@ -110,11 +149,14 @@ where
         jude_wallet: Arc<jude::Wallet>,
         db: Arc<Database>,
         rate_service: RS,
+        spread: Spread,
+        min_buy: bitcoin::Amount,
         max_sell: Amount,
+        peer_limits: PeerLimits,
     ) -> Result<(Self, mpsc::Receiver<RemoteHandle<Result<AliceState>>>)> {
         let identity = seed.derive_libp2p_identity();
         let behaviour = Behaviour::default();
-        let transport = transport::build(&identity)?;
+        let transport = transport::build(&identity, None)?;
         let peer_id = PeerId::from(identity.public());
 
         let mut swarm = libp2p::swarm::SwarmBuilder::new(transport, behaviour, peer_id)
@@ -126,8 +168,8 @@ where
         Swarm::listen_on(&mut swarm, listen_address.clone())
             .with_context(|| format!("Address is not supported: {:#}", listen_address))?;
 
-        let recv_encrypted_signature = BroadcastChannels::default();
         let send_transfer_proof = MpscChannels::default();
+        let swap_finished = MpscChannels::default();
         let swap_handle = MpscChannels::default();
 
         let event_loop = EventLoop {
@@ -138,18 +180,58 @@ where
             jude_wallet,
             db,
             rate_service,
-            recv_encrypted_signature: recv_encrypted_signature.sender,
+            spread,
+            recv_encrypted_signature: HashMap::new(),
             send_transfer_proof: send_transfer_proof.receiver,
             send_transfer_proof_sender: send_transfer_proof.sender,
+            swap_finished: swap_finished.receiver,
+            swap_finished_sender: swap_finished.sender,
             swap_handle_sender: swap_handle.sender,
+            min_buy,
             max_sell,
+            peer_limits,
+            rendezvous: None,
+            jude_balance: None,
+            bid_quotes_sent: HashMap::new(),
         };
         Ok((event_loop, swap_handle.receiver))
     }
 
-    pub fn new_handle(&self) -> EventLoopHandle {
+    /// Registers with the rendezvous point once the event loop runs and keeps
+    /// the registration from expiring while it does. Our listen addresses are
+    /// usually not reachable by others, hence the addresses to advertise have
+    /// to be given.
+    pub fn register_with(
+        &mut self,
+        rendezvous_point: RendezvousPoint,
+        env: Env,
+        external_addresses: Vec<Multiaddr>,
+    ) -> Result<()> {
+        if external_addresses.is_empty() {
+            bail!(
+                "No addresses to advertise at rendezvous point {}",
+                rendezvous_point
+            )
+        }
+
+        self.swarm.add_rendezvous_point(&rendezvous_point);
+        self.rendezvous = Some(Rendezvous {
+            point: rendezvous_point,
+            env,
+            external_addresses,
+        });
+
+        Ok(())
+    }
+
+    pub fn new_handle(&mut self, swap_id: Uuid, bob_peer_id: Option<PeerId>) -> EventLoopHandle {
+        let recv_encrypted_signature = MpscChannels::default();
+        self.recv_encrypted_signature
+            .insert(swap_id, (bob_peer_id, recv_encrypted_signature.sender));
+
         EventLoopHandle {
-            recv_encrypted_signature: self.recv_encrypted_signature.subscribe(),
+            swap_id,
+            recv_encrypted_signature: recv_encrypted_signature.receiver,
             send_transfer_proof: self.send_transfer_proof_sender.clone(),
         }
     }
@@ -159,6 +241,16 @@ where
     }
 
     pub async fn run(mut self) {
+        // Completes right away so that we register as soon as we run.
+        let registration = tokio::time::sleep(Duration::from_secs(0));
+        tokio::pin!(registration);
+        let mut setup_expiry = tokio::time::interval(SETUP_EXPIRY_INTERVAL);
+        // The first tick completes right away, the balance is fetched before
+        // the first registration instead.
+        let mut balance_refresh = tokio::time::interval(BALANCE_REFRESH_INTERVAL);
+        balance_refresh.tick().await;
+        self.refresh_jude_balance().await;
+
         loop {
             tokio::select! {
                 swarm_event = self.swarm.next().fuse() => {
@@ -166,24 +258,64 @@ where
                         OutEvent::ConnectionEstablished(alice) => {
                             debug!("Connection Established with {}", alice);
                         }
+                        OutEvent::Disconnected(bob_peer_id) => {
+                            for swap_id in self.peer_limits.disconnected(bob_peer_id, Instant::now()) {
+                                debug!("Aborting execution setup of swap {} with {} after disconnect", swap_id, bob_peer_id);
+                                self.swarm.abort_execution_setup(bob_peer_id, swap_id);
+                            }
+                        }
                         OutEvent::QuoteRequest { msg, channel, bob_peer_id } => {
                             if let Err(error) = self.handle_quote_request(msg, channel, bob_peer_id, self.jude_wallet.clone()).await {
                                 error!("Failed to handle quote request: {:#}", error);
                             }
                         }
-                        OutEvent::ExecutionSetupDone{bob_peer_id, state3} => {
-                            let _ = self.handle_execution_setup_done(bob_peer_id, *state3).await;
+                        OutEvent::BidQuoteRequest { channel, bob_peer_id } => {
+                            let response = match self.limit_bid_quotes(bob_peer_id, Instant::now()).and_then(|()| self.bid_quote()) {
+                                Ok(quote) => BidQuoteResponse::Quote(quote),
+                                Err(rejection) => BidQuoteResponse::Unavailable(rejection),
+                            };
+                            if let Err(error) = self.swarm.send_bid_quote_response(channel, response) {
+                                error!("Failed to send bid quote to {}: {:#}", bob_peer_id, error);
+                            }
+                        }
+                        OutEvent::ExecutionSetupDone{bob_peer_id, swap_id, state3} => {
+                            if !self.peer_limits.setup_done(bob_peer_id, swap_id) {
+                                warn!("Not starting swap {} with {}, the execution setup was not done in time", swap_id, bob_peer_id);
+                            } else if let Err(error) = self.handle_execution_setup_done(bob_peer_id, swap_id, *state3).await {
+                                error!("Failed to start swap {}: {:#}", swap_id, error);
+                                self.peer_limits.finish(bob_peer_id, Instant::now());
+                            }
+                        }
+                        OutEvent::ExecutionSetupFailed{bob_peer_id, swap_id, error} => {
+                            error!("Execution setup with {} failed: {:#}", bob_peer_id, error);
+                            // Otherwise the slot is given back once the setup expires
+                            if let Some(swap_id) = swap_id {
+                                self.peer_limits.setup_failed(bob_peer_id, swap_id);
+                            }
                         }
                         OutEvent::TransferProofAcknowledged => {
                             trace!("Bob acknowledged transfer proof");
                         }
-                        OutEvent::EncryptedSignature{ msg, channel } => {
-                            let _ = self.recv_encrypted_signature.send(*msg);
-                            // Send back empty response so that the request/response protocol completes.
-                            if let Err(error) = self.swarm.send_encrypted_signature_ack(channel) {
-                                error!("Failed to send Encrypted Signature ack: {:?}", error);
+                        OutEvent::EncryptedSignature{ bob_peer_id, msg, channel } => {
+                            let swap_id = msg.swap_id;
+                            match self.recv_encrypted_signature.get(&swap_id) {
+                                Some((Some(expected), _)) if *expected != bob_peer_id => {
+                                    warn!("Ignoring encrypted signature for swap {} from {} instead of {}", swap_id, bob_peer_id, expected);
+                                }
+                                Some((_, sender)) => {
+                                    let _ = sender.send(*msg).await;
+                                    // Send back empty response so that the request/response protocol completes.
+                                    if let Err(error) = self.swarm.send_encrypted_signature_ack(channel) {
+                                        error!("Failed to send Encrypted Signature ack: {:?}", error);
+                                    }
+                                }
+                                None => warn!("Ignoring encrypted signature for unknown swap {}", swap_id),
                             }
                         }
+                        OutEvent::Registered { rendezvous_node, ttl } => {
+                            info!("Registered with rendezvous point {} for {}s", rendezvous_node, ttl.as_secs());
+                            registration.as_mut().reset(tokio::time::Instant::now() + ttl / 2);
+                        }
                         OutEvent::ResponseSent => {}
                         OutEvent::Failure(err) => {
                             error!("Communication error: {:#}", err);
@@ -195,8 +327,105 @@ where
                       self.swarm.send_transfer_proof(bob_peer_id, msg);
                     }
                 },
+                _ = setup_expiry.tick() => {
+                    let now = Instant::now();
+                    for (bob_peer_id, swap_id) in self.peer_limits.expire_setups(now) {
+                        info!("Execution setup of swap {} with {} was not done in time", swap_id, bob_peer_id);
+                        self.swarm.abort_execution_setup(bob_peer_id, swap_id);
+                    }
+                    self.bid_quotes_sent.retain(|_, sent_at| now.duration_since(*sent_at) < BID_QUOTE_INTERVAL);
+                },
+                _ = balance_refresh.tick() => {
+                    self.refresh_jude_balance().await;
+                },
+                _ = &mut registration, if self.rendezvous.is_some() => {
+                    // Pushed back once the rendezvous point confirms the registration.
+                    registration.as_mut().reset(tokio::time::Instant::now() + REGISTRATION_RETRY);
+                    if let Err(error) = self.register().await {
+                        warn!("Failed to register with rendezvous point: {:#}", error);
+                    }
+                },
+                swap_finished = self.swap_finished.recv().fuse() => {
+                    if let Some((swap_id, limited_peer)) = swap_finished {
+                        self.recv_encrypted_signature.remove(&swap_id);
+                        if let Some(bob_peer_id) = limited_peer {
+                            self.peer_limits.finish(bob_peer_id, Instant::now());
+                        }
+                        self.refresh_jude_balance().await;
+                    }
+                },
+            }
+        }
+    }
+
+    async fn register(&mut self) -> Result<()> {
+        let rendezvous = match &self.rendezvous {
+            Some(rendezvous) => rendezvous,
+            None => return Ok(()),
+        };
+        let rendezvous_node = rendezvous.point.peer_id;
+        let env = rendezvous.env;
+        let addresses = rendezvous.external_addresses.clone();
+
+        let quote = self.bid_quote().context("Failed to determine bid quote")?;
+        self.swarm.register(&rendezvous_node, env, addresses, quote);
+
+        Ok(())
+    }
+
+    async fn refresh_jude_balance(&mut self) {
+        match self.jude_wallet.get_balance().await {
+            Ok(balance) => self.jude_balance = Some(balance),
+            Err(error) => warn!("Failed to refresh jude balance: {:#}", error),
+        }
+    }
+
+    /// Rejects bid quote requests of peers that got a bid quote less than
+    /// [`BID_QUOTE_INTERVAL`] ago.
+    fn limit_bid_quotes(&mut self, peer: PeerId, now: Instant) -> Result<(), QuoteRejection> {
+        if let Some(sent_at) = self.bid_quotes_sent.get(&peer) {
+            if now.duration_since(*sent_at) < BID_QUOTE_INTERVAL {
+                return Err(QuoteRejection::RateLimited);
             }
         }
+        self.bid_quotes_sent.insert(peer, now);
+
+        Ok(())
+    }
+
+    /// Our current terms, we never offer to sell more than our last known
+    /// balance.
+    fn bid_quote(&mut self) -> Result<BidQuote, QuoteRejection> {
+        let rate = self.rate_service.latest_rate().map_err(|error| {
+            warn!("Failed to get latest rate: {:#}", error);
+            QuoteRejection::RateUnavailable
+        })?;
+        let price = self
+            .spread
+            .adjust(rate, self.min_buy)
+            .map_err(|error| {
+                warn!("Failed to quote {}: {:#}", self.min_buy, error);
+                QuoteRejection::RateUnavailable
+            })?
+            .ask;
+
+        let jude_balance = self.jude_balance.ok_or(QuoteRejection::NoLiquidity)?;
+        let jude_lock_fees = self.jude_wallet.static_tx_fee_estimate();
+
+        if jude_balance <= jude_lock_fees {
+            return Err(QuoteRejection::NoLiquidity);
+        }
+        let available = jude_balance - jude_lock_fees;
+
+        Ok(BidQuote {
+            price,
+            min_buy: self.min_buy,
+            max_sell: if available < self.max_sell {
+                available
+            } else {
+                self.max_sell
+            },
+        })
     }
 
     async fn handle_quote_request(
@@ -209,81 +438,172 @@ where
         // 1. Check if acceptable request
         // 2. Send response
 
-        let rate = self
-            .rate_service
-            .latest_rate()
-            .context("Failed to get latest rate")?;
-
+        let swap_id = quote_request.swap_id;
         let btc_amount = quote_request.btc_amount;
-        let jude_amount = rate.sell_quote(btc_amount)?;
 
-        if jude_amount > self.max_sell {
-            bail!(MaximumSellAmountExceeded {
-                actual: jude_amount,
-                max_sell: self.max_sell
-            })
+        if self.recv_encrypted_signature.contains_key(&swap_id)
+            || self.db.get_state(swap_id).is_ok()
+        {
+            return self.reject_quote_request(channel, bob_peer_id, QuoteRejection::SwapIdInUse);
         }
 
-        let jude_balance = jude_wallet.get_balance().await?;
+        let rate = self
+            .rate_service
+            .latest_rate()
+            .map_err(|error| warn!("Failed to get latest rate: {:#}", error))
+            .ok();
+
+        let (adjusted_rate, jude_amount) =
+            match sell_quote(rate, &self.spread, btc_amount, self.min_buy, self.max_sell) {
+                Ok(quote) => quote,
+                Err(rejection) => {
+                    return self.reject_quote_request(channel, bob_peer_id, rejection)
+                }
+            };
+
+        let jude_balance = match jude_wallet.get_balance().await {
+            Ok(balance) => balance,
+            Err(error) => {
+                warn!("Failed to get jude balance: {:#}", error);
+                return self.reject_quote_request(
+                    channel,
+                    bob_peer_id,
+                    QuoteRejection::NoLiquidity,
+                );
+            }
+        };
         let jude_lock_fees = jude_wallet.static_tx_fee_estimate();
 
         if jude_balance < jude_amount + jude_lock_fees {
-            bail!(BalanceTooLow {
-                balance: jude_balance
-            })
+            warn!(
+                "Balance of {} is too low to sell {}",
+                jude_balance, jude_amount
+            );
+            return self.reject_quote_request(channel, bob_peer_id, QuoteRejection::NoLiquidity);
         }
 
-        let quote_response = QuoteResponse { jude_amount };
+        if let Err(limit_exceeded) =
+            self.peer_limits
+                .try_start(bob_peer_id, swap_id, Instant::now())
+        {
+            warn!("Swap limit reached for {}: {}", bob_peer_id, limit_exceeded);
 
-        self.swarm
-            .send_quote_response(channel, quote_response)
-            .context("Failed to send quote response")?;
+            return self.reject_quote_request(channel, bob_peer_id, QuoteRejection::RateLimited);
+        }
+
+        let quote_response = QuoteResponse::Accepted { jude_amount };
+
+        if let Err(error) = self.swarm.send_quote_response(channel, quote_response) {
+            self.peer_limits.setup_failed(bob_peer_id, swap_id);
+            return Err(error.context("Failed to send quote response"));
+        }
 
         // 3. Start setup execution
 
-        let state0 = State0::new(
+        let state0 = match State0::new(
             btc_amount,
             jude_amount,
             self.execution_params,
             self.bitcoin_wallet.as_ref(),
             &mut OsRng,
         )
-        .await?;
+        .await
+        {
+            Ok(state0) => state0,
+            Err(error) => {
+                self.peer_limits.setup_failed(bob_peer_id, swap_id);
+                return Err(error);
+            }
+        };
 
         info!(
             "Starting execution setup to sell {} for {} (rate of {}) with {}",
-            jude_amount, btc_amount, rate, bob_peer_id
+            jude_amount, btc_amount, adjusted_rate, bob_peer_id
         );
 
-        self.swarm.start_execution_setup(bob_peer_id, state0);
+        self.swarm
+            .start_execution_setup(bob_peer_id, swap_id, state0);
         // Continues once the execution setup protocol is done
         Ok(())
     }
 
+    fn reject_quote_request(
+        &mut self,
+        channel: ResponseChannel<QuoteResponse>,
+        bob_peer_id: PeerId,
+        rejection: QuoteRejection,
+    ) -> Result<()> {
+        info!("Rejecting quote request of {}: {}", bob_peer_id, rejection);
+
+        self.swarm
+            .send_quote_response(channel, QuoteResponse::Rejected(rejection))
+            .context("Failed to send quote rejection")
+    }
+
     async fn handle_execution_setup_done(
         &mut self,
         bob_peer_id: PeerId,
+        swap_id: Uuid,
         state3: State3,
     ) -> Result<()> {
-        let swap_id = Uuid::new_v4();
-        let handle = self.new_handle();
+        // Bob may have set up another swap with the same id in the meantime.
+        if self.recv_encrypted_signature.contains_key(&swap_id) {
+            bail!("Swap {} is running already", swap_id)
+        }
 
         let initial_state = AliceState::Started {
             state3: Box::new(state3),
             bob_peer_id,
         };
 
-        let swap = Swap {
-            event_loop_handle: handle,
+        let swap = self.new_swap(swap_id, initial_state, Some(bob_peer_id));
+        self.spawn_swap(swap, Some(bob_peer_id)).await;
+
+        Ok(())
+    }
+
+    /// Resumes every swap in the database that has not finished yet. They
+    /// keep running alongside the swaps that are started afterwards.
+    pub async fn resume_unfinished_swaps(&mut self) -> Result<()> {
+        for (swap_id, state) in unfinished_swaps(&self.db)? {
+            info!("Resuming swap {} which is in state: {}", swap_id, state);
+
+            let bob_peer_id = match &state {
+                AliceState::Started { bob_peer_id, .. }
+                | AliceState::BtcLocked { bob_peer_id, .. } => Some(*bob_peer_id),
+                _ => None,
+            };
+            let swap = self.new_swap(swap_id, state, bob_peer_id);
+            self.spawn_swap(swap, None).await;
+        }
+
+        Ok(())
+    }
+
+    fn new_swap(&mut self, swap_id: Uuid, state: AliceState, bob_peer_id: Option<PeerId>) -> Swap {
+        Swap {
+            event_loop_handle: self.new_handle(swap_id, bob_peer_id),
             bitcoin_wallet: self.bitcoin_wallet.clone(),
             jude_wallet: self.jude_wallet.clone(),
             execution_params: self.execution_params,
             db: self.db.clone(),
-            state: initial_state,
+            state,
             swap_id,
-        };
+        }
+    }
 
-        let (swap, swap_handle) = alice::run(swap).remote_handle();
+    /// Runs the swap in the background. Only swaps that were set up by this
+    /// event loop count towards the limits of the peer, hence only for those
+    /// the peer is given.
+    async fn spawn_swap(&mut self, swap: Swap, limited_peer: Option<PeerId>) {
+        let swap_finished = self.swap_finished_sender.clone();
+        let swap_id = swap.swap_id;
+        let (swap, swap_handle) = async move {
+            let result = alice::run(swap).await;
+            let _ = swap_finished.send((swap_id, limited_peer)).await;
+            result
+        }
+        .remote_handle();
         tokio::spawn(swap);
 
         // For testing purposes the handle is currently sent via a channel so we can
@@ -293,14 +613,202 @@ where
         if let Err(SendError(handle)) = self.swap_handle_sender.send(swap_handle).await {
             handle.forget();
         }
+    }
+}
 
-        Ok(())
+/// The jude amount we sell for `btc_amount` and the rate it is based on, or
+/// why we do not sell.
+fn sell_quote(
+    rate: Option<Rate>,
+    spread: &Spread,
+    btc_amount: bitcoin::Amount,
+    min_buy: bitcoin::Amount,
+    max_sell: Amount,
+) -> Result<(Rate, Amount), QuoteRejection> {
+    if btc_amount < min_buy {
+        return Err(QuoteRejection::AmountTooLow {
+            min: min_buy,
+            requested: btc_amount,
+        });
+    }
+
+    let rate = rate.ok_or(QuoteRejection::RateUnavailable)?;
+
+    let quote = spread.adjust(rate, btc_amount).and_then(|adjusted_rate| {
+        let jude_amount = rate.sell_quote(btc_amount, spread)?;
+        Ok((adjusted_rate, jude_amount))
+    });
+    let (adjusted_rate, jude_amount) = match quote {
+        Ok(quote) => quote,
+        Err(error) => {
+            if let Some(below_flat_fee) = error.downcast_ref::<QuoteBelowFlatFee>() {
+                return Err(QuoteRejection::AmountTooLow {
+                    min: below_flat_fee.min_quote(),
+                    requested: btc_amount,
+                });
+            }
+
+            warn!("Failed to quote {}: {:#}", btc_amount, error);
+            return Err(QuoteRejection::RateUnavailable);
+        }
+    };
+
+    info!(
+        "Quoting {} for {} at market rate {} adjusted to {} with a flat fee of {}",
+        jude_amount, btc_amount, rate, adjusted_rate, spread.flat_fee
+    );
+
+    if jude_amount > max_sell {
+        return Err(QuoteRejection::AmountTooHigh {
+            max: max_sell,
+            requested: jude_amount,
+        });
     }
+
+    Ok((adjusted_rate, jude_amount))
 }
 
-#[derive(Debug, Clone, Copy, thiserror::Error)]
-#[error("The amount {actual} exceeds the configured maximum sell amount of {max_sell} jude")]
-pub struct MaximumSellAmountExceeded {
-    pub max_sell: Amount,
-    pub actual: Amount,
+/// The swaps in the database that have not reached a final state.
+pub fn unfinished_swaps(db: &Database) -> Result<Vec<(Uuid, AliceState)>> {
+    let mut unfinished = Vec::new();
+
+    for (swap_id, state) in db.all()? {
+        let state = AliceState::from(state.try_into_alice()?);
+
+        if !alice::swap::is_complete(&state) {
+            unfinished.push((swap_id, state));
+        }
+    }
+
+    Ok(unfinished)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{database::Alice, protocol::alice::fixtures};
+    use rust_decimal::Decimal;
+
+    fn rate() -> Option<Rate> {
+        Some(Rate {
+            ask: bitcoin::Amount::from_sat(100_000),
+        })
+    }
+
+    fn spread() -> Spread {
+        Spread {
+            percent: Decimal::from(0),
+            flat_fee: bitcoin::Amount::from_sat(10_000),
+            tiers: vec![],
+        }
+    }
+
+    fn sats(sats: u64) -> bitcoin::Amount {
+        bitcoin::Amount::from_sat(sats)
+    }
+
+    #[test]
+    fn quote_within_bounds_is_accepted() {
+        let (_, jude_amount) = sell_quote(
+            rate(),
+            &spread(),
+            sats(1_010_000),
+            sats(50_000),
+            Amount::from_jude(100.0).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(jude_amount, Amount::from_jude(10.0).unwrap());
+    }
+
+    #[test]
+    fn given_amount_below_min_buy_then_amount_too_low() {
+        let rejection = sell_quote(
+            None,
+            &spread(),
+            sats(40_000),
+            sats(50_000),
+            Amount::from_jude(100.0).unwrap(),
+        )
+        .unwrap_err();
+
+        assert_eq!(rejection, QuoteRejection::AmountTooLow {
+            min: sats(50_000),
+            requested: sats(40_000),
+        });
+    }
+
+    #[test]
+    fn given_amount_not_covering_the_flat_fee_then_amount_too_low() {
+        let rejection = sell_quote(
+            rate(),
+            &spread(),
+            sats(10_000),
+            sats(0),
+            Amount::from_jude(100.0).unwrap(),
+        )
+        .unwrap_err();
+
+        assert_eq!(rejection, QuoteRejection::AmountTooLow {
+            min: sats(10_001),
+            requested: sats(10_000),
+        });
+    }
+
+    #[test]
+    fn given_amount_above_max_sell_then_amount_too_high() {
+        let rejection = sell_quote(
+            rate(),
+            &spread(),
+            sats(1_010_000),
+            sats(50_000),
+            Amount::from_jude(5.0).unwrap(),
+        )
+        .unwrap_err();
+
+        assert_eq!(rejection, QuoteRejection::AmountTooHigh {
+            max: Amount::from_jude(5.0).unwrap(),
+            requested: Amount::from_jude(10.0).unwrap(),
+        });
+    }
+
+    #[test]
+    fn given_no_rate_then_rate_unavailable() {
+        let rejection = sell_quote(
+            None,
+            &spread(),
+            sats(1_010_000),
+            sats(50_000),
+            Amount::from_jude(100.0).unwrap(),
+        )
+        .unwrap_err();
+
+        assert_eq!(rejection, QuoteRejection::RateUnavailable);
+    }
+
+    #[tokio::test]
+    async fn only_unfinished_swaps_are_resumed() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+
+        let finished = Uuid::new_v4();
+        db.insert_latest_state(finished, Alice::from(&AliceState::BtcRedeemed).into())
+            .await
+            .unwrap();
+
+        let unfinished = Uuid::new_v4();
+        let state = AliceState::BtcLocked {
+            bob_peer_id: PeerId::random(),
+            state3: Box::new(fixtures::state3()),
+        };
+        db.insert_latest_state(unfinished, Alice::from(&state).into())
+            .await
+            .unwrap();
+
+        let swaps = unfinished_swaps(&db).unwrap();
+
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(swaps[0].0, unfinished);
+        assert!(matches!(swaps[0].1, AliceState::BtcLocked { .. }));
+    }
 }
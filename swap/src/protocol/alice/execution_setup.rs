@@ -13,6 +13,11 @@ use libp2p::PeerId;
 use libp2p_async_await::BehaviourOutEvent;
 use serde::{Deserialize, Serialize};
 use sigma_fun::ext::dl_secp256k1_ed25519_eq::CrossCurveDLEQProof;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use uuid::Uuid;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Message1 {
@@ -23,6 +28,7 @@ pub struct Message1 {
     pub(crate) v_a: jude::PrivateViewKey,
     pub(crate) redeem_address: bitcoin::Address,
     pub(crate) punish_address: bitcoin::Address,
+    pub(crate) tx_fees: bitcoin::TxFees,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -33,18 +39,41 @@ pub struct Message3 {
 
 #[derive(Debug)]
 pub enum OutEvent {
-    Done { bob_peer_id: PeerId, state3: State3 },
-    Failure(Error),
+    Done {
+        bob_peer_id: PeerId,
+        swap_id: Uuid,
+        state3: State3,
+    },
+    /// The swap is only known if Bob got as far as telling us which one the
+    /// setup is for.
+    Failure {
+        bob_peer_id: PeerId,
+        swap_id: Option<Uuid>,
+        error: Error,
+    },
 }
 
-impl From<BehaviourOutEvent<(PeerId, State3), (), Error>> for OutEvent {
-    fn from(event: BehaviourOutEvent<(PeerId, State3), (), Error>) -> Self {
+#[derive(Debug)]
+pub struct SetupFailed {
+    swap_id: Option<Uuid>,
+    error: Error,
+}
+
+impl From<BehaviourOutEvent<(PeerId, Uuid, State3), (), SetupFailed>> for OutEvent {
+    fn from(event: BehaviourOutEvent<(PeerId, Uuid, State3), (), SetupFailed>) -> Self {
         match event {
-            BehaviourOutEvent::Inbound(_, Ok((bob_peer_id, state3))) => OutEvent::Done {
+            BehaviourOutEvent::Inbound(_, Ok((bob_peer_id, swap_id, state3))) => OutEvent::Done {
                 bob_peer_id,
+                swap_id,
                 state3,
             },
-            BehaviourOutEvent::Inbound(_, Err(e)) => OutEvent::Failure(e),
+            BehaviourOutEvent::Inbound(bob_peer_id, Err(SetupFailed { swap_id, error })) => {
+                OutEvent::Failure {
+                    bob_peer_id,
+                    swap_id,
+                    error,
+                }
+            }
             BehaviourOutEvent::Outbound(..) => unreachable!("Alice only supports inbound"),
         }
     }
@@ -53,51 +82,100 @@ impl From<BehaviourOutEvent<(PeerId, State3), (), Error>> for OutEvent {
 #[derive(libp2p::NetworkBehaviour)]
 #[behaviour(out_event = "OutEvent", event_process = false)]
 pub struct Behaviour {
-    inner: libp2p_async_await::Behaviour<(PeerId, State3), (), anyhow::Error>,
+    inner: libp2p_async_await::Behaviour<(PeerId, Uuid, State3), (), SetupFailed>,
+    /// The initial states of the swaps whose quote was accepted, by the peer
+    /// and the id Bob chose for the swap.
+    #[behaviour(ignore)]
+    pending: Arc<Mutex<HashMap<(PeerId, Uuid), State0>>>,
 }
 
 impl Default for Behaviour {
     fn default() -> Self {
         Self {
-            inner: libp2p_async_await::Behaviour::new(b"/comit/jude/btc/execution_setup/1.0.0"),
+            inner: libp2p_async_await::Behaviour::new(b"/comit/jude/btc/execution_setup/2.0.0"),
+            pending: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 }
 
 impl Behaviour {
-    pub fn run(&mut self, bob: PeerId, state0: State0) {
+    /// Waits for Bob to set up the swap. Bob may set up several swaps at the
+    /// same time, the first message he sends says which one the substream is
+    /// for.
+    pub fn run(&mut self, bob: PeerId, swap_id: Uuid, state0: State0) {
+        self.pending
+            .lock()
+            .expect("lock is not poisoned")
+            .insert((bob, swap_id), state0);
+
+        let pending = self.pending.clone();
         self.inner
             .do_protocol_listener(bob, move |mut substream| async move {
-                let message0 =
-                    serde_cbor::from_slice::<Message0>(&substream.read_message(BUF_SIZE).await?)
-                        .context("failed to deserialize message0")?;
-                let state1 = state0.receive(message0)?;
-
-                substream
-                    .write_message(
-                        &serde_cbor::to_vec(&state1.next_message())
-                            .context("failed to serialize message1")?,
+                let message0 = async {
+                    let message0 = serde_cbor::from_slice::<Message0>(
+                        &substream.read_message(BUF_SIZE).await?,
                     )
-                    .await?;
+                    .context("failed to deserialize message0")?;
 
-                let message2 =
-                    serde_cbor::from_slice::<Message2>(&substream.read_message(BUF_SIZE).await?)
-                        .context("failed to deserialize message2")?;
-                let state2 = state1.receive(message2);
+                    Ok::<_, Error>(message0)
+                }
+                .await
+                .map_err(|error| SetupFailed {
+                    swap_id: None,
+                    error,
+                })?;
+                let swap_id = message0.swap_id;
 
-                substream
-                    .write_message(
-                        &serde_cbor::to_vec(&state2.next_message())
-                            .context("failed to serialize message3")?,
+                async {
+                    let state0 = pending
+                        .lock()
+                        .expect("lock is not poisoned")
+                        .remove(&(bob, swap_id))
+                        .with_context(|| format!("no quote was accepted for swap {}", swap_id))?;
+                    let state1 = state0.receive(message0)?;
+
+                    substream
+                        .write_message(
+                            &serde_cbor::to_vec(&state1.next_message())
+                                .context("failed to serialize message1")?,
+                        )
+                        .await?;
+
+                    let message2 = serde_cbor::from_slice::<Message2>(
+                        &substream.read_message(BUF_SIZE).await?,
                     )
-                    .await?;
+                    .context("failed to deserialize message2")?;
+                    let state2 = state1.receive(message2);
+
+                    substream
+                        .write_message(
+                            &serde_cbor::to_vec(&state2.next_message())
+                                .context("failed to serialize message3")?,
+                        )
+                        .await?;
 
-                let message4 =
-                    serde_cbor::from_slice::<Message4>(&substream.read_message(BUF_SIZE).await?)
-                        .context("failed to deserialize message4")?;
-                let state3 = state2.receive(message4)?;
+                    let message4 = serde_cbor::from_slice::<Message4>(
+                        &substream.read_message(BUF_SIZE).await?,
+                    )
+                    .context("failed to deserialize message4")?;
+                    let state3 = state2.receive(message4)?;
 
-                Ok((bob, state3))
+                    Ok((bob, swap_id, state3))
+                }
+                .await
+                .map_err(|error| SetupFailed {
+                    swap_id: Some(swap_id),
+                    error,
+                })
             })
     }
+
+    /// Forgets the initial state of a swap whose setup will not be done, a
+    /// setup Bob starts for it afterwards fails.
+    pub fn abort(&mut self, bob: PeerId, swap_id: Uuid) {
+        self.pending
+            .lock()
+            .expect("lock is not poisoned")
+            .remove(&(bob, swap_id));
+    }
 }
@@ -27,8 +27,34 @@ pub enum OutEvent {
 }
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
-pub struct QuoteResponse {
-    pub jude_amount: jude::Amount,
+pub enum QuoteResponse {
+    Accepted { jude_amount: jude::Amount },
+    Rejected(QuoteRejection),
+}
+
+/// Why Alice refuses to swap with Bob.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, thiserror::Error, PartialEq)]
+pub enum QuoteRejection {
+    #[error("The amount of {requested} is below the minimum of {min}")]
+    AmountTooLow {
+        #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+        min: bitcoin::Amount,
+        #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+        requested: bitcoin::Amount,
+    },
+    #[error("The amount of {requested} exceeds the maximum of {max}")]
+    AmountTooHigh {
+        max: jude::Amount,
+        requested: jude::Amount,
+    },
+    #[error("Not enough jude available to serve this swap")]
+    NoLiquidity,
+    #[error("No rate available, please try again later")]
+    RateUnavailable,
+    #[error("Too many swaps or requests with this peer, please try again later")]
+    RateLimited,
+    #[error("A swap with this id exists already")]
+    SwapIdInUse,
 }
 
 impl From<RequestResponseEvent<QuoteRequest, QuoteResponse>> for OutEvent {
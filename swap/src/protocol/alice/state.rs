@@ -83,6 +83,46 @@ impl fmt::Display for AliceState {
     }
 }
 
+impl AliceState {
+    /// The name of the state, part of the machine-readable output and hence
+    /// kept when a variant is renamed, as opposed to its `Display`
+    /// representation.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AliceState::Started { .. } => "Started",
+            AliceState::BtcLocked { .. } => "BtcLocked",
+            AliceState::judeLocked { .. } => "judeLocked",
+            AliceState::EncSigLearned { .. } => "EncSigLearned",
+            AliceState::BtcRedeemed => "BtcRedeemed",
+            AliceState::BtcCancelled { .. } => "BtcCancelled",
+            AliceState::BtcRefunded { .. } => "BtcRefunded",
+            AliceState::BtcPunishable { .. } => "BtcPunishable",
+            AliceState::judeRefunded => "judeRefunded",
+            AliceState::CancelTimelockExpired { .. } => "CancelTimelockExpired",
+            AliceState::BtcPunished => "BtcPunished",
+            AliceState::SafelyAborted => "SafelyAborted",
+        }
+    }
+
+    /// The negotiated swap, `None` once the swap is over.
+    pub fn state3(&self) -> Option<&State3> {
+        match self {
+            AliceState::Started { state3, .. }
+            | AliceState::BtcLocked { state3, .. }
+            | AliceState::judeLocked { state3, .. }
+            | AliceState::EncSigLearned { state3, .. }
+            | AliceState::BtcCancelled { state3, .. }
+            | AliceState::BtcRefunded { state3, .. }
+            | AliceState::BtcPunishable { state3, .. }
+            | AliceState::CancelTimelockExpired { state3, .. } => Some(state3.as_ref()),
+            AliceState::BtcRedeemed
+            | AliceState::judeRefunded
+            | AliceState::BtcPunished
+            | AliceState::SafelyAborted => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct State0 {
     pub a: bitcoin::SecretKey,
@@ -98,6 +138,8 @@ pub struct State0 {
     pub punish_timelock: PunishTimelock,
     pub redeem_address: bitcoin::Address,
     pub punish_address: bitcoin::Address,
+    pub tx_fees: bitcoin::TxFees,
+    pub max_tx_fees: bitcoin::TxFees,
 }
 
 impl State0 {
@@ -115,6 +157,8 @@ impl State0 {
         let v_a = jude::PrivateViewKey::new_random(rng);
         let redeem_address = bitcoin_wallet.new_address().await?;
         let punish_address = redeem_address.clone();
+        let tx_fees = bitcoin_wallet.estimate_tx_fees().await;
+        let max_tx_fees = bitcoin_wallet.max_tx_fees();
 
         let s_a = jude::Scalar::random(rng);
         let (dleq_proof_s_a, (S_a_bitcoin, S_a_jude)) = CROSS_CURVE_PROOF_SYSTEM.prove(&s_a, rng);
@@ -134,6 +178,8 @@ impl State0 {
             jude,
             cancel_timelock: execution_params.bitcoin_cancel_timelock,
             punish_timelock: execution_params.bitcoin_punish_timelock,
+            tx_fees,
+            max_tx_fees,
         })
     }
 
@@ -153,6 +199,14 @@ impl State0 {
             bail!("Bob's dleq proof doesn't verify")
         }
 
+        if msg.tx_fees.exceeds(&self.max_tx_fees) {
+            bail!(
+                "Transaction fees {:?} proposed by Bob exceed our maximum of {:?}",
+                msg.tx_fees,
+                self.max_tx_fees
+            )
+        }
+
         let v = self.v_a + msg.v_b;
 
         Ok(State1 {
@@ -173,6 +227,7 @@ impl State0 {
             refund_address: msg.refund_address,
             redeem_address: self.redeem_address,
             punish_address: self.punish_address,
+            tx_fees: self.tx_fees.agree(msg.tx_fees),
         })
     }
 }
@@ -197,6 +252,7 @@ pub struct State1 {
     refund_address: bitcoin::Address,
     redeem_address: bitcoin::Address,
     punish_address: bitcoin::Address,
+    tx_fees: bitcoin::TxFees,
 }
 
 impl State1 {
@@ -209,6 +265,7 @@ impl State1 {
             v_a: self.v_a,
             redeem_address: self.redeem_address.clone(),
             punish_address: self.punish_address.clone(),
+            tx_fees: self.tx_fees,
         }
     }
 
@@ -228,6 +285,7 @@ impl State1 {
             redeem_address: self.redeem_address,
             punish_address: self.punish_address,
             tx_lock: msg.tx_lock,
+            tx_fees: self.tx_fees,
         }
     }
 }
@@ -249,14 +307,21 @@ pub struct State2 {
     redeem_address: bitcoin::Address,
     punish_address: bitcoin::Address,
     tx_lock: bitcoin::TxLock,
+    tx_fees: bitcoin::TxFees,
 }
 
 impl State2 {
     pub fn next_message(&self) -> Message3 {
-        let tx_cancel =
-            bitcoin::TxCancel::new(&self.tx_lock, self.cancel_timelock, self.a.public(), self.B);
+        let tx_cancel = bitcoin::TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.a.public(),
+            self.B,
+            self.tx_fees.cancel,
+        );
 
-        let tx_refund = bitcoin::TxRefund::new(&tx_cancel, &self.refund_address);
+        let tx_refund =
+            bitcoin::TxRefund::new(&tx_cancel, &self.refund_address, self.tx_fees.refund);
         // Alice encsigns the refund transaction(bitcoin) digest with Bob's jude
         // pubkey(S_b). The refund transaction spends the output of
         // tx_lock_bitcoin to Bob's refund address.
@@ -272,12 +337,21 @@ impl State2 {
     }
 
     pub fn receive(self, msg: Message4) -> Result<State3> {
-        let tx_cancel =
-            bitcoin::TxCancel::new(&self.tx_lock, self.cancel_timelock, self.a.public(), self.B);
+        let tx_cancel = bitcoin::TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.a.public(),
+            self.B,
+            self.tx_fees.cancel,
+        );
         bitcoin::verify_sig(&self.B, &tx_cancel.digest(), &msg.tx_cancel_sig)
             .context("Failed to verify cancel transaction")?;
-        let tx_punish =
-            bitcoin::TxPunish::new(&tx_cancel, &self.punish_address, self.punish_timelock);
+        let tx_punish = bitcoin::TxPunish::new(
+            &tx_cancel,
+            &self.punish_address,
+            self.punish_timelock,
+            self.tx_fees.punish,
+        );
         bitcoin::verify_sig(&self.B, &tx_punish.digest(), &msg.tx_punish_sig)
             .context("Failed to verify punish transaction")?;
 
@@ -298,6 +372,7 @@ impl State2 {
             tx_lock: self.tx_lock,
             tx_punish_sig_bob: msg.tx_punish_sig,
             tx_cancel_sig_bob: msg.tx_cancel_sig,
+            tx_fees: self.tx_fees,
         })
     }
 }
@@ -321,6 +396,8 @@ pub struct State3 {
     pub tx_lock: bitcoin::TxLock,
     pub tx_punish_sig_bob: bitcoin::Signature,
     pub tx_cancel_sig_bob: bitcoin::Signature,
+    #[serde(default)]
+    pub tx_fees: bitcoin::TxFees,
 }
 
 impl State3 {
@@ -349,3 +426,67 @@ impl State3 {
         .await
     }
 }
+
+#[cfg(test)]
+pub mod fixtures {
+    //! Deterministic states for tests pinning how they are serialized.
+
+    use super::*;
+    use crate::bitcoin::TxPunish;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    pub fn state3() -> State3 {
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+
+        let a = bitcoin::SecretKey::new_random(&mut rng);
+        let b = bitcoin::SecretKey::new_random(&mut rng);
+        let s_a = jude::Scalar::random(&mut rng);
+        let s_b = jude::Scalar::random(&mut rng);
+        let S_b_bitcoin = bitcoin::SecretKey::new_random(&mut rng).public();
+        let v = jude::PrivateViewKey::new_random(&mut rng);
+
+        let btc = bitcoin::Amount::from_sat(100_000);
+        let cancel_timelock = CancelTimelock::new(12);
+        let punish_timelock = PunishTimelock::new(6);
+        let punish_address = address(3);
+        let tx_fees = bitcoin::TxFees::from_sat_per_vb(1.0);
+
+        let tx_lock = bitcoin::TxLock::new_for_tests(btc, a.public(), b.public());
+        let tx_cancel = TxCancel::new(
+            &tx_lock,
+            cancel_timelock,
+            a.public(),
+            b.public(),
+            tx_fees.cancel,
+        );
+        let tx_punish = TxPunish::new(&tx_cancel, &punish_address, punish_timelock, tx_fees.punish);
+
+        State3 {
+            B: b.public(),
+            s_a,
+            S_b_jude: jude::PublicKey::from_private_key(&jude::PrivateKey::from_scalar(s_b)),
+            S_b_bitcoin,
+            v,
+            btc,
+            jude: jude::Amount::from_piconero(1_000_000_000_000),
+            cancel_timelock,
+            punish_timelock,
+            refund_address: address(1),
+            redeem_address: address(2),
+            punish_address,
+            tx_lock,
+            tx_punish_sig_bob: b.sign(tx_punish.digest()),
+            tx_cancel_sig_bob: b.sign(tx_cancel.digest()),
+            tx_fees,
+            a,
+        }
+    }
+
+    fn address(index: u8) -> bitcoin::Address {
+        bitcoin::Address::p2wsh(
+            &::bitcoin::Script::from(vec![index]),
+            bitcoin::Network::Regtest,
+        )
+    }
+}
@@ -7,10 +7,7 @@ use crate::{
     execution_params::ExecutionParams,
     jude,
     jude::Transfer,
-    protocol::{
-        alice,
-        alice::{event_loop::EventLoopHandle, TransferProof},
-    },
+    protocol::{alice, alice::event_loop::EventLoopHandle},
 };
 use anyhow::{Context, Result};
 use ecdsa_fun::{
@@ -73,9 +70,7 @@ where
     //  Otherwise Alice might publish the lock tx twice!
 
     event_loop_handle
-        .send_transfer_proof(bob_peer_id, TransferProof {
-            tx_lock_proof: transfer_proof,
-        })
+        .send_transfer_proof(bob_peer_id, transfer_proof)
         .await?;
 
     Ok(())
@@ -101,10 +96,11 @@ pub fn build_bitcoin_redeem_transaction(
     s_a: ecdsa_fun::fun::Scalar,
     B: bitcoin::PublicKey,
     redeem_address: &bitcoin::Address,
+    tx_fees: bitcoin::TxFees,
 ) -> Result<bitcoin::Transaction> {
     let adaptor = Adaptor::<HashTranscript<Sha256>, Deterministic<Sha256>>::default();
 
-    let tx_redeem = bitcoin::TxRedeem::new(tx_lock, redeem_address);
+    let tx_redeem = bitcoin::TxRedeem::new(tx_lock, redeem_address, tx_fees.redeem);
 
     bitcoin::verify_encsig(
         B,
@@ -130,6 +126,7 @@ pub async fn publish_cancel_transaction(
     B: bitcoin::PublicKey,
     cancel_timelock: CancelTimelock,
     tx_cancel_sig_bob: bitcoin::Signature,
+    tx_fees: bitcoin::TxFees,
     bitcoin_wallet: Arc<bitcoin::Wallet>,
 ) -> Result<bitcoin::TxCancel> {
     // First wait for cancel timelock to expire
@@ -139,7 +136,8 @@ pub async fn publish_cancel_transaction(
     poll_until_block_height_is_gte(bitcoin_wallet.as_ref(), tx_lock_height + cancel_timelock)
         .await?;
 
-    let tx_cancel = bitcoin::TxCancel::new(&tx_lock, cancel_timelock, a.public(), B);
+    let tx_cancel =
+        bitcoin::TxCancel::new(&tx_lock, cancel_timelock, a.public(), B, tx_fees.cancel);
 
     // If Bob hasn't yet broadcasted the tx cancel, we do it
     if bitcoin_wallet
@@ -173,12 +171,13 @@ pub async fn wait_for_bitcoin_refund(
     cancel_tx_height: BlockHeight,
     punish_timelock: PunishTimelock,
     refund_address: &bitcoin::Address,
+    tx_fees: bitcoin::TxFees,
     bitcoin_wallet: &bitcoin::Wallet,
 ) -> Result<(bitcoin::TxRefund, Option<bitcoin::Transaction>)> {
     let punish_timelock_expired =
         poll_until_block_height_is_gte(bitcoin_wallet, cancel_tx_height + punish_timelock);
 
-    let tx_refund = bitcoin::TxRefund::new(tx_cancel, refund_address);
+    let tx_refund = bitcoin::TxRefund::new(tx_cancel, refund_address, tx_fees.refund);
 
     // TODO(Franck): This only checks the mempool, need to cater for the case where
     // the transaction goes directly in a block
@@ -216,6 +215,7 @@ pub fn extract_jude_private_key(
     Ok(spend_key)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn build_bitcoin_punish_transaction(
     tx_lock: &TxLock,
     cancel_timelock: CancelTimelock,
@@ -224,9 +224,12 @@ pub fn build_bitcoin_punish_transaction(
     tx_punish_sig_bob: bitcoin::Signature,
     a: bitcoin::SecretKey,
     B: bitcoin::PublicKey,
+    tx_fees: bitcoin::TxFees,
 ) -> Result<bitcoin::Transaction> {
-    let tx_cancel = bitcoin::TxCancel::new(&tx_lock, cancel_timelock, a.public(), B);
-    let tx_punish = bitcoin::TxPunish::new(&tx_cancel, &punish_address, punish_timelock);
+    let tx_cancel =
+        bitcoin::TxCancel::new(&tx_lock, cancel_timelock, a.public(), B, tx_fees.cancel);
+    let tx_punish =
+        bitcoin::TxPunish::new(&tx_cancel, &punish_address, punish_timelock, tx_fees.punish);
 
     let sig_a = a.sign(tx_punish.digest());
     let sig_b = tx_punish_sig_bob;
@@ -217,6 +217,7 @@ async fn run_until_internal(
                             state3.s_a.to_secpfun_scalar(),
                             state3.B,
                             &state3.redeem_address,
+                            state3.tx_fees,
                         ) {
                             Ok(tx) => match bitcoin_wallet.broadcast(tx, "redeem").await {
                                 Ok(txid) => {
@@ -287,6 +288,7 @@ async fn run_until_internal(
                     state3.B,
                     state3.cancel_timelock,
                     state3.tx_cancel_sig_bob.clone(),
+                    state3.tx_fees,
                     bitcoin_wallet.clone(),
                 )
                 .await?;
@@ -325,6 +327,7 @@ async fn run_until_internal(
                     tx_cancel_height,
                     state3.punish_timelock,
                     &state3.refund_address,
+                    state3.tx_fees,
                     &bitcoin_wallet,
                 )
                 .await?;
@@ -418,6 +421,7 @@ async fn run_until_internal(
                     state3.tx_punish_sig_bob.clone(),
                     state3.a.clone(),
                     state3.B,
+                    state3.tx_fees,
                 )?;
 
                 let punish_tx_finalised = async {
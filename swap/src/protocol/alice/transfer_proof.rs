@@ -12,9 +12,11 @@ use libp2p::{
 };
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
+use uuid::Uuid;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TransferProof {
+    pub swap_id: Uuid,
     pub tx_lock_proof: jude::TransferProof,
 }
 
@@ -15,17 +15,19 @@ use tracing::debug;
 use uuid::Uuid;
 
 pub use self::{
+    bid_quote::{request_bid_quote, BidQuoteRequest},
     cancel::cancel,
     encrypted_signature::EncryptedSignature,
-    event_loop::{EventLoop, EventLoopHandle},
+    event_loop::{EventLoop, EventLoopHandle, SwapRegistry},
     quote_request::*,
     refund::refund,
     state::*,
     swap::{run, run_until},
 };
 pub use execution_setup::{Message0, Message2, Message4};
-use libp2p::request_response::ResponseChannel;
+use libp2p::request_response::{RequestId, ResponseChannel};
 
+mod bid_quote;
 pub mod cancel;
 mod encrypted_signature;
 pub mod event_loop;
@@ -44,6 +46,7 @@ pub struct Swap {
     pub jude_wallet: Arc<jude::Wallet>,
     pub execution_params: ExecutionParams,
     pub swap_id: Uuid,
+    pub quote_check: QuoteCheck,
 }
 
 pub struct Builder {
@@ -55,6 +58,7 @@ pub struct Builder {
 
     init_params: InitParams,
     execution_params: ExecutionParams,
+    quote_check: QuoteCheck,
 
     event_loop_handle: bob::EventLoopHandle,
 }
@@ -81,6 +85,7 @@ impl Builder {
             jude_wallet,
             init_params: InitParams::None,
             execution_params,
+            quote_check: QuoteCheck::default(),
             event_loop_handle,
         }
     }
@@ -92,6 +97,14 @@ impl Builder {
         }
     }
 
+    /// Only applies to swaps that have not been set up with Alice yet.
+    pub fn with_quote_check(self, quote_check: QuoteCheck) -> Self {
+        Self {
+            quote_check,
+            ..self
+        }
+    }
+
     pub fn build(self) -> Result<bob::Swap> {
         let state = match self.init_params {
             InitParams::New { btc_amount } => BobState::Started { btc_amount },
@@ -106,6 +119,7 @@ impl Builder {
             jude_wallet: self.jude_wallet.clone(),
             swap_id: self.swap_id,
             execution_params: self.execution_params,
+            quote_check: self.quote_check,
         })
     }
 }
@@ -113,9 +127,25 @@ impl Builder {
 #[derive(Debug)]
 pub enum OutEvent {
     ConnectionEstablished(PeerId),
-    QuoteResponse(alice::QuoteResponse),
-    ExecutionSetupDone(Result<Box<State2>>),
+    Disconnected(PeerId),
+    QuoteResponse {
+        request_id: RequestId,
+        response: alice::QuoteResponse,
+    },
+    QuoteRequestFailed {
+        request_id: RequestId,
+        error: Error,
+    },
+    ExecutionSetupDone {
+        swap_id: Uuid,
+        result: Result<Box<State2>>,
+    },
+    ExecutionSetupFailed {
+        alice: PeerId,
+        error: Error,
+    },
     TransferProof {
+        peer: PeerId,
         msg: Box<TransferProof>,
         channel: ResponseChannel<()>,
     },
@@ -130,6 +160,7 @@ impl From<peer_tracker::OutEvent> for OutEvent {
             peer_tracker::OutEvent::ConnectionEstablished(id) => {
                 OutEvent::ConnectionEstablished(id)
             }
+            peer_tracker::OutEvent::Disconnected(id) => OutEvent::Disconnected(id),
         }
     }
 }
@@ -138,7 +169,17 @@ impl From<quote_request::OutEvent> for OutEvent {
     fn from(event: quote_request::OutEvent) -> Self {
         use quote_request::OutEvent::*;
         match event {
-            MsgReceived(quote_response) => OutEvent::QuoteResponse(quote_response),
+            MsgReceived {
+                request_id,
+                response,
+            } => OutEvent::QuoteResponse {
+                request_id,
+                response,
+            },
+            RequestFailed { request_id, error } => OutEvent::QuoteRequestFailed {
+                request_id,
+                error: error.context("Failure with Quote Request"),
+            },
             Failure(err) => OutEvent::CommunicationError(err.context("Failure with Quote Request")),
         }
     }
@@ -147,7 +188,14 @@ impl From<quote_request::OutEvent> for OutEvent {
 impl From<execution_setup::OutEvent> for OutEvent {
     fn from(event: execution_setup::OutEvent) -> Self {
         match event {
-            execution_setup::OutEvent::Done(res) => OutEvent::ExecutionSetupDone(res.map(Box::new)),
+            execution_setup::OutEvent::Done { swap_id, result } => OutEvent::ExecutionSetupDone {
+                swap_id,
+                result: result.map(Box::new),
+            },
+            execution_setup::OutEvent::Failed { alice, error } => OutEvent::ExecutionSetupFailed {
+                alice,
+                error: error.context("Failure with Execution Setup"),
+            },
         }
     }
 }
@@ -156,7 +204,8 @@ impl From<transfer_proof::OutEvent> for OutEvent {
     fn from(event: transfer_proof::OutEvent) -> Self {
         use transfer_proof::OutEvent::*;
         match event {
-            MsgReceived { msg, channel } => OutEvent::TransferProof {
+            MsgReceived { peer, msg, channel } => OutEvent::TransferProof {
+                peer,
                 msg: Box::new(msg),
                 channel,
             },
@@ -194,26 +243,31 @@ pub struct Behaviour {
 
 impl Behaviour {
     /// Sends a quote request to Alice to retrieve the rate.
-    pub fn send_quote_request(&mut self, alice: PeerId, quote_request: QuoteRequest) {
-        let _ = self.quote_request.send(alice, quote_request);
+    pub fn send_quote_request(&mut self, alice: PeerId, quote_request: QuoteRequest) -> RequestId {
+        self.quote_request.send(alice, quote_request)
     }
 
     pub fn start_execution_setup(
         &mut self,
+        swap_id: Uuid,
         alice_peer_id: PeerId,
         state0: State0,
         bitcoin_wallet: Arc<bitcoin::Wallet>,
     ) {
         self.execution_setup
-            .run(alice_peer_id, state0, bitcoin_wallet);
+            .run(swap_id, alice_peer_id, state0, bitcoin_wallet);
     }
 
     pub fn send_encrypted_signature(
         &mut self,
         alice: PeerId,
+        swap_id: Uuid,
         tx_redeem_encsig: bitcoin::EncryptedSignature,
     ) {
-        let msg = EncryptedSignature { tx_redeem_encsig };
+        let msg = EncryptedSignature {
+            swap_id,
+            tx_redeem_encsig,
+        };
         self.encrypted_signature.send(alice, msg);
         debug!("Encrypted signature sent");
     }
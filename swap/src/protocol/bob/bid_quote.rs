@@ -0,0 +1,122 @@
+use crate::{
+    network::{
+        request_response::{BidQuoteProtocol, CborCodec},
+        transport, TokioExecutor,
+    },
+    protocol::alice::{BidQuote, BidQuoteResponse},
+};
+use anyhow::{anyhow, Context, Error, Result};
+use libp2p::{
+    core::identity,
+    request_response::{
+        ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+        RequestResponseMessage,
+    },
+    swarm::SwarmBuilder,
+    Multiaddr, NetworkBehaviour, PeerId,
+};
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, time::Duration};
+
+/// Time to wait for Alice to answer, she does not have to do anything but
+/// look up her rate and balance.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Asks Alice for her current terms without starting a swap.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct BidQuoteRequest;
+
+#[derive(Debug)]
+pub enum OutEvent {
+    MsgReceived(BidQuoteResponse),
+    Failure(Error),
+}
+
+/// A `NetworkBehaviour` that asks Alice for her current terms.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "OutEvent", event_process = false)]
+#[allow(missing_debug_implementations)]
+pub struct Behaviour {
+    rr: RequestResponse<CborCodec<BidQuoteProtocol, BidQuoteRequest, BidQuoteResponse>>,
+}
+
+impl Behaviour {
+    pub fn send(&mut self, alice: PeerId, alice_addr: Multiaddr) {
+        self.rr.add_address(&alice, alice_addr);
+        let _ = self.rr.send_request(&alice, BidQuoteRequest);
+    }
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        let mut config = RequestResponseConfig::default();
+        config.set_request_timeout(TIMEOUT);
+
+        Self {
+            rr: RequestResponse::new(
+                CborCodec::default(),
+                vec![(BidQuoteProtocol, ProtocolSupport::Outbound)],
+                config,
+            ),
+        }
+    }
+}
+
+impl From<RequestResponseEvent<BidQuoteRequest, BidQuoteResponse>> for OutEvent {
+    fn from(event: RequestResponseEvent<BidQuoteRequest, BidQuoteResponse>) -> Self {
+        match event {
+            RequestResponseEvent::Message {
+                message: RequestResponseMessage::Request { .. },
+                ..
+            } => OutEvent::Failure(anyhow!("Bob should never get a request from Alice")),
+            RequestResponseEvent::Message {
+                message: RequestResponseMessage::Response { response, .. },
+                ..
+            } => OutEvent::MsgReceived(response),
+            RequestResponseEvent::InboundFailure { error, .. } => {
+                OutEvent::Failure(anyhow!("Inbound failure: {:?}", error))
+            }
+            RequestResponseEvent::OutboundFailure { error, .. } => {
+                OutEvent::Failure(anyhow!("Outbound failure: {:?}", error))
+            }
+            RequestResponseEvent::ResponseSent { .. } => {
+                OutEvent::Failure(anyhow!("Bob does not send a bid quote response to Alice"))
+            }
+        }
+    }
+}
+
+/// Asks Alice for her current terms over a connection of its own, so it does
+/// not need an event loop.
+pub async fn request_bid_quote(
+    identity: &identity::Keypair,
+    tor_socks5_proxy: Option<SocketAddr>,
+    alice_peer_id: PeerId,
+    alice_addr: Multiaddr,
+) -> Result<BidQuote> {
+    let transport = transport::build(identity, tor_socks5_proxy)?;
+    let peer_id = PeerId::from(identity.public());
+
+    let mut swarm = SwarmBuilder::new(transport, Behaviour::default(), peer_id)
+        .executor(Box::new(TokioExecutor {
+            handle: tokio::runtime::Handle::current(),
+        }))
+        .build();
+
+    swarm.send(alice_peer_id, alice_addr);
+
+    let response = match swarm.next().await {
+        OutEvent::MsgReceived(response) => response,
+        OutEvent::Failure(error) => {
+            return Err(error)
+                .with_context(|| format!("Failed to get bid quote of {}", alice_peer_id))
+        }
+    };
+
+    match response {
+        BidQuoteResponse::Quote(quote) => Ok(quote),
+        BidQuoteResponse::Unavailable(rejection) => {
+            Err(rejection).context("Alice does not offer any jude at the moment")
+        }
+    }
+}
@@ -1,3 +1,10 @@
+//! The event loop drives the network on behalf of all the swaps of a process.
+//!
+//! Swaps are registered with a [`SwapRegistry`] and talk to the event loop
+//! through their own [`EventLoopHandle`]. Every request a handle makes is
+//! tagged with the id of its swap, as are the messages Alice sends during the
+//! swap, so that they reach the right swap whether the swaps trade with the
+//! same Alice or with different ones.
 use crate::{
     bitcoin,
     bitcoin::EncryptedSignature,
@@ -7,12 +14,13 @@ use crate::{
         bob::{Behaviour, OutEvent, QuoteRequest, State0, State2},
     },
 };
-use anyhow::{anyhow, bail, Context, Result};
+use anyhow::{anyhow, Context, Result};
 use futures::FutureExt;
-use libp2p::{core::Multiaddr, PeerId};
-use std::{convert::Infallible, sync::Arc};
-use tokio::sync::mpsc::{Receiver, Sender};
-use tracing::{debug, error, trace};
+use libp2p::{core::Multiaddr, request_response::RequestId, PeerId};
+use std::{collections::HashMap, convert::Infallible, net::SocketAddr, sync::Arc};
+use tokio::sync::mpsc::{error::TrySendError, Receiver, Sender};
+use tracing::{debug, error, trace, warn};
+use uuid::Uuid;
 
 #[derive(Debug)]
 pub struct Channels<T> {
@@ -33,16 +41,95 @@ impl<T> Default for Channels<T> {
     }
 }
 
+/// What a swap asks the event loop to do.
+#[derive(Debug)]
+enum Request {
+    Register(Box<Swap>),
+    /// Sent once the handle of the registration is dropped.
+    Deregister {
+        registration: Uuid,
+    },
+    Dial,
+    QuoteRequest(QuoteRequest),
+    ExecutionSetup(Box<State0>),
+    EncryptedSignature(Box<EncryptedSignature>),
+}
+
+/// The event loop side of an [`EventLoopHandle`].
+#[derive(Debug)]
+struct Swap {
+    swap_id: Uuid,
+    /// Tells this registration apart from later ones of the same swap.
+    registration: Uuid,
+    alice_peer_id: PeerId,
+    alice_addr: Multiaddr,
+    recv_quote_response: Sender<Result<QuoteResponse>>,
+    done_execution_setup: Sender<Result<State2>>,
+    recv_transfer_proof: Sender<TransferProof>,
+    conn_established: Sender<Result<()>>,
+    awaits_connection: bool,
+    awaits_execution_setup: bool,
+}
+
+/// Registers swaps with a running [`EventLoop`].
+#[derive(Debug, Clone)]
+pub struct SwapRegistry {
+    requests: Sender<(Uuid, Request)>,
+}
+
+impl SwapRegistry {
+    /// Returns the handle through which the swap talks to Alice. Registering a
+    /// swap again replaces the handle it was registered with before.
+    pub async fn register(
+        &self,
+        swap_id: Uuid,
+        alice_peer_id: PeerId,
+        alice_addr: Multiaddr,
+    ) -> Result<EventLoopHandle> {
+        let recv_quote_response = Channels::new();
+        let done_execution_setup = Channels::new();
+        let recv_transfer_proof = Channels::new();
+        let conn_established = Channels::new();
+        let registration = Uuid::new_v4();
+
+        let swap = Swap {
+            swap_id,
+            registration,
+            alice_peer_id,
+            alice_addr,
+            recv_quote_response: recv_quote_response.sender,
+            done_execution_setup: done_execution_setup.sender,
+            recv_transfer_proof: recv_transfer_proof.sender,
+            conn_established: conn_established.sender,
+            awaits_connection: false,
+            awaits_execution_setup: false,
+        };
+        self.requests
+            .send((swap_id, Request::Register(Box::new(swap))))
+            .await
+            .map_err(|_| anyhow!("Event loop is not running"))?;
+
+        Ok(EventLoopHandle {
+            swap_id,
+            registration,
+            requests: self.requests.clone(),
+            recv_quote_response: recv_quote_response.receiver,
+            done_execution_setup: done_execution_setup.receiver,
+            recv_transfer_proof: recv_transfer_proof.receiver,
+            conn_established: conn_established.receiver,
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct EventLoopHandle {
-    recv_quote_response: Receiver<QuoteResponse>,
-    start_execution_setup: Sender<State0>,
+    swap_id: Uuid,
+    registration: Uuid,
+    requests: Sender<(Uuid, Request)>,
+    recv_quote_response: Receiver<Result<QuoteResponse>>,
     done_execution_setup: Receiver<Result<State2>>,
     recv_transfer_proof: Receiver<TransferProof>,
-    conn_established: Receiver<PeerId>,
-    dial_alice: Sender<()>,
-    send_quote_request: Sender<QuoteRequest>,
-    send_encrypted_signature: Sender<EncryptedSignature>,
+    conn_established: Receiver<Result<()>>,
 }
 
 impl EventLoopHandle {
@@ -50,11 +137,12 @@ impl EventLoopHandle {
         self.recv_quote_response
             .recv()
             .await
-            .ok_or_else(|| anyhow!("Failed to receive quote response from Alice"))
+            .ok_or_else(|| anyhow!("Failed to receive quote response from Alice"))?
     }
 
     pub async fn execution_setup(&mut self, state0: State0) -> Result<State2> {
-        let _ = self.start_execution_setup.send(state0).await?;
+        self.request(Request::ExecutionSetup(Box::new(state0)))
+            .await?;
 
         self.done_execution_setup
             .recv()
@@ -72,28 +160,48 @@ impl EventLoopHandle {
     /// Dials other party and wait for the connection to be established.
     /// Do nothing if we are already connected
     pub async fn dial(&mut self) -> Result<()> {
-        let _ = self.dial_alice.send(()).await?;
+        self.request(Request::Dial).await?;
 
         self.conn_established
             .recv()
             .await
-            .ok_or_else(|| anyhow!("Failed to receive connection established from Alice"))?;
-
-        Ok(())
+            .ok_or_else(|| anyhow!("Failed to receive connection established from Alice"))?
     }
 
     pub async fn send_quote_request(&mut self, quote_request: QuoteRequest) -> Result<()> {
-        let _ = self.send_quote_request.send(quote_request).await?;
-        Ok(())
+        self.request(Request::QuoteRequest(quote_request)).await
     }
 
     pub async fn send_encrypted_signature(
         &mut self,
         tx_redeem_encsig: EncryptedSignature,
     ) -> Result<()> {
-        self.send_encrypted_signature.send(tx_redeem_encsig).await?;
+        self.request(Request::EncryptedSignature(Box::new(tx_redeem_encsig)))
+            .await
+    }
 
-        Ok(())
+    async fn request(&self, request: Request) -> Result<()> {
+        self.requests
+            .send((self.swap_id, request))
+            .await
+            .map_err(|_| anyhow!("Event loop is not running"))
+    }
+}
+
+/// The event loop forgets the swap once the swap is over, that is once the
+/// swap drops its handle.
+impl Drop for EventLoopHandle {
+    fn drop(&mut self) {
+        let deregister = Request::Deregister {
+            registration: self.registration,
+        };
+        // Once the event loop is gone there is nothing left to forget.
+        if let Err(TrySendError::Full(_)) = self.requests.try_send((self.swap_id, deregister)) {
+            warn!(
+                "Failed to tell the event loop that swap {} is over",
+                self.swap_id
+            );
+        }
     }
 }
 
@@ -101,28 +209,24 @@ impl EventLoopHandle {
 pub struct EventLoop {
     swarm: libp2p::Swarm<Behaviour>,
     bitcoin_wallet: Arc<bitcoin::Wallet>,
-    alice_peer_id: PeerId,
-    recv_quote_response: Sender<QuoteResponse>,
-    start_execution_setup: Receiver<State0>,
-    done_execution_setup: Sender<Result<State2>>,
-    recv_transfer_proof: Sender<TransferProof>,
-    dial_alice: Receiver<()>,
-    conn_established: Sender<PeerId>,
-    send_quote_request: Receiver<QuoteRequest>,
-    send_encrypted_signature: Receiver<EncryptedSignature>,
+    /// Kept so that the channel stays open while no swap is registered.
+    requests_sender: Sender<(Uuid, Request)>,
+    requests: Receiver<(Uuid, Request)>,
+    /// The registered swaps, in the order they were registered in.
+    swaps: Vec<Swap>,
+    quote_requests: HashMap<RequestId, Uuid>,
 }
 
 impl EventLoop {
     pub fn new(
         identity: &libp2p::core::identity::Keypair,
-        alice_peer_id: PeerId,
-        alice_addr: Multiaddr,
+        tor_socks5_proxy: Option<SocketAddr>,
         bitcoin_wallet: Arc<bitcoin::Wallet>,
-    ) -> Result<(Self, EventLoopHandle)> {
+    ) -> Result<(Self, SwapRegistry)> {
         let behaviour = Behaviour::default();
-        let transport = transport::build(identity)?;
+        let transport = transport::build(identity, tor_socks5_proxy)?;
 
-        let mut swarm = libp2p::swarm::SwarmBuilder::new(
+        let swarm = libp2p::swarm::SwarmBuilder::new(
             transport,
             behaviour,
             identity.public().into_peer_id(),
@@ -132,105 +236,214 @@ impl EventLoop {
         }))
         .build();
 
-        swarm.add_address(alice_peer_id, alice_addr);
-
-        let quote_response = Channels::new();
-        let start_execution_setup = Channels::new();
-        let done_execution_setup = Channels::new();
-        let recv_transfer_proof = Channels::new();
-        let dial_alice = Channels::new();
-        let conn_established = Channels::new();
-        let send_quote_request = Channels::new();
-        let send_encrypted_signature = Channels::new();
+        let requests = Channels::new();
 
         let event_loop = EventLoop {
             swarm,
-            alice_peer_id,
             bitcoin_wallet,
-            recv_quote_response: quote_response.sender,
-            start_execution_setup: start_execution_setup.receiver,
-            done_execution_setup: done_execution_setup.sender,
-            recv_transfer_proof: recv_transfer_proof.sender,
-            conn_established: conn_established.sender,
-            dial_alice: dial_alice.receiver,
-            send_quote_request: send_quote_request.receiver,
-            send_encrypted_signature: send_encrypted_signature.receiver,
+            requests_sender: requests.sender,
+            requests: requests.receiver,
+            swaps: Vec::new(),
+            quote_requests: HashMap::new(),
         };
 
-        let handle = EventLoopHandle {
-            recv_quote_response: quote_response.receiver,
-            start_execution_setup: start_execution_setup.sender,
-            done_execution_setup: done_execution_setup.receiver,
-            recv_transfer_proof: recv_transfer_proof.receiver,
-            conn_established: conn_established.receiver,
-            dial_alice: dial_alice.sender,
-            send_quote_request: send_quote_request.sender,
-            send_encrypted_signature: send_encrypted_signature.sender,
+        let registry = SwapRegistry {
+            requests: event_loop.requests_sender.clone(),
         };
 
-        Ok((event_loop, handle))
+        Ok((event_loop, registry))
     }
 
     pub async fn run(mut self) -> Result<Infallible> {
         loop {
             tokio::select! {
                 swarm_event = self.swarm.next().fuse() => {
-                    match swarm_event {
-                        OutEvent::ConnectionEstablished(peer_id) => {
-                            let _ = self.conn_established.send(peer_id).await;
-                        }
-                        OutEvent::QuoteResponse(msg) => {
-                            let _ = self.recv_quote_response.send(msg).await;
-                        },
-                        OutEvent::ExecutionSetupDone(res) => {
-                            let _ = self.done_execution_setup.send(res.map(|state|*state)).await;
-                        }
-                        OutEvent::TransferProof{ msg, channel }=> {
-                            let _ = self.recv_transfer_proof.send(*msg).await;
-                            // Send back empty response so that the request/response protocol completes.
-                            if let Err(error) = self.swarm.transfer_proof.send_ack(channel) {
-                                error!("Failed to send Transfer Proof ack: {:?}", error);
-                            }
-                        }
-                        OutEvent::EncryptedSignatureAcknowledged => {
-                            debug!("Alice acknowledged encrypted signature");
-                        }
-                        OutEvent::ResponseSent => {}
-                        OutEvent::CommunicationError(err) => {
-                            bail!("Communication error: {:#}", err)
-                        }
-                    }
+                    self.handle_swarm_event(swarm_event).await;
                 },
-                option = self.dial_alice.recv().fuse() => {
-                    if option.is_some() {
-                           let peer_id = self.alice_peer_id;
-                        if self.swarm.pt.is_connected(&peer_id) {
-                            trace!("Already connected to Alice at {}", peer_id);
-                            let _ = self.conn_established.send(peer_id).await;
-                        } else {
-                            debug!("Dialing alice at {}", peer_id);
-                            libp2p::Swarm::dial(&mut self.swarm, &peer_id).context("failed to dial alice")?;
-                        }
+                request = self.requests.recv().fuse() => {
+                    if let Some((swap_id, request)) = request {
+                        self.handle_request(swap_id, request).await;
                     }
-                },
-                quote_request = self.send_quote_request.recv().fuse() =>  {
-                    if let Some(quote_request) = quote_request {
-                        self.swarm.send_quote_request(self.alice_peer_id, quote_request);
+                }
+            }
+        }
+    }
+
+    async fn handle_swarm_event(&mut self, event: OutEvent) {
+        match event {
+            OutEvent::ConnectionEstablished(peer_id) => {
+                for swap in self.swaps.iter_mut() {
+                    if swap.alice_peer_id == peer_id && swap.awaits_connection {
+                        swap.awaits_connection = false;
+                        let _ = swap.conn_established.send(Ok(())).await;
                     }
-                },
-                option = self.start_execution_setup.recv().fuse() => {
-                    if let Some(state0) = option {
-                        let _ = self
-                            .swarm
-                            .start_execution_setup(self.alice_peer_id, state0, self.bitcoin_wallet.clone());
+                }
+            }
+            OutEvent::Disconnected(peer_id) => {
+                debug!("Disconnected from {}", peer_id);
+            }
+            OutEvent::QuoteResponse {
+                request_id,
+                response,
+            } => {
+                if let Some(swap) = self.swap_of_quote_request(request_id) {
+                    let _ = swap.recv_quote_response.send(Ok(response)).await;
+                }
+            }
+            OutEvent::QuoteRequestFailed { request_id, error } => {
+                if let Some(swap) = self.swap_of_quote_request(request_id) {
+                    let _ = swap.recv_quote_response.send(Err(error)).await;
+                }
+            }
+            OutEvent::ExecutionSetupDone { swap_id, result } => {
+                if let Some(swap) = self.swap_mut(swap_id) {
+                    swap.awaits_execution_setup = false;
+                    let _ = swap
+                        .done_execution_setup
+                        .send(result.map(|state| *state))
+                        .await;
+                }
+            }
+            OutEvent::ExecutionSetupFailed { alice, error } => {
+                let error = format!("{:#}", error);
+                for swap in self.swaps.iter_mut() {
+                    if swap.alice_peer_id == alice && swap.awaits_execution_setup {
+                        swap.awaits_execution_setup = false;
+                        let _ = swap
+                            .done_execution_setup
+                            .send(Err(anyhow!(error.clone())))
+                            .await;
                     }
-                },
-                encrypted_signature = self.send_encrypted_signature.recv().fuse() => {
-                    if let Some(tx_redeem_encsig) = encrypted_signature {
-                        self.swarm.send_encrypted_signature(self.alice_peer_id, tx_redeem_encsig);
+                }
+            }
+            OutEvent::TransferProof { peer, msg, channel } => {
+                let swap = match self
+                    .swaps
+                    .iter()
+                    .find(|swap| swap.swap_id == msg.swap_id && swap.alice_peer_id == peer)
+                {
+                    Some(swap) => swap,
+                    None => {
+                        warn!(
+                            "Ignoring transfer proof from {} for unknown swap {}",
+                            peer, msg.swap_id
+                        );
+                        return;
                     }
+                };
+
+                let _ = swap.recv_transfer_proof.send(*msg).await;
+                // Send back empty response so that the request/response protocol completes.
+                if let Err(error) = self.swarm.transfer_proof.send_ack(channel) {
+                    error!("Failed to send Transfer Proof ack: {:?}", error);
                 }
             }
+            OutEvent::EncryptedSignatureAcknowledged => {
+                debug!("Alice acknowledged encrypted signature");
+            }
+            OutEvent::ResponseSent => {}
+            OutEvent::CommunicationError(err) => {
+                warn!("Communication error: {:#}", err)
+            }
         }
     }
+
+    async fn handle_request(&mut self, swap_id: Uuid, request: Request) {
+        if let Request::Register(swap) = request {
+            debug!("Registering swap {} with {}", swap_id, swap.alice_peer_id);
+            self.swarm
+                .add_address(swap.alice_peer_id, swap.alice_addr.clone());
+            self.swaps
+                .retain(|registered| registered.swap_id != swap_id);
+            self.swaps.push(*swap);
+            return;
+        }
+        if let Request::Deregister { registration } = request {
+            debug!("Deregistering swap {}", swap_id);
+            self.swaps
+                .retain(|registered| registered.registration != registration);
+            if !self.swaps.iter().any(|swap| swap.swap_id == swap_id) {
+                self.quote_requests
+                    .retain(|_, requesting_swap| *requesting_swap != swap_id);
+            }
+            return;
+        }
+
+        let index = match self.swaps.iter().position(|swap| swap.swap_id == swap_id) {
+            Some(index) => index,
+            None => {
+                error!(
+                    "Ignoring request of swap {} which is not registered",
+                    swap_id
+                );
+                return;
+            }
+        };
+        let alice = self.swaps[index].alice_peer_id;
+
+        match request {
+            Request::Register(_) | Request::Deregister { .. } => {
+                unreachable!("registrations are handled above")
+            }
+            Request::Dial => {
+                if self.swarm.pt.is_connected(&alice) {
+                    trace!("Already connected to Alice at {}", alice);
+                    let _ = self.swaps[index].conn_established.send(Ok(())).await;
+                    return;
+                }
+
+                let already_dialing = self
+                    .swaps
+                    .iter()
+                    .any(|swap| swap.alice_peer_id == alice && swap.awaits_connection);
+                if already_dialing {
+                    self.swaps[index].awaits_connection = true;
+                    return;
+                }
+
+                debug!("Dialing alice at {}", alice);
+                match libp2p::Swarm::dial(&mut self.swarm, &alice).context("failed to dial alice") {
+                    Ok(()) => self.swaps[index].awaits_connection = true,
+                    Err(error) => {
+                        let _ = self.swaps[index].conn_established.send(Err(error)).await;
+                    }
+                }
+            }
+            Request::QuoteRequest(quote_request) => {
+                let request_id = self.swarm.send_quote_request(alice, quote_request);
+                self.quote_requests.insert(request_id, swap_id);
+            }
+            Request::ExecutionSetup(state0) => {
+                self.swaps[index].awaits_execution_setup = true;
+                self.swarm.start_execution_setup(
+                    swap_id,
+                    alice,
+                    *state0,
+                    self.bitcoin_wallet.clone(),
+                );
+            }
+            Request::EncryptedSignature(tx_redeem_encsig) => {
+                self.swarm
+                    .send_encrypted_signature(alice, swap_id, *tx_redeem_encsig);
+            }
+        }
+    }
+
+    fn swap_mut(&mut self, swap_id: Uuid) -> Option<&mut Swap> {
+        let swap = self.swaps.iter_mut().find(|swap| swap.swap_id == swap_id);
+        if swap.is_none() {
+            debug!(
+                "Dropping message for swap {} which is not registered",
+                swap_id
+            );
+        }
+
+        swap
+    }
+
+    fn swap_of_quote_request(&mut self, request_id: RequestId) -> Option<&mut Swap> {
+        let swap_id = self.quote_requests.remove(&request_id)?;
+
+        self.swap_mut(swap_id)
+    }
 }
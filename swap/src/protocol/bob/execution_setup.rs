@@ -12,15 +12,18 @@ use libp2p_async_await::BehaviourOutEvent;
 use serde::{Deserialize, Serialize};
 use sigma_fun::ext::dl_secp256k1_ed25519_eq::CrossCurveDLEQProof;
 use std::sync::Arc;
+use uuid::Uuid;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Message0 {
+    pub(crate) swap_id: Uuid,
     pub(crate) B: crate::bitcoin::PublicKey,
     pub(crate) S_b_jude: jude::PublicKey,
     pub(crate) S_b_bitcoin: crate::bitcoin::PublicKey,
     pub(crate) dleq_proof_s_b: CrossCurveDLEQProof,
     pub(crate) v_b: crate::jude::PrivateViewKey,
     pub(crate) refund_address: bitcoin::Address,
+    pub(crate) tx_fees: crate::bitcoin::TxFees,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -36,14 +39,21 @@ pub struct Message4 {
 
 #[derive(Debug)]
 pub enum OutEvent {
-    Done(Result<State2>),
+    Done {
+        swap_id: Uuid,
+        result: Result<State2>,
+    },
+    /// The protocol failed before it was started for a particular swap.
+    Failed { alice: PeerId, error: Error },
 }
 
-impl From<BehaviourOutEvent<(), State2, anyhow::Error>> for OutEvent {
-    fn from(event: BehaviourOutEvent<(), State2, Error>) -> Self {
+impl From<BehaviourOutEvent<(), (Uuid, Result<State2>), anyhow::Error>> for OutEvent {
+    fn from(event: BehaviourOutEvent<(), (Uuid, Result<State2>), Error>) -> Self {
         match event {
-            BehaviourOutEvent::Outbound(_, Ok(State2)) => OutEvent::Done(Ok(State2)),
-            BehaviourOutEvent::Outbound(_, Err(e)) => OutEvent::Done(Err(e)),
+            BehaviourOutEvent::Outbound(_, Ok((swap_id, result))) => {
+                OutEvent::Done { swap_id, result }
+            }
+            BehaviourOutEvent::Outbound(alice, Err(error)) => OutEvent::Failed { alice, error },
             BehaviourOutEvent::Inbound(..) => unreachable!("Bob only supports outbound"),
         }
     }
@@ -52,60 +62,82 @@ impl From<BehaviourOutEvent<(), State2, anyhow::Error>> for OutEvent {
 #[derive(libp2p::NetworkBehaviour)]
 #[behaviour(out_event = "OutEvent", event_process = false)]
 pub struct Behaviour {
-    inner: libp2p_async_await::Behaviour<(), State2, anyhow::Error>,
+    inner: libp2p_async_await::Behaviour<(), (Uuid, Result<State2>), anyhow::Error>,
 }
 
 impl Default for Behaviour {
     fn default() -> Self {
         Self {
-            inner: libp2p_async_await::Behaviour::new(b"/comit/jude/btc/execution_setup/1.0.0"),
+            inner: libp2p_async_await::Behaviour::new(b"/comit/jude/btc/execution_setup/2.0.0"),
         }
     }
 }
 
 impl Behaviour {
+    /// Sets up the swap with Alice, the outcome is reported together with the
+    /// id of the swap so that several setups can run at the same time.
     pub fn run(
         &mut self,
+        swap_id: Uuid,
         alice: PeerId,
         state0: State0,
         bitcoin_wallet: Arc<crate::bitcoin::Wallet>,
     ) {
         self.inner
             .do_protocol_dialer(alice, move |mut substream| async move {
-                tracing::debug!("Starting execution setup with {}", alice);
-
-                substream
-                    .write_message(
-                        &serde_cbor::to_vec(&state0.next_message())
-                            .context("failed to serialize message0")?,
-                    )
-                    .await?;
+                let result: Result<State2> = async {
+                    tracing::debug!("Starting execution setup with {}", alice);
 
-                let message1 =
-                    serde_cbor::from_slice::<Message1>(&substream.read_message(BUF_SIZE).await?)
-                        .context("failed to deserialize message1")?;
-                let state1 = state0.receive(bitcoin_wallet.as_ref(), message1).await?;
+                    substream
+                        .write_message(
+                            &serde_cbor::to_vec(&state0.next_message())
+                                .context("failed to serialize message0")?,
+                        )
+                        .await?;
 
-                substream
-                    .write_message(
-                        &serde_cbor::to_vec(&state1.next_message())
-                            .context("failed to serialize message2")?,
+                    let message1 = serde_cbor::from_slice::<Message1>(
+                        &substream.read_message(BUF_SIZE).await?,
                     )
-                    .await?;
+                    .context("failed to deserialize message1")?;
+                    let state1 = state0.receive(bitcoin_wallet.as_ref(), message1).await?;
+                    let tx_lock = state1.tx_lock().clone();
+
+                    let result: Result<State2> = async {
+                        substream
+                            .write_message(
+                                &serde_cbor::to_vec(&state1.next_message())
+                                    .context("failed to serialize message2")?,
+                            )
+                            .await?;
 
-                let message3 =
-                    serde_cbor::from_slice::<Message3>(&substream.read_message(BUF_SIZE).await?)
+                        let message3 = serde_cbor::from_slice::<Message3>(
+                            &substream.read_message(BUF_SIZE).await?,
+                        )
                         .context("failed to deserialize message3")?;
-                let state2 = state1.receive(message3)?;
+                        let state2 = state1.receive(message3)?;
 
-                substream
-                    .write_message(
-                        &serde_cbor::to_vec(&state2.next_message())
-                            .context("failed to serialize message4")?,
-                    )
-                    .await?;
+                        substream
+                            .write_message(
+                                &serde_cbor::to_vec(&state2.next_message())
+                                    .context("failed to serialize message4")?,
+                            )
+                            .await?;
+
+                        Ok(state2)
+                    }
+                    .await;
+
+                    // The lock transaction will not be published, its inputs can
+                    // fund other swaps
+                    if result.is_err() {
+                        bitcoin_wallet.release(tx_lock.into()).await;
+                    }
+
+                    result
+                }
+                .await;
 
-                Ok(state2)
+                Ok((swap_id, result))
             })
     }
 }
@@ -1,4 +1,5 @@
 use crate::{
+    jude,
     network::request_response::{CborCodec, Swap, TIMEOUT},
     protocol::alice::QuoteResponse,
 };
@@ -11,18 +12,118 @@ use libp2p::{
     NetworkBehaviour, PeerId,
 };
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::{convert::TryFrom, fmt, sync::Arc, time::Duration};
 use tracing::debug;
+use uuid::Uuid;
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct QuoteRequest {
+    /// Chosen by Bob, Alice uses the same id for the swap if she accepts and
+    /// every later message of the swap refers to it.
+    pub swap_id: Uuid,
     #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
     pub btc_amount: bitcoin::Amount,
 }
 
+/// What Alice offers in exchange for Bob's BTC.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quote {
+    pub btc_amount: bitcoin::Amount,
+    pub jude_amount: jude::Amount,
+}
+
+impl Quote {
+    /// The price Bob pays for 1 jude, `None` if Alice offers no jude at all.
+    pub fn rate(&self) -> Option<bitcoin::Amount> {
+        let piconero = u128::from(self.jude_amount.as_piconero());
+        let sats = u128::from(self.btc_amount.as_sat()) * u128::from(jude::PICONERO_OFFSET);
+
+        let rate = sats.checked_div(piconero)?;
+
+        u64::try_from(rate).ok().map(bitcoin::Amount::from_sat)
+    }
+}
+
+/// Asks the user whether to accept a quote, returning `false` if declined.
+pub type ConfirmQuote = Arc<dyn Fn(Quote) -> Result<bool> + Send + Sync>;
+
+/// Decides whether Bob accepts Alice's quote, which happens before any funds
+/// are committed to the swap.
+#[derive(Clone, Default)]
+pub struct QuoteCheck {
+    pub min_jude: Option<jude::Amount>,
+    /// The maximum price in BTC Bob is willing to pay for 1 jude.
+    pub max_rate: Option<bitcoin::Amount>,
+    pub confirm: Option<ConfirmQuote>,
+}
+
+impl fmt::Debug for QuoteCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QuoteCheck")
+            .field("min_jude", &self.min_jude)
+            .field("max_rate", &self.max_rate)
+            .field("confirm", &self.confirm.is_some())
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy, Debug, thiserror::Error, PartialEq)]
+pub enum QuoteNotAccepted {
+    #[error("Alice offers {offered} which is less than the minimum of {min}")]
+    TooLittlejude {
+        min: jude::Amount,
+        offered: jude::Amount,
+    },
+    #[error("Alice asks for {offered} per jude which is more than the maximum of {max}")]
+    RateTooHigh {
+        max: bitcoin::Amount,
+        offered: bitcoin::Amount,
+    },
+    #[error("Alice offers no jude")]
+    Nojude,
+    #[error("Quote declined")]
+    Declined,
+}
+
+impl QuoteCheck {
+    pub fn check(&self, quote: Quote) -> Result<()> {
+        let rate = quote.rate().ok_or(QuoteNotAccepted::Nojude)?;
+
+        if let Some(min) = self.min_jude {
+            if quote.jude_amount < min {
+                return Err(QuoteNotAccepted::TooLittlejude {
+                    min,
+                    offered: quote.jude_amount,
+                }
+                .into());
+            }
+        }
+        if let Some(max) = self.max_rate {
+            if rate > max {
+                return Err(QuoteNotAccepted::RateTooHigh { max, offered: rate }.into());
+            }
+        }
+        if let Some(confirm) = &self.confirm {
+            if !confirm(quote)? {
+                return Err(QuoteNotAccepted::Declined.into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub enum OutEvent {
-    MsgReceived(QuoteResponse),
+    MsgReceived {
+        request_id: RequestId,
+        response: QuoteResponse,
+    },
+    /// The request could not be sent or was not answered.
+    RequestFailed {
+        request_id: RequestId,
+        error: Error,
+    },
     Failure(Error),
 }
 
@@ -35,12 +136,10 @@ pub struct Behaviour {
 }
 
 impl Behaviour {
-    pub fn send(&mut self, alice: PeerId, quote_request: QuoteRequest) -> Result<RequestId> {
+    pub fn send(&mut self, alice: PeerId, quote_request: QuoteRequest) -> RequestId {
         debug!("Requesting quote for {}", quote_request.btc_amount);
 
-        let id = self.rr.send_request(&alice, quote_request);
-
-        Ok(id)
+        self.rr.send_request(&alice, quote_request)
     }
 }
 
@@ -69,18 +168,76 @@ impl From<RequestResponseEvent<QuoteRequest, QuoteResponse>> for OutEvent {
                 ..
             } => OutEvent::Failure(anyhow!("Bob should never get a request from Alice")),
             RequestResponseEvent::Message {
-                message: RequestResponseMessage::Response { response, .. },
+                message:
+                    RequestResponseMessage::Response {
+                        request_id,
+                        response,
+                    },
                 ..
-            } => OutEvent::MsgReceived(response),
+            } => OutEvent::MsgReceived {
+                request_id,
+                response,
+            },
             RequestResponseEvent::InboundFailure { error, .. } => {
                 OutEvent::Failure(anyhow!("Inbound failure: {:?}", error))
             }
-            RequestResponseEvent::OutboundFailure { error, .. } => {
-                OutEvent::Failure(anyhow!("Outbound failure: {:?}", error))
-            }
+            RequestResponseEvent::OutboundFailure {
+                request_id, error, ..
+            } => OutEvent::RequestFailed {
+                request_id,
+                error: anyhow!("Outbound failure: {:?}", error),
+            },
             RequestResponseEvent::ResponseSent { .. } => {
                 OutEvent::Failure(anyhow!("Bob does not send a quote response to Alice"))
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote() -> Quote {
+        Quote {
+            btc_amount: bitcoin::Amount::from_btc(0.5).unwrap(),
+            jude_amount: jude::Amount::from_jude(100.0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn rate_is_price_of_one_jude() {
+        assert_eq!(quote().rate(), Some(bitcoin::Amount::from_sat(500_000)));
+    }
+
+    #[test]
+    fn given_quote_within_limits_then_accepted() {
+        let check = QuoteCheck {
+            min_jude: Some(jude::Amount::from_jude(100.0).unwrap()),
+            max_rate: Some(bitcoin::Amount::from_sat(500_000)),
+            confirm: None,
+        };
+
+        assert!(check.check(quote()).is_ok());
+    }
+
+    #[test]
+    fn given_quote_outside_limits_then_not_accepted() {
+        let too_little_jude = QuoteCheck {
+            min_jude: Some(jude::Amount::from_jude(101.0).unwrap()),
+            ..QuoteCheck::default()
+        };
+        let rate_too_high = QuoteCheck {
+            max_rate: Some(bitcoin::Amount::from_sat(499_999)),
+            ..QuoteCheck::default()
+        };
+        let declined = QuoteCheck {
+            confirm: Some(Arc::new(|_| Ok(false))),
+            ..QuoteCheck::default()
+        };
+
+        assert!(too_little_jude.check(quote()).is_err());
+        assert!(rate_too_high.check(quote()).is_err());
+        assert!(declined.check(quote()).is_err());
+    }
+}
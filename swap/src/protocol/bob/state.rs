@@ -9,7 +9,7 @@ use crate::{
     jude_ext::ScalarExt,
     protocol::{
         alice::{Message1, Message3},
-        bob::{EncryptedSignature, Message0, Message2, Message4},
+        bob::{Message0, Message2, Message4},
         CROSS_CURVE_PROOF_SYSTEM,
     },
 };
@@ -25,6 +25,7 @@ use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 use sigma_fun::ext::dl_secp256k1_ed25519_eq::CrossCurveDLEQProof;
 use std::fmt;
+use uuid::Uuid;
 
 #[derive(Debug, Clone)]
 pub enum BobState {
@@ -75,8 +76,153 @@ impl fmt::Display for BobState {
     }
 }
 
+impl BobState {
+    /// The name of the state, part of the machine-readable output and hence
+    /// kept when a variant is renamed, as opposed to its `Display`
+    /// representation.
+    pub fn name(&self) -> &'static str {
+        match self {
+            BobState::Started { .. } => "Started",
+            BobState::ExecutionSetupDone(..) => "ExecutionSetupDone",
+            BobState::BtcLocked(..) => "BtcLocked",
+            BobState::judeLockProofReceived { .. } => "judeLockProofReceived",
+            BobState::judeLocked(..) => "judeLocked",
+            BobState::EncSigSent(..) => "EncSigSent",
+            BobState::BtcRedeemed(..) => "BtcRedeemed",
+            BobState::CancelTimelockExpired(..) => "CancelTimelockExpired",
+            BobState::BtcCancelled(..) => "BtcCancelled",
+            BobState::BtcRefunded(..) => "BtcRefunded",
+            BobState::judeRedeemed { .. } => "judeRedeemed",
+            BobState::BtcPunished { .. } => "BtcPunished",
+            BobState::SafelyAborted => "SafelyAborted",
+        }
+    }
+
+    /// The amount of BTC locked, or to be locked, by Bob.
+    ///
+    /// `None` once the swap is over because the final states only record
+    /// the id of the lock transaction.
+    pub fn btc_amount(&self) -> Option<bitcoin::Amount> {
+        match self {
+            BobState::Started { btc_amount } => Some(*btc_amount),
+            BobState::ExecutionSetupDone(state2) => Some(state2.tx_lock.lock_amount()),
+            BobState::BtcLocked(state3) | BobState::judeLockProofReceived { state: state3, .. } => {
+                Some(state3.tx_lock.lock_amount())
+            }
+            BobState::judeLocked(state4)
+            | BobState::EncSigSent(state4)
+            | BobState::CancelTimelockExpired(state4)
+            | BobState::BtcCancelled(state4)
+            | BobState::BtcRefunded(state4) => Some(state4.tx_lock.lock_amount()),
+            BobState::BtcRedeemed(state5) => Some(state5.tx_lock.lock_amount()),
+            BobState::judeRedeemed { .. }
+            | BobState::BtcPunished { .. }
+            | BobState::SafelyAborted => None,
+        }
+    }
+
+    /// The amount of jude Alice agreed to lock.
+    ///
+    /// Only known until Bob saw the jude being locked, later states do not
+    /// record it anymore.
+    pub fn jude_amount(&self) -> Option<jude::Amount> {
+        match self {
+            BobState::ExecutionSetupDone(state2) => Some(state2.jude),
+            BobState::BtcLocked(state3) | BobState::judeLockProofReceived { state: state3, .. } => {
+                Some(state3.jude)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn tx_lock_id(&self) -> Option<bitcoin::Txid> {
+        match self {
+            BobState::Started { .. } | BobState::SafelyAborted => None,
+            BobState::ExecutionSetupDone(state2) => Some(state2.tx_lock.txid()),
+            BobState::BtcLocked(state3) | BobState::judeLockProofReceived { state: state3, .. } => {
+                Some(state3.tx_lock_id())
+            }
+            BobState::judeLocked(state4)
+            | BobState::EncSigSent(state4)
+            | BobState::CancelTimelockExpired(state4)
+            | BobState::BtcCancelled(state4)
+            | BobState::BtcRefunded(state4) => Some(state4.tx_lock_id()),
+            BobState::BtcRedeemed(state5) => Some(state5.tx_lock_id()),
+            BobState::judeRedeemed { tx_lock_id } | BobState::BtcPunished { tx_lock_id } => {
+                Some(*tx_lock_id)
+            }
+        }
+    }
+
+    /// The id of the cancel transaction, once it was published.
+    pub fn tx_cancel_id(&self) -> Option<bitcoin::Txid> {
+        match self {
+            BobState::BtcCancelled(state4) | BobState::BtcRefunded(state4) => {
+                Some(state4.tx_cancel().txid())
+            }
+            _ => None,
+        }
+    }
+
+    /// The id of the refund transaction, once it was published.
+    pub fn tx_refund_id(&self) -> Option<bitcoin::Txid> {
+        match self {
+            BobState::BtcRefunded(state4) => Some(state4.tx_refund().txid()),
+            _ => None,
+        }
+    }
+
+    /// The id of the transaction Alice publishes to redeem the BTC, as long
+    /// as she still can.
+    pub fn tx_redeem_id(&self) -> Option<bitcoin::Txid> {
+        match self {
+            BobState::judeLocked(state4) | BobState::EncSigSent(state4) => Some(
+                bitcoin::TxRedeem::new(
+                    &state4.tx_lock,
+                    &state4.redeem_address,
+                    state4.tx_fees.redeem,
+                )
+                .txid(),
+            ),
+            _ => None,
+        }
+    }
+
+    pub fn timelocks(&self) -> Option<(CancelTimelock, PunishTimelock)> {
+        match self {
+            BobState::ExecutionSetupDone(state2) => {
+                Some((state2.cancel_timelock, state2.punish_timelock))
+            }
+            BobState::BtcLocked(state3) | BobState::judeLockProofReceived { state: state3, .. } => {
+                Some((state3.cancel_timelock, state3.punish_timelock))
+            }
+            BobState::judeLocked(state4)
+            | BobState::EncSigSent(state4)
+            | BobState::CancelTimelockExpired(state4)
+            | BobState::BtcCancelled(state4)
+            | BobState::BtcRefunded(state4) => {
+                Some((state4.cancel_timelock, state4.punish_timelock))
+            }
+            _ => None,
+        }
+    }
+
+    /// The hash of Alice's jude lock transaction, only recorded until Bob
+    /// saw it confirmed.
+    pub fn jude_lock_tx_hash(&self) -> Option<jude::TxHash> {
+        match self {
+            BobState::judeLockProofReceived {
+                lock_transfer_proof,
+                ..
+            } => Some(lock_transfer_proof.tx_hash()),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct State0 {
+    swap_id: Uuid,
     b: bitcoin::SecretKey,
     s_b: jude::Scalar,
     S_b_jude: jude::PublicKey,
@@ -90,17 +236,21 @@ pub struct State0 {
     punish_timelock: PunishTimelock,
     refund_address: bitcoin::Address,
     min_jude_confirmations: u32,
+    tx_fees: bitcoin::TxFees,
 }
 
 impl State0 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new<R: RngCore + CryptoRng>(
         rng: &mut R,
+        swap_id: Uuid,
         btc: bitcoin::Amount,
         jude: jude::Amount,
         cancel_timelock: CancelTimelock,
         punish_timelock: PunishTimelock,
         refund_address: bitcoin::Address,
         min_jude_confirmations: u32,
+        tx_fees: bitcoin::TxFees,
     ) -> Self {
         let b = bitcoin::SecretKey::new_random(rng);
 
@@ -110,6 +260,7 @@ impl State0 {
         let (dleq_proof_s_b, (S_b_bitcoin, S_b_jude)) = CROSS_CURVE_PROOF_SYSTEM.prove(&s_b, rng);
 
         Self {
+            swap_id,
             b,
             s_b,
             v_b,
@@ -124,17 +275,20 @@ impl State0 {
             punish_timelock,
             refund_address,
             min_jude_confirmations,
+            tx_fees,
         }
     }
 
     pub fn next_message(&self) -> Message0 {
         Message0 {
+            swap_id: self.swap_id,
             B: self.b.public(),
             S_b_jude: self.S_b_jude,
             S_b_bitcoin: self.S_b_bitcoin,
             dleq_proof_s_b: self.dleq_proof_s_b.clone(),
             v_b: self.v_b,
             refund_address: self.refund_address.clone(),
+            tx_fees: self.tx_fees,
         }
     }
 
@@ -154,6 +308,23 @@ impl State0 {
             bail!("Alice's dleq proof doesn't verify")
         }
 
+        if self.tx_fees.exceeds(&msg.tx_fees) {
+            bail!(
+                "Alice lowered the proposed transaction fees from {:?} to {:?}",
+                self.tx_fees,
+                msg.tx_fees
+            )
+        }
+
+        let max_tx_fees = wallet.max_tx_fees();
+        if msg.tx_fees.exceeds(&max_tx_fees) {
+            bail!(
+                "Transaction fees {:?} requested by Alice exceed our maximum of {:?}",
+                msg.tx_fees,
+                max_tx_fees
+            )
+        }
+
         let tx_lock = bitcoin::TxLock::new(wallet, self.btc, msg.A, self.b.public()).await?;
         let v = msg.v_a + self.v_b;
 
@@ -172,6 +343,7 @@ impl State0 {
             punish_address: msg.punish_address,
             tx_lock,
             min_jude_confirmations: self.min_jude_confirmations,
+            tx_fees: msg.tx_fees,
         })
     }
 }
@@ -192,9 +364,14 @@ pub struct State1 {
     punish_address: bitcoin::Address,
     tx_lock: bitcoin::TxLock,
     min_jude_confirmations: u32,
+    tx_fees: bitcoin::TxFees,
 }
 
 impl State1 {
+    pub fn tx_lock(&self) -> &bitcoin::TxLock {
+        &self.tx_lock
+    }
+
     pub fn next_message(&self) -> Message2 {
         Message2 {
             tx_lock: self.tx_lock.clone(),
@@ -202,8 +379,15 @@ impl State1 {
     }
 
     pub fn receive(self, msg: Message3) -> Result<State2> {
-        let tx_cancel = TxCancel::new(&self.tx_lock, self.cancel_timelock, self.A, self.b.public());
-        let tx_refund = bitcoin::TxRefund::new(&tx_cancel, &self.refund_address);
+        let tx_cancel = TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.A,
+            self.b.public(),
+            self.tx_fees.cancel,
+        );
+        let tx_refund =
+            bitcoin::TxRefund::new(&tx_cancel, &self.refund_address, self.tx_fees.refund);
 
         bitcoin::verify_sig(&self.A, &tx_cancel.digest(), &msg.tx_cancel_sig)?;
         bitcoin::verify_encsig(
@@ -230,6 +414,7 @@ impl State1 {
             tx_cancel_sig_a: msg.tx_cancel_sig,
             tx_refund_encsig: msg.tx_refund_encsig,
             min_jude_confirmations: self.min_jude_confirmations,
+            tx_fees: self.tx_fees,
         })
     }
 }
@@ -252,14 +437,26 @@ pub struct State2 {
     tx_cancel_sig_a: Signature,
     tx_refund_encsig: bitcoin::EncryptedSignature,
     min_jude_confirmations: u32,
+    #[serde(default)]
+    tx_fees: bitcoin::TxFees,
 }
 
 impl State2 {
     pub fn next_message(&self) -> Message4 {
-        let tx_cancel = TxCancel::new(&self.tx_lock, self.cancel_timelock, self.A, self.b.public());
+        let tx_cancel = TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.A,
+            self.b.public(),
+            self.tx_fees.cancel,
+        );
         let tx_cancel_sig = self.b.sign(tx_cancel.digest());
-        let tx_punish =
-            bitcoin::TxPunish::new(&tx_cancel, &self.punish_address, self.punish_timelock);
+        let tx_punish = bitcoin::TxPunish::new(
+            &tx_cancel,
+            &self.punish_address,
+            self.punish_timelock,
+            self.tx_fees.punish,
+        );
         let tx_punish_sig = self.b.sign(tx_punish.digest());
 
         Message4 {
@@ -269,12 +466,19 @@ impl State2 {
     }
 
     pub async fn lock_btc(self, bitcoin_wallet: &bitcoin::Wallet) -> Result<State3> {
-        let signed_tx = bitcoin_wallet
-            .sign_and_finalize(self.tx_lock.clone().into())
-            .await
-            .context("failed to sign Bitcoin lock transaction")?;
+        let published = async {
+            let signed_tx = bitcoin_wallet
+                .sign_and_finalize(self.tx_lock.clone().into())
+                .await
+                .context("failed to sign Bitcoin lock transaction")?;
 
-        let _ = bitcoin_wallet.broadcast(signed_tx, "lock").await?;
+            bitcoin_wallet.broadcast(signed_tx, "lock").await
+        }
+        .await;
+        if let Err(error) = published {
+            bitcoin_wallet.release(self.tx_lock.clone().into()).await;
+            return Err(error);
+        }
 
         Ok(State3 {
             A: self.A,
@@ -292,6 +496,7 @@ impl State2 {
             tx_cancel_sig_a: self.tx_cancel_sig_a,
             tx_refund_encsig: self.tx_refund_encsig,
             min_jude_confirmations: self.min_jude_confirmations,
+            tx_fees: self.tx_fees,
         })
     }
 }
@@ -313,6 +518,8 @@ pub struct State3 {
     tx_cancel_sig_a: Signature,
     tx_refund_encsig: bitcoin::EncryptedSignature,
     min_jude_confirmations: u32,
+    #[serde(default)]
+    tx_fees: bitcoin::TxFees,
 }
 
 impl State3 {
@@ -356,6 +563,7 @@ impl State3 {
             tx_cancel_sig_a: self.tx_cancel_sig_a,
             tx_refund_encsig: self.tx_refund_encsig,
             jude_wallet_restore_blockheight,
+            tx_fees: self.tx_fees,
         }))
     }
 
@@ -388,6 +596,7 @@ impl State3 {
             // For cancel scenarios the jude wallet rescan blockchain height is irrelevant for
             // Bob, because Bob's cancel can only lead to refunding on Bitcoin
             jude_wallet_restore_blockheight: BlockHeight { height: 0 },
+            tx_fees: self.tx_fees,
         }
     }
 
@@ -424,18 +633,14 @@ pub struct State4 {
     tx_cancel_sig_a: Signature,
     tx_refund_encsig: bitcoin::EncryptedSignature,
     jude_wallet_restore_blockheight: BlockHeight,
+    #[serde(default)]
+    tx_fees: bitcoin::TxFees,
 }
 
 impl State4 {
-    pub fn next_message(&self) -> EncryptedSignature {
-        let tx_redeem = bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address);
-        let tx_redeem_encsig = self.b.encsign(self.S_a_bitcoin, tx_redeem.digest());
-
-        EncryptedSignature { tx_redeem_encsig }
-    }
-
     pub fn tx_redeem_encsig(&self) -> bitcoin::EncryptedSignature {
-        let tx_redeem = bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address);
+        let tx_redeem =
+            bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address, self.tx_fees.redeem);
         self.b.encsign(self.S_a_bitcoin, tx_redeem.digest())
     }
 
@@ -443,8 +648,7 @@ impl State4 {
         &self,
         bitcoin_wallet: &bitcoin::Wallet,
     ) -> Result<Transaction> {
-        let tx_cancel =
-            bitcoin::TxCancel::new(&self.tx_lock, self.cancel_timelock, self.A, self.b.public());
+        let tx_cancel = self.tx_cancel();
 
         let sig_a = self.tx_cancel_sig_a.clone();
         let sig_b = self.b.sign(tx_cancel.digest());
@@ -463,8 +667,7 @@ impl State4 {
     }
 
     pub async fn submit_tx_cancel(&self, bitcoin_wallet: &bitcoin::Wallet) -> Result<Txid> {
-        let tx_cancel =
-            bitcoin::TxCancel::new(&self.tx_lock, self.cancel_timelock, self.A, self.b.public());
+        let tx_cancel = self.tx_cancel();
 
         let sig_a = self.tx_cancel_sig_a.clone();
         let sig_b = self.b.sign(tx_cancel.digest());
@@ -483,7 +686,8 @@ impl State4 {
     }
 
     pub async fn watch_for_redeem_btc(&self, bitcoin_wallet: &bitcoin::Wallet) -> Result<State5> {
-        let tx_redeem = bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address);
+        let tx_redeem =
+            bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address, self.tx_fees.redeem);
         let tx_redeem_encsig = self.b.encsign(self.S_a_bitcoin, tx_redeem.digest());
 
         let tx_redeem_candidate = bitcoin_wallet
@@ -534,9 +738,7 @@ impl State4 {
         bitcoin_wallet: &bitcoin::Wallet,
         execution_params: ExecutionParams,
     ) -> Result<()> {
-        let tx_cancel =
-            bitcoin::TxCancel::new(&self.tx_lock, self.cancel_timelock, self.A, self.b.public());
-        let tx_refund = bitcoin::TxRefund::new(&tx_cancel, &self.refund_address);
+        let tx_refund = self.tx_refund();
 
         let adaptor = Adaptor::<HashTranscript<Sha256>, Deterministic<Sha256>>::default();
 
@@ -559,6 +761,20 @@ impl State4 {
     pub fn tx_lock_id(&self) -> bitcoin::Txid {
         self.tx_lock.txid()
     }
+
+    fn tx_cancel(&self) -> TxCancel {
+        TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.A,
+            self.b.public(),
+            self.tx_fees.cancel,
+        )
+    }
+
+    fn tx_refund(&self) -> bitcoin::TxRefund {
+        bitcoin::TxRefund::new(&self.tx_cancel(), &self.refund_address, self.tx_fees.refund)
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
@@ -592,3 +808,113 @@ impl State5 {
         self.tx_lock.txid()
     }
 }
+
+#[cfg(test)]
+pub mod fixtures {
+    //! Deterministic states for tests pinning how they are serialized.
+
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    pub fn state2() -> State2 {
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+
+        let a = bitcoin::SecretKey::new_random(&mut rng);
+        let b = bitcoin::SecretKey::new_random(&mut rng);
+        let s_a = jude::Scalar::random(&mut rng);
+        let s_b = jude::Scalar::random(&mut rng);
+        let S_b_bitcoin = bitcoin::SecretKey::new_random(&mut rng).public();
+        let v = jude::PrivateViewKey::new_random(&mut rng);
+
+        let cancel_timelock = CancelTimelock::new(12);
+        let refund_address = address(1);
+        let tx_fees = bitcoin::TxFees::from_sat_per_vb(1.0);
+
+        let tx_lock = bitcoin::TxLock::new_for_tests(
+            bitcoin::Amount::from_sat(100_000),
+            a.public(),
+            b.public(),
+        );
+        let tx_cancel = TxCancel::new(
+            &tx_lock,
+            cancel_timelock,
+            a.public(),
+            b.public(),
+            tx_fees.cancel,
+        );
+        let tx_refund = bitcoin::TxRefund::new(&tx_cancel, &refund_address, tx_fees.refund);
+
+        State2 {
+            A: a.public(),
+            b,
+            s_b,
+            S_a_jude: jude::PublicKey::from_private_key(&jude::PrivateKey::from_scalar(s_a)),
+            S_a_bitcoin: bitcoin::SecretKey::new_random(&mut rng).public(),
+            v,
+            jude: jude::Amount::from_piconero(1_000_000_000_000),
+            cancel_timelock,
+            punish_timelock: PunishTimelock::new(6),
+            refund_address,
+            redeem_address: address(2),
+            punish_address: address(3),
+            tx_cancel_sig_a: a.sign(tx_cancel.digest()),
+            tx_refund_encsig: a.encsign(S_b_bitcoin, tx_refund.digest()),
+            tx_lock,
+            min_jude_confirmations: 10,
+            tx_fees,
+        }
+    }
+
+    pub fn state3() -> State3 {
+        let state2 = state2();
+
+        State3 {
+            A: state2.A,
+            b: state2.b,
+            s_b: state2.s_b,
+            S_a_jude: state2.S_a_jude,
+            S_a_bitcoin: state2.S_a_bitcoin,
+            v: state2.v,
+            jude: state2.jude,
+            cancel_timelock: state2.cancel_timelock,
+            punish_timelock: state2.punish_timelock,
+            refund_address: state2.refund_address,
+            redeem_address: state2.redeem_address,
+            tx_lock: state2.tx_lock,
+            tx_cancel_sig_a: state2.tx_cancel_sig_a,
+            tx_refund_encsig: state2.tx_refund_encsig,
+            min_jude_confirmations: state2.min_jude_confirmations,
+            tx_fees: state2.tx_fees,
+        }
+    }
+
+    pub fn state4() -> State4 {
+        let state3 = state3();
+
+        State4 {
+            jude_wallet_restore_blockheight: BlockHeight { height: 1000 },
+            ..state3.cancel()
+        }
+    }
+
+    pub fn state5() -> State5 {
+        let state3 = state3();
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+
+        State5 {
+            s_a: jude::PrivateKey::from_scalar(jude::Scalar::random(&mut rng)),
+            s_b: state3.s_b,
+            v: state3.v,
+            tx_lock: state3.tx_lock,
+            jude_wallet_restore_blockheight: BlockHeight { height: 1000 },
+        }
+    }
+
+    fn address(index: u8) -> bitcoin::Address {
+        bitcoin::Address::p2wsh(
+            &::bitcoin::Script::from(vec![index]),
+            bitcoin::Network::Regtest,
+        )
+    }
+}
@@ -5,9 +5,15 @@ use crate::{
     execution_params::ExecutionParams,
     jude,
     jude::InsufficientFunds,
-    protocol::bob::{self, event_loop::EventLoopHandle, state::*, QuoteRequest},
+    protocol::{
+        alice::QuoteResponse,
+        bob::{
+            self, event_loop::EventLoopHandle, state::*, Quote, QuoteCheck, QuoteNotAccepted,
+            QuoteRequest,
+        },
+    },
 };
-use anyhow::{bail, Result};
+use anyhow::{bail, Error, Result};
 use async_recursion::async_recursion;
 use rand::rngs::OsRng;
 use std::sync::Arc;
@@ -43,6 +49,7 @@ pub async fn run_until(
         swap.jude_wallet,
         swap.swap_id,
         swap.execution_params,
+        swap.quote_check,
     )
     .await
 }
@@ -59,6 +66,7 @@ async fn run_until_internal(
     jude_wallet: Arc<jude::Wallet>,
     swap_id: Uuid,
     execution_params: ExecutionParams,
+    quote_check: QuoteCheck,
 ) -> Result<BobState> {
     trace!("Current state: {}", state);
     if is_target_state(&state) {
@@ -67,16 +75,36 @@ async fn run_until_internal(
         match state {
             BobState::Started { btc_amount } => {
                 let bitcoin_refund_address = bitcoin_wallet.new_address().await?;
+                let tx_fees = bitcoin_wallet.estimate_tx_fees().await;
 
                 event_loop_handle.dial().await?;
 
-                let state2 = request_quote_and_setup(
+                let state2 = match request_quote_and_setup(
+                    swap_id,
                     btc_amount,
                     &mut event_loop_handle,
                     execution_params,
                     bitcoin_refund_address,
+                    tx_fees,
+                    &quote_check,
                 )
-                .await?;
+                .await
+                {
+                    Ok(state2) => state2,
+                    Err(error) => {
+                        // Nothing has been committed yet, hence we can give up on the swap
+                        // without risking any funds.
+                        if let Some(not_accepted) = error.downcast_ref::<QuoteNotAccepted>() {
+                            warn!("Aborting swap: {}", not_accepted);
+                            db.insert_latest_state(
+                                swap_id,
+                                Swap::Bob(BobState::SafelyAborted.into()),
+                            )
+                            .await?;
+                        }
+                        return Err(error);
+                    }
+                };
 
                 let state = BobState::ExecutionSetupDone(state2);
                 let db_state = state.clone().into();
@@ -90,6 +118,7 @@ async fn run_until_internal(
                     jude_wallet,
                     swap_id,
                     execution_params,
+                    quote_check,
                 )
                 .await
             }
@@ -111,6 +140,7 @@ async fn run_until_internal(
                     jude_wallet,
                     swap_id,
                     execution_params,
+                    quote_check,
                 )
                 .await
             }
@@ -159,6 +189,7 @@ async fn run_until_internal(
                     jude_wallet,
                     swap_id,
                     execution_params,
+                    quote_check,
                 )
                 .await
             }
@@ -213,6 +244,7 @@ async fn run_until_internal(
                     jude_wallet,
                     swap_id,
                     execution_params,
+                    quote_check,
                 )
                 .await
             }
@@ -255,6 +287,7 @@ async fn run_until_internal(
                     jude_wallet,
                     swap_id,
                     execution_params,
+                    quote_check,
                 )
                 .await
             }
@@ -290,6 +323,7 @@ async fn run_until_internal(
                     jude_wallet,
                     swap_id,
                     execution_params,
+                    quote_check,
                 )
                 .await
             }
@@ -311,6 +345,7 @@ async fn run_until_internal(
                     jude_wallet,
                     swap_id,
                     execution_params,
+                    quote_check,
                 )
                 .await
             }
@@ -336,6 +371,7 @@ async fn run_until_internal(
                     jude_wallet,
                     swap_id,
                     execution_params,
+                    quote_check,
                 )
                 .await
             }
@@ -367,6 +403,7 @@ async fn run_until_internal(
                     jude_wallet,
                     swap_id,
                     execution_params,
+                    quote_check,
                 )
                 .await
             }
@@ -379,27 +416,45 @@ async fn run_until_internal(
 }
 
 pub async fn request_quote_and_setup(
+    swap_id: Uuid,
     btc_amount: bitcoin::Amount,
     event_loop_handle: &mut EventLoopHandle,
     execution_params: ExecutionParams,
     bitcoin_refund_address: bitcoin::Address,
+    tx_fees: bitcoin::TxFees,
+    quote_check: &QuoteCheck,
 ) -> Result<bob::state::State2> {
     event_loop_handle
-        .send_quote_request(QuoteRequest { btc_amount })
+        .send_quote_request(QuoteRequest {
+            swap_id,
+            btc_amount,
+        })
         .await?;
 
-    let jude_amount = event_loop_handle.recv_quote_response().await?.jude_amount;
+    let jude_amount = match event_loop_handle.recv_quote_response().await? {
+        QuoteResponse::Accepted { jude_amount } => jude_amount,
+        QuoteResponse::Rejected(rejection) => {
+            return Err(Error::new(rejection).context("Alice rejected the quote request"))
+        }
+    };
 
     tracing::info!("Quote for {} is {}", btc_amount, jude_amount);
 
+    quote_check.check(Quote {
+        btc_amount,
+        jude_amount,
+    })?;
+
     let state0 = State0::new(
         &mut OsRng,
+        swap_id,
         btc_amount,
         jude_amount,
         execution_params.bitcoin_cancel_timelock,
         execution_params.bitcoin_punish_timelock,
         bitcoin_refund_address,
         execution_params.jude_finality_confirmations,
+        tx_fees,
     );
 
     let state2 = event_loop_handle.execution_setup(state0).await?;
@@ -8,7 +8,7 @@ use libp2p::{
         ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
         RequestResponseMessage, ResponseChannel,
     },
-    NetworkBehaviour,
+    NetworkBehaviour, PeerId,
 };
 use std::time::Duration;
 use tracing::debug;
@@ -16,6 +16,7 @@ use tracing::debug;
 #[derive(Debug)]
 pub enum OutEvent {
     MsgReceived {
+        peer: PeerId,
         msg: TransferProof,
         channel: ResponseChannel<()>,
     },
@@ -69,6 +70,7 @@ impl From<RequestResponseEvent<TransferProof, ()>> for OutEvent {
             } => {
                 debug!("Received Transfer Proof from {}", peer);
                 OutEvent::MsgReceived {
+                    peer,
                     msg: request,
                     channel,
                 }
@@ -0,0 +1,627 @@
+//! A summary of a single swap, as shown by the `swap-details` command.
+//!
+//! Most of the details are derived from the latest state stored in the
+//! database. The final states only keep what is needed to finish the swap,
+//! hence the details of finished swaps are sparse.
+
+use crate::{
+    bitcoin::{self, BlockHeight, CancelTimelock, ExpiredTimelocks, PunishTimelock, Txid},
+    jude,
+    protocol::{
+        alice::AliceState,
+        bob::{BobState, Quote},
+    },
+};
+use anyhow::Result;
+use serde::Serialize;
+use std::fmt;
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct SwapDetails {
+    pub state: &'static str,
+    pub description: String,
+    pub btc_amount: Option<bitcoin::Amount>,
+    pub jude_amount: Option<jude::Amount>,
+    pub tx_lock_id: Option<Txid>,
+    pub tx_cancel_id: Option<Txid>,
+    pub tx_refund_id: Option<Txid>,
+    pub tx_redeem_id: Option<Txid>,
+    pub timelocks: Option<(CancelTimelock, PunishTimelock)>,
+    pub jude_lock_tx_hash: Option<jude::TxHash>,
+}
+
+impl SwapDetails {
+    /// The price of 1 jude in this swap.
+    pub fn rate(&self) -> Option<bitcoin::Amount> {
+        Quote {
+            btc_amount: self.btc_amount?,
+            jude_amount: self.jude_amount?,
+        }
+        .rate()
+    }
+}
+
+impl From<&BobState> for SwapDetails {
+    fn from(state: &BobState) -> Self {
+        Self {
+            state: state.name(),
+            description: state.to_string(),
+            btc_amount: state.btc_amount(),
+            jude_amount: state.jude_amount(),
+            tx_lock_id: state.tx_lock_id(),
+            tx_cancel_id: state.tx_cancel_id(),
+            tx_refund_id: state.tx_refund_id(),
+            tx_redeem_id: state.tx_redeem_id(),
+            timelocks: state.timelocks(),
+            jude_lock_tx_hash: state.jude_lock_tx_hash(),
+        }
+    }
+}
+
+/// Alice does not record the hash of her jude lock transaction, it is only
+/// known to Bob.
+impl From<&AliceState> for SwapDetails {
+    fn from(state: &AliceState) -> Self {
+        let state3 = state.state3();
+
+        let tx_cancel = state3.map(|state3| {
+            bitcoin::TxCancel::new(
+                &state3.tx_lock,
+                state3.cancel_timelock,
+                state3.a.public(),
+                state3.B,
+                state3.tx_fees.cancel,
+            )
+        });
+        let tx_cancel_id = match state {
+            AliceState::BtcCancelled { .. }
+            | AliceState::BtcPunishable { .. }
+            | AliceState::BtcRefunded { .. } => tx_cancel.as_ref().map(|tx| tx.txid()),
+            _ => None,
+        };
+        let tx_refund_id = match (state, state3, &tx_cancel) {
+            (AliceState::BtcRefunded { .. }, Some(state3), Some(tx_cancel)) => Some(
+                bitcoin::TxRefund::new(tx_cancel, &state3.refund_address, state3.tx_fees.refund)
+                    .txid(),
+            ),
+            _ => None,
+        };
+        let tx_redeem_id = match (state, state3) {
+            (AliceState::EncSigLearned { .. }, Some(state3)) => Some(
+                bitcoin::TxRedeem::new(
+                    &state3.tx_lock,
+                    &state3.redeem_address,
+                    state3.tx_fees.redeem,
+                )
+                .txid(),
+            ),
+            _ => None,
+        };
+
+        Self {
+            state: state.name(),
+            description: state.to_string(),
+            btc_amount: state3.map(|state3| state3.btc),
+            jude_amount: state3.map(|state3| state3.jude),
+            tx_lock_id: state3.map(|state3| state3.tx_lock.txid()),
+            tx_cancel_id,
+            tx_refund_id,
+            tx_redeem_id,
+            timelocks: state3.map(|state3| (state3.cancel_timelock, state3.punish_timelock)),
+            jude_lock_tx_hash: None,
+        }
+    }
+}
+
+/// The absolute heights at which the timelocks of a swap expire.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimelockHeights {
+    pub current: BlockHeight,
+    pub lock: BlockHeight,
+    pub cancel: BlockHeight,
+    pub punish: BlockHeight,
+    expired: ExpiredTimelocks,
+}
+
+impl TimelockHeights {
+    /// Returns `None` as long as the lock transaction is not mined, the
+    /// timelocks are relative to its block.
+    pub async fn fetch(
+        bitcoin_wallet: &bitcoin::Wallet,
+        tx_lock_id: Txid,
+        (cancel_timelock, punish_timelock): (CancelTimelock, PunishTimelock),
+    ) -> Result<Option<Self>> {
+        let lock = match bitcoin_wallet
+            .transaction_block_height_if_mined(tx_lock_id)
+            .await?
+        {
+            Some(lock) => lock,
+            None => return Ok(None),
+        };
+        let current = bitcoin_wallet.get_block_height().await?;
+
+        Ok(Some(Self::new(
+            current,
+            lock,
+            cancel_timelock,
+            punish_timelock,
+        )))
+    }
+
+    pub fn new(
+        current: BlockHeight,
+        lock: BlockHeight,
+        cancel_timelock: CancelTimelock,
+        punish_timelock: PunishTimelock,
+    ) -> Self {
+        let cancel = lock + cancel_timelock;
+        let punish = cancel + punish_timelock;
+
+        Self {
+            current,
+            lock,
+            cancel,
+            punish,
+            expired: bitcoin::epoch(current, lock, cancel_timelock, punish_timelock),
+        }
+    }
+
+    pub fn expired(&self) -> ExpiredTimelocks {
+        self.expired
+    }
+
+    /// The number of blocks until `height` is reached, 0 if it already was.
+    pub fn blocks_until(&self, height: BlockHeight) -> u32 {
+        height
+            .checked_sub(self.current)
+            .map(u32::from)
+            .unwrap_or_default()
+    }
+}
+
+/// What the user, or the asb, can do with a swap right now.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, strum::Display)]
+pub enum Action {
+    /// Continue the swap from where it stopped.
+    Resume,
+    /// Publish the cancel transaction.
+    Cancel,
+    /// Refund the locked BTC, or the locked jude in case of Alice.
+    Refund,
+    /// Redeem the locked BTC using Bob's encrypted signature.
+    Redeem,
+    /// Take Bob's BTC because he did not refund in time.
+    Punish,
+}
+
+pub fn bob_actions(state: &BobState, timelocks: Option<TimelockHeights>) -> Vec<Action> {
+    let cancel_expired = timelocks.map_or(false, |timelocks| {
+        !matches!(timelocks.expired(), ExpiredTimelocks::None)
+    });
+
+    match state {
+        BobState::BtcRefunded(..)
+        | BobState::judeRedeemed { .. }
+        | BobState::BtcPunished { .. }
+        | BobState::SafelyAborted => vec![],
+        BobState::BtcCancelled(..) => vec![Action::Resume, Action::Refund],
+        BobState::CancelTimelockExpired(..) => vec![Action::Resume, Action::Cancel],
+        BobState::BtcLocked(..)
+        | BobState::judeLockProofReceived { .. }
+        | BobState::judeLocked(..)
+        | BobState::EncSigSent(..)
+            if cancel_expired =>
+        {
+            vec![Action::Resume, Action::Cancel]
+        }
+        _ => vec![Action::Resume],
+    }
+}
+
+pub fn alice_actions(state: &AliceState, timelocks: Option<TimelockHeights>) -> Vec<Action> {
+    let expired = timelocks.map(|timelocks| timelocks.expired());
+    let cancel_expired = matches!(
+        expired,
+        Some(ExpiredTimelocks::Cancel) | Some(ExpiredTimelocks::Punish)
+    );
+    let punish_expired = matches!(expired, Some(ExpiredTimelocks::Punish));
+
+    match state {
+        AliceState::EncSigLearned { .. } if !cancel_expired => vec![Action::Redeem],
+        AliceState::BtcLocked { .. }
+        | AliceState::judeLocked { .. }
+        | AliceState::EncSigLearned { .. }
+        | AliceState::CancelTimelockExpired { .. }
+            if cancel_expired =>
+        {
+            vec![Action::Cancel]
+        }
+        AliceState::BtcCancelled { .. } | AliceState::BtcPunishable { .. } if punish_expired => {
+            vec![Action::Punish]
+        }
+        AliceState::BtcRefunded { .. } => vec![Action::Refund],
+        _ => vec![],
+    }
+}
+
+/// The details of a swap as printed by the `swap-details` command.
+///
+/// Amounts are given in satoshi and piconero.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub swap_id: Uuid,
+    pub state: &'static str,
+    pub description: String,
+    pub btc_amount_sat: Option<u64>,
+    pub jude_amount_piconero: Option<u64>,
+    /// Satoshi per jude.
+    pub rate_sat: Option<u64>,
+    pub tx_lock_id: Option<Txid>,
+    pub tx_cancel_id: Option<Txid>,
+    pub tx_refund_id: Option<Txid>,
+    pub tx_redeem_id: Option<Txid>,
+    pub jude_lock_tx_hash: Option<String>,
+    pub cancel_timelock_blocks: Option<u32>,
+    pub punish_timelock_blocks: Option<u32>,
+    /// `None` until the lock transaction is mined.
+    pub timelock_heights: Option<TimelockHeightsReport>,
+    pub actions: Vec<Action>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct TimelockHeightsReport {
+    pub current: u32,
+    pub lock: u32,
+    pub cancel: u32,
+    pub punish: u32,
+    pub blocks_until_cancel: u32,
+    pub blocks_until_punish: u32,
+}
+
+impl From<TimelockHeights> for TimelockHeightsReport {
+    fn from(heights: TimelockHeights) -> Self {
+        Self {
+            current: heights.current.into(),
+            lock: heights.lock.into(),
+            cancel: heights.cancel.into(),
+            punish: heights.punish.into(),
+            blocks_until_cancel: heights.blocks_until(heights.cancel),
+            blocks_until_punish: heights.blocks_until(heights.punish),
+        }
+    }
+}
+
+impl Report {
+    pub async fn bob(
+        swap_id: Uuid,
+        state: &BobState,
+        bitcoin_wallet: &bitcoin::Wallet,
+    ) -> Result<Self> {
+        let details = SwapDetails::from(state);
+        let heights = fetch_timelock_heights(&details, bitcoin_wallet).await?;
+
+        Ok(Self::new(
+            swap_id,
+            details,
+            heights,
+            bob_actions(state, heights),
+        ))
+    }
+
+    pub async fn alice(
+        swap_id: Uuid,
+        state: &AliceState,
+        bitcoin_wallet: &bitcoin::Wallet,
+    ) -> Result<Self> {
+        let details = SwapDetails::from(state);
+        let heights = fetch_timelock_heights(&details, bitcoin_wallet).await?;
+
+        Ok(Self::new(
+            swap_id,
+            details,
+            heights,
+            alice_actions(state, heights),
+        ))
+    }
+
+    fn new(
+        swap_id: Uuid,
+        details: SwapDetails,
+        heights: Option<TimelockHeights>,
+        actions: Vec<Action>,
+    ) -> Self {
+        Self {
+            swap_id,
+            state: details.state,
+            description: details.description.clone(),
+            btc_amount_sat: details.btc_amount.map(|amount| amount.as_sat()),
+            jude_amount_piconero: details.jude_amount.map(|amount| amount.as_piconero()),
+            rate_sat: details.rate().map(|rate| rate.as_sat()),
+            tx_lock_id: details.tx_lock_id,
+            tx_cancel_id: details.tx_cancel_id,
+            tx_refund_id: details.tx_refund_id,
+            tx_redeem_id: details.tx_redeem_id,
+            jude_lock_tx_hash: details.jude_lock_tx_hash.map(String::from),
+            cancel_timelock_blocks: details.timelocks.map(|(cancel, _)| cancel.into()),
+            punish_timelock_blocks: details.timelocks.map(|(_, punish)| punish.into()),
+            timelock_heights: heights.map(TimelockHeightsReport::from),
+            actions,
+        }
+    }
+}
+
+async fn fetch_timelock_heights(
+    details: &SwapDetails,
+    bitcoin_wallet: &bitcoin::Wallet,
+) -> Result<Option<TimelockHeights>> {
+    match (details.tx_lock_id, details.timelocks) {
+        (Some(tx_lock_id), Some(timelocks)) => {
+            TimelockHeights::fetch(bitcoin_wallet, tx_lock_id, timelocks).await
+        }
+        _ => Ok(None),
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn or_unknown<T: fmt::Display>(value: Option<T>) -> String {
+            value.map_or_else(|| "unknown".to_string(), |value| value.to_string())
+        }
+
+        writeln!(f, "Swap id:            {}", self.swap_id)?;
+        writeln!(
+            f,
+            "State:              {} ({})",
+            self.state, self.description
+        )?;
+        writeln!(
+            f,
+            "BTC amount:         {}",
+            or_unknown(self.btc_amount_sat.map(bitcoin::Amount::from_sat))
+        )?;
+        writeln!(
+            f,
+            "jude amount:        {}",
+            or_unknown(self.jude_amount_piconero.map(jude::Amount::from_piconero))
+        )?;
+        writeln!(
+            f,
+            "Rate:               {} per jude",
+            or_unknown(self.rate_sat.map(bitcoin::Amount::from_sat))
+        )?;
+        writeln!(f, "Lock tx:            {}", or_unknown(self.tx_lock_id))?;
+        writeln!(f, "Cancel tx:          {}", or_unknown(self.tx_cancel_id))?;
+        writeln!(f, "Refund tx:          {}", or_unknown(self.tx_refund_id))?;
+        writeln!(f, "Redeem tx:          {}", or_unknown(self.tx_redeem_id))?;
+        writeln!(
+            f,
+            "jude lock tx:       {}",
+            or_unknown(self.jude_lock_tx_hash.as_ref())
+        )?;
+
+        match (self.timelock_heights, self.cancel_timelock_blocks) {
+            (Some(heights), _) => {
+                writeln!(f, "Current block:      {}", heights.current)?;
+                writeln!(f, "Lock tx mined in:   {}", heights.lock)?;
+                writeln!(
+                    f,
+                    "Cancel timelock:    block {} ({} blocks left)",
+                    heights.cancel, heights.blocks_until_cancel
+                )?;
+                writeln!(
+                    f,
+                    "Punish timelock:    block {} ({} blocks left)",
+                    heights.punish, heights.blocks_until_punish
+                )?;
+            }
+            (None, Some(cancel)) => {
+                writeln!(
+                    f,
+                    "Timelocks:          cancel {} and punish {} blocks after the lock tx is mined",
+                    cancel,
+                    or_unknown(self.punish_timelock_blocks)
+                )?;
+            }
+            (None, None) => {}
+        }
+
+        let actions = if self.actions.is_empty() {
+            "none".to_string()
+        } else {
+            self.actions
+                .iter()
+                .map(|action| action.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        write!(f, "Possible actions:   {}", actions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::Alice,
+        protocol::{
+            alice::state::fixtures as alice_fixtures, bob::state::fixtures as bob_fixtures,
+        },
+    };
+
+    fn heights(current: u32) -> TimelockHeights {
+        TimelockHeights::new(
+            BlockHeight::new(current),
+            BlockHeight::new(100),
+            CancelTimelock::new(12),
+            PunishTimelock::new(6),
+        )
+    }
+
+    #[test]
+    fn timelocks_are_relative_to_the_lock_transaction() {
+        let heights = heights(105);
+
+        assert_eq!(heights.cancel, BlockHeight::new(112));
+        assert_eq!(heights.punish, BlockHeight::new(118));
+        assert_eq!(heights.blocks_until(heights.cancel), 7);
+        assert_eq!(heights.blocks_until(heights.punish), 13);
+        assert!(matches!(heights.expired(), ExpiredTimelocks::None));
+    }
+
+    #[test]
+    fn timelocks_expire_at_their_height() {
+        assert!(matches!(heights(112).expired(), ExpiredTimelocks::Cancel));
+        assert!(matches!(heights(118).expired(), ExpiredTimelocks::Punish));
+        assert_eq!(heights(200).blocks_until(BlockHeight::new(118)), 0);
+    }
+
+    // With the lock transaction mined at 100 the cancel timelock expires at
+    // 112 and the punish timelock at 118.
+    const BEFORE_CANCEL: u32 = 111;
+    const CANCEL: u32 = 112;
+    const BEFORE_PUNISH: u32 = 117;
+    const PUNISH: u32 = 118;
+
+    fn alice_state(alice: Alice) -> AliceState {
+        alice.into()
+    }
+
+    #[test]
+    fn alice_redeems_until_the_cancel_timelock_expires() {
+        let state = alice_state(Alice::EncSigLearned {
+            jude_wallet_restore_blockheight: jude_rpc::wallet::BlockHeight { height: 1000 },
+            encrypted_signature: bob_fixtures::state4().tx_redeem_encsig(),
+            state3: alice_fixtures::state3(),
+        });
+
+        let table = [
+            (BEFORE_CANCEL, vec![Action::Redeem]),
+            (CANCEL, vec![Action::Cancel]),
+            (PUNISH, vec![Action::Cancel]),
+        ];
+        for (current, actions) in table.iter() {
+            assert_eq!(
+                alice_actions(&state, Some(heights(*current))),
+                *actions,
+                "at height {}",
+                current
+            );
+        }
+        assert_eq!(alice_actions(&state, None), vec![Action::Redeem]);
+    }
+
+    #[test]
+    fn alice_cancels_once_the_cancel_timelock_expires() {
+        let state = alice_state(Alice::judeLocked {
+            jude_wallet_restore_blockheight: jude_rpc::wallet::BlockHeight { height: 1000 },
+            state3: alice_fixtures::state3(),
+        });
+
+        let table = [
+            (BEFORE_CANCEL, vec![]),
+            (CANCEL, vec![Action::Cancel]),
+            (PUNISH, vec![Action::Cancel]),
+        ];
+        for (current, actions) in table.iter() {
+            assert_eq!(
+                alice_actions(&state, Some(heights(*current))),
+                *actions,
+                "at height {}",
+                current
+            );
+        }
+    }
+
+    #[test]
+    fn alice_punishes_once_the_punish_timelock_expires() {
+        let cancelled = alice_state(Alice::BtcCancelled {
+            jude_wallet_restore_blockheight: jude_rpc::wallet::BlockHeight { height: 1000 },
+            state3: alice_fixtures::state3(),
+        });
+        let punishable = alice_state(Alice::BtcPunishable {
+            jude_wallet_restore_blockheight: jude_rpc::wallet::BlockHeight { height: 1000 },
+            state3: alice_fixtures::state3(),
+        });
+
+        for state in &[cancelled, punishable] {
+            let table = [
+                (CANCEL, vec![]),
+                (BEFORE_PUNISH, vec![]),
+                (PUNISH, vec![Action::Punish]),
+            ];
+            for (current, actions) in table.iter() {
+                assert_eq!(
+                    alice_actions(state, Some(heights(*current))),
+                    *actions,
+                    "{} at height {}",
+                    state.name(),
+                    current
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn alice_refunds_regardless_of_the_timelocks() {
+        let state = alice_state(Alice::BtcRefunded {
+            jude_wallet_restore_blockheight: jude_rpc::wallet::BlockHeight { height: 1000 },
+            spend_key: jude::PrivateKey::from_scalar(jude::Scalar::from_bytes_mod_order([1; 32])),
+            state3: alice_fixtures::state3(),
+        });
+
+        for current in &[BEFORE_CANCEL, CANCEL, PUNISH] {
+            assert_eq!(alice_actions(&state, Some(heights(*current))), vec![
+                Action::Refund
+            ]);
+        }
+    }
+
+    #[test]
+    fn bob_cancels_once_the_cancel_timelock_expires() {
+        let state = BobState::EncSigSent(bob_fixtures::state4());
+
+        let table = [
+            (BEFORE_CANCEL, vec![Action::Resume]),
+            (CANCEL, vec![Action::Resume, Action::Cancel]),
+            (PUNISH, vec![Action::Resume, Action::Cancel]),
+        ];
+        for (current, actions) in table.iter() {
+            assert_eq!(
+                bob_actions(&state, Some(heights(*current))),
+                *actions,
+                "at height {}",
+                current
+            );
+        }
+        assert_eq!(bob_actions(&state, None), vec![Action::Resume]);
+    }
+
+    #[test]
+    fn bob_refunds_a_cancelled_swap() {
+        let cancelled = BobState::BtcCancelled(bob_fixtures::state4());
+        let expired = BobState::CancelTimelockExpired(bob_fixtures::state4());
+
+        for current in &[CANCEL, PUNISH] {
+            assert_eq!(bob_actions(&cancelled, Some(heights(*current))), vec![
+                Action::Resume,
+                Action::Refund
+            ]);
+            assert_eq!(bob_actions(&expired, Some(heights(*current))), vec![
+                Action::Resume,
+                Action::Cancel
+            ]);
+        }
+        assert!(bob_actions(&BobState::BtcRefunded(bob_fixtures::state4()), None).is_empty());
+    }
+
+    #[test]
+    fn bob_can_only_resume_a_started_swap() {
+        let state = BobState::Started {
+            btc_amount: bitcoin::Amount::from_sat(100_000),
+        };
+
+        assert_eq!(bob_actions(&state, None), vec![Action::Resume]);
+        assert!(bob_actions(&BobState::SafelyAborted, None).is_empty());
+    }
+}
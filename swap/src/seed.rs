@@ -1,8 +1,10 @@
-use crate::fs::ensure_directory_exists;
+use crate::{encryption::Encrypted, env::Env, fs::ensure_directory_exists};
 use ::bitcoin::secp256k1::{self, constants::SECRET_KEY_SIZE, SecretKey};
 use anyhow::Result;
 use bdk::bitcoin::util::bip32::ExtendedPrivKey;
+use bip39::Mnemonic;
 use bitcoin::hashes::{sha256, Hash, HashEngine};
+use dialoguer::{theme::ColorfulTheme, Input};
 use libp2p::identity;
 use pem::{encode, Pem};
 use rand::prelude::*;
@@ -16,6 +18,12 @@ use std::{
 
 pub const SEED_LENGTH: usize = 32;
 
+/// PEM tag of seed files written before the environment was recorded in the
+/// tag.
+const LEGACY_PEM_TAG: &str = "SEED";
+
+const SEED_FILE_NAME: &str = "seed.pem";
+
 #[derive(Clone, Copy, Eq, PartialEq)]
 pub struct Seed([u8; SEED_LENGTH]);
 
@@ -30,6 +38,27 @@ impl Seed {
         Ok(Seed(bytes))
     }
 
+    /// Restores a seed from the mnemonic returned by [`Seed::to_mnemonic`].
+    pub fn from_mnemonic(words: &str) -> Result<Self, Error> {
+        let entropy = Mnemonic::parse(words)?.to_entropy();
+
+        if entropy.len() != SEED_LENGTH {
+            return Err(Error::IncorrectLength(entropy.len()));
+        }
+        let _ = SecretKey::from_slice(&entropy)?;
+
+        let mut bytes = [0u8; SEED_LENGTH];
+        bytes.copy_from_slice(&entropy);
+
+        Ok(Seed(bytes))
+    }
+
+    /// Encodes the seed as a BIP39 mnemonic of 24 words. The words encode the
+    /// seed itself, hence everything derived from it is restored with it.
+    pub fn to_mnemonic(&self) -> Mnemonic {
+        Mnemonic::from_entropy(&self.0).expect("32 bytes are valid entropy")
+    }
+
     pub fn derive_extended_private_key(
         &self,
         network: bitcoin::Network,
@@ -47,22 +76,50 @@ impl Seed {
         identity::Keypair::Ed25519(key.into())
     }
 
-    pub fn from_file_or_generate(data_dir: &Path) -> Result<Self, Error> {
-        let file_path_buf = data_dir.join("seed.pem");
+    /// Reads the seed from the data directory or generates a new one. The
+    /// passphrase is only asked for if the seed file is encrypted.
+    pub fn from_file_or_generate<F>(data_dir: &Path, env: Env, passphrase: F) -> Result<Self, Error>
+    where
+        F: FnOnce() -> anyhow::Result<String>,
+    {
+        let file_path_buf = data_dir.join(SEED_FILE_NAME);
         let file_path = Path::new(&file_path_buf);
 
         if file_path.exists() {
-            return Self::from_file(&file_path);
+            return match Self::from_file(&file_path, env) {
+                Err(Error::Encrypted) => Self::from_encrypted_file(file_path, env, &passphrase()?),
+                result => result,
+            };
         }
 
         tracing::debug!("No seed file found, creating at: {}", file_path.display());
 
         let random_seed = Seed::random()?;
-        random_seed.write_to(file_path.to_path_buf())?;
+        random_seed.write_to(file_path.to_path_buf(), env)?;
 
         Ok(random_seed)
     }
 
+    /// Replaces the seed file in the data directory, encrypting it if a
+    /// passphrase is given.
+    pub fn write_to_data_dir(
+        &self,
+        data_dir: &Path,
+        env: Env,
+        passphrase: Option<&str>,
+    ) -> Result<(), Error> {
+        let seed_file = data_dir.join(SEED_FILE_NAME);
+        let tmp_file = data_dir.join(format!("{}.tmp", SEED_FILE_NAME));
+
+        match passphrase {
+            Some(passphrase) => self.write_encrypted_to(tmp_file.clone(), env, passphrase)?,
+            None => self.write_to(tmp_file.clone(), env)?,
+        }
+        fs::rename(tmp_file, seed_file)?;
+
+        Ok(())
+    }
+
     /// Derive a new seed using the given scope.
     ///
     /// This function is purposely kept private because it is only a helper
@@ -83,7 +140,7 @@ impl Seed {
         self.0
     }
 
-    fn from_file<D>(seed_file: D) -> Result<Self, Error>
+    fn from_file<D>(seed_file: D, env: Env) -> Result<Self, Error>
     where
         D: AsRef<OsStr>,
     {
@@ -93,10 +150,44 @@ impl Seed {
 
         tracing::trace!("Read in seed from {}", file.display());
 
-        Self::from_pem(pem)
+        Self::from_pem(pem, env)
+    }
+
+    fn from_encrypted_file(seed_file: &Path, env: Env, passphrase: &str) -> Result<Self, Error> {
+        let contents = fs::read_to_string(seed_file)?;
+        let pem = pem::parse(contents)?;
+
+        if pem.tag != encrypted_pem_tag(env) {
+            return Err(Error::WrongEnv {
+                expected: encrypted_pem_tag(env),
+                actual: pem.tag,
+            });
+        }
+
+        let encrypted = serde_cbor::from_slice::<Encrypted>(&pem.contents)?;
+        let decrypted = encrypted.decrypt(passphrase)?;
+
+        Self::from_pem(
+            Pem {
+                tag: pem_tag(env),
+                contents: decrypted,
+            },
+            env,
+        )
     }
 
-    fn from_pem(pem: pem::Pem) -> Result<Self, Error> {
+    fn from_pem(pem: pem::Pem, env: Env) -> Result<Self, Error> {
+        if pem.tag == encrypted_pem_tag(env) {
+            return Err(Error::Encrypted);
+        }
+
+        if pem.tag != LEGACY_PEM_TAG && pem.tag != pem_tag(env) {
+            return Err(Error::WrongEnv {
+                expected: pem_tag(env),
+                actual: pem.tag,
+            });
+        }
+
         if pem.contents.len() != SEED_LENGTH {
             Err(Error::IncorrectLength(pem.contents.len()))
         } else {
@@ -109,12 +200,12 @@ impl Seed {
         }
     }
 
-    fn write_to(&self, seed_file: PathBuf) -> Result<(), Error> {
+    fn write_to(&self, seed_file: PathBuf, env: Env) -> Result<(), Error> {
         ensure_directory_exists(&seed_file)?;
 
         let data = self.bytes();
         let pem = Pem {
-            tag: String::from("SEED"),
+            tag: pem_tag(env),
             contents: data.to_vec(),
         };
 
@@ -125,6 +216,57 @@ impl Seed {
 
         Ok(())
     }
+
+    fn write_encrypted_to(
+        &self,
+        seed_file: PathBuf,
+        env: Env,
+        passphrase: &str,
+    ) -> Result<(), Error> {
+        ensure_directory_exists(&seed_file)?;
+
+        let encrypted = Encrypted::new(passphrase, &self.bytes())?;
+        let pem = Pem {
+            tag: encrypted_pem_tag(env),
+            contents: serde_cbor::to_vec(&encrypted)?,
+        };
+
+        let pem_string = encode(&pem);
+
+        let mut file = File::create(seed_file)?;
+        file.write_all(pem_string.as_bytes())?;
+
+        Ok(())
+    }
+}
+
+/// Asks for the words of a mnemonic, or reads them from stdin if it is not a
+/// terminal.
+pub fn read_mnemonic() -> Result<String> {
+    if atty::is(atty::Stream::Stdin) {
+        let words = Input::<String>::with_theme(&ColorfulTheme::default())
+            .with_prompt("Mnemonic")
+            .interact_text()?;
+
+        return Ok(words);
+    }
+
+    let mut words = String::new();
+    io::stdin().read_line(&mut words)?;
+
+    Ok(words.trim().to_string())
+}
+
+fn pem_tag(env: Env) -> String {
+    format!("{} {}", env.to_string().to_uppercase(), LEGACY_PEM_TAG)
+}
+
+fn encrypted_pem_tag(env: Env) -> String {
+    format!(
+        "{} ENCRYPTED {}",
+        env.to_string().to_uppercase(),
+        LEGACY_PEM_TAG
+    )
 }
 
 impl fmt::Debug for Seed {
@@ -159,6 +301,16 @@ pub enum Error {
     Rand(#[from] rand::Error),
     #[error("no default path")]
     NoDefaultPath,
+    #[error("seed file is tagged {actual} but {expected} was expected, refusing to use a seed of another network")]
+    WrongEnv { expected: String, actual: String },
+    #[error("seed file is encrypted, a passphrase is needed to read it")]
+    Encrypted,
+    #[error("CBOR: {0}")]
+    Cbor(#[from] serde_cbor::Error),
+    #[error("encryption: {0:#}")]
+    Encryption(#[from] anyhow::Error),
+    #[error("invalid mnemonic: {0}")]
+    Mnemonic(#[from] bip39::Error),
 }
 
 #[cfg(test)]
@@ -188,7 +340,7 @@ syl9wSYaruvgxg9P5Q1qkZaq5YkM6GvXkxe+VYrL/XM=
 
         let want = base64::decode(payload).unwrap();
         let pem = pem::parse(pem_string).unwrap();
-        let got = Seed::from_pem(pem).unwrap();
+        let got = Seed::from_pem(pem, Env::Testnet).unwrap();
 
         assert_eq!(got.bytes(), *want);
     }
@@ -200,7 +352,7 @@ VnZUNFZ4dlY=
 -----END SEED-----
 ";
         let pem = pem::parse(short).unwrap();
-        match Seed::from_pem(pem) {
+        match Seed::from_pem(pem, Env::Testnet) {
             Ok(_) => panic!("should fail for short payload"),
             Err(e) => {
                 match e {
@@ -220,7 +372,7 @@ mbKANv2qKGmNVg1qtquj6Hx1pFPelpqOfE2JaJJAMEg1FlFhNRNlFlE=
 -----END SEED-----
 ";
         let pem = pem::parse(long).unwrap();
-        match Seed::from_pem(pem) {
+        match Seed::from_pem(pem, Env::Testnet) {
             Ok(_) => panic!("should fail for long payload"),
             Err(e) => {
                 match e {
@@ -236,10 +388,91 @@ mbKANv2qKGmNVg1qtquj6Hx1pFPelpqOfE2JaJJAMEg1FlFhNRNlFlE=
         let tmpfile = temp_dir().join("seed.pem");
 
         let seed = Seed::random().unwrap();
-        seed.write_to(tmpfile.clone())
+        seed.write_to(tmpfile.clone(), Env::Testnet)
             .expect("Write seed to temp file");
 
-        let rinsed = Seed::from_file(tmpfile).expect("Read from temp file");
+        let rinsed = Seed::from_file(tmpfile, Env::Testnet).expect("Read from temp file");
         assert_eq!(seed.0, rinsed.0);
     }
+
+    #[test]
+    fn encrypted_seed_is_read_with_the_passphrase() {
+        let data_dir = tempfile::tempdir().unwrap();
+
+        let seed = Seed::random().unwrap();
+        seed.write_to_data_dir(data_dir.path(), Env::Testnet, Some("correct horse"))
+            .unwrap();
+
+        match Seed::from_file(data_dir.path().join(SEED_FILE_NAME), Env::Testnet) {
+            Err(Error::Encrypted) => {} // pass
+            _ => panic!("should fail with Encrypted error"),
+        }
+
+        let read = Seed::from_file_or_generate(data_dir.path(), Env::Testnet, || {
+            Ok("correct horse".to_string())
+        })
+        .unwrap();
+        assert_eq!(read, seed);
+
+        assert!(
+            Seed::from_file_or_generate(data_dir.path(), Env::Testnet, || {
+                Ok("battery staple".to_string())
+            })
+            .is_err()
+        );
+
+        seed.write_to_data_dir(data_dir.path(), Env::Testnet, None)
+            .unwrap();
+        let read = Seed::from_file_or_generate(data_dir.path(), Env::Testnet, || {
+            panic!("passphrase asked for")
+        })
+        .unwrap();
+        assert_eq!(read, seed);
+    }
+
+    #[test]
+    fn seed_restored_from_mnemonic_derives_the_same_keys() {
+        let seed = Seed::random().unwrap();
+
+        let words = seed.to_mnemonic().to_string();
+        assert_eq!(words.split_whitespace().count(), 24);
+
+        let restored = Seed::from_mnemonic(&words).unwrap();
+        assert_eq!(restored, seed);
+        assert_eq!(
+            restored
+                .derive_extended_private_key(bitcoin::Network::Testnet)
+                .unwrap(),
+            seed.derive_extended_private_key(bitcoin::Network::Testnet)
+                .unwrap()
+        );
+        assert_eq!(
+            restored.derive_libp2p_identity().public().into_peer_id(),
+            seed.derive_libp2p_identity().public().into_peer_id()
+        );
+    }
+
+    #[test]
+    fn short_mnemonic_is_rejected() {
+        let words = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+        match Seed::from_mnemonic(words) {
+            Err(Error::IncorrectLength(16)) => {} // pass
+            _ => panic!("should fail with IncorrectLength error"),
+        }
+    }
+
+    #[test]
+    fn seed_of_other_env_is_rejected() {
+        let tmpfile = temp_dir().join("mainnet-seed.pem");
+
+        let seed = Seed::random().unwrap();
+        seed.write_to(tmpfile.clone(), Env::Mainnet)
+            .expect("Write seed to temp file");
+
+        match Seed::from_file(tmpfile, Env::Testnet) {
+            Err(Error::WrongEnv { .. }) => {} // pass
+            _ => panic!("should fail with WrongEnv error"),
+        }
+    }
 }
@@ -15,7 +15,7 @@ use std::{
     time::Duration,
 };
 use swap::{
-    asb::{fixed_rate, fixed_rate::RATE},
+    asb::{fixed_rate, fixed_rate::RATE, PeerLimits, Spread},
     bitcoin,
     bitcoin::{CancelTimelock, PunishTimelock},
     database::Database,
@@ -35,6 +35,10 @@ use uuid::Uuid;
 
 const TEST_WALLET_NAME: &str = "testwallet";
 
+/// Both parties use this fee rate so that the agreed transaction fees are
+/// known up-front.
+const FEE_RATE_SAT_PER_VB: f32 = 10.0;
+
 #[derive(Debug, Clone)]
 pub struct StartingBalances {
     pub jude: jude::Amount,
@@ -65,13 +69,17 @@ impl BobParams {
         )
     }
 
-    pub fn new_eventloop(&self) -> Result<(bob::EventLoop, bob::EventLoopHandle)> {
-        bob::EventLoop::new(
+    pub async fn new_eventloop(&self) -> Result<(bob::EventLoop, bob::EventLoopHandle)> {
+        let (event_loop, swap_registry) = bob::EventLoop::new(
             &self.seed.derive_libp2p_identity(),
-            self.alice_peer_id,
-            self.alice_address.clone(),
+            None,
             self.bitcoin_wallet.clone(),
-        )
+        )?;
+        let event_loop_handle = swap_registry
+            .register(self.swap_id, self.alice_peer_id, self.alice_address.clone())
+            .await?;
+
+        Ok((event_loop, event_loop_handle))
     }
 }
 
@@ -102,7 +110,7 @@ pub struct TestContext {
 
 impl TestContext {
     pub async fn new_swap_as_bob(&mut self) -> (bob::Swap, BobEventLoopJoinHandle) {
-        let (event_loop, event_loop_handle) = self.bob_params.new_eventloop().unwrap();
+        let (event_loop, event_loop_handle) = self.bob_params.new_eventloop().await.unwrap();
 
         let swap = self
             .bob_params
@@ -122,7 +130,7 @@ impl TestContext {
     ) -> (bob::Swap, BobEventLoopJoinHandle) {
         join_handle.abort();
 
-        let (event_loop, event_loop_handle) = self.bob_params.new_eventloop().unwrap();
+        let (event_loop, event_loop_handle) = self.bob_params.new_eventloop().await.unwrap();
 
         let swap = self.bob_params.builder(event_loop_handle).build().unwrap();
 
@@ -145,8 +153,7 @@ impl TestContext {
         let btc_balance_after_swap = self.alice_bitcoin_wallet.as_ref().balance().await.unwrap();
         assert_eq!(
             btc_balance_after_swap,
-            self.alice_starting_balances.btc + self.btc_amount
-                - bitcoin::Amount::from_sat(bitcoin::TX_FEE)
+            self.alice_starting_balances.btc + self.btc_amount - tx_fees().redeem
         );
 
         let jude_balance_after_swap = self
@@ -201,7 +208,8 @@ impl TestContext {
         assert_eq!(
             btc_balance_after_swap,
             self.alice_starting_balances.btc + self.btc_amount
-                - bitcoin::Amount::from_sat(2 * bitcoin::TX_FEE)
+                - tx_fees().cancel
+                - tx_fees().punish
         );
 
         let jude_balance_after_swap = self
@@ -266,14 +274,13 @@ impl TestContext {
         let btc_balance_after_swap = self.bob_bitcoin_wallet.as_ref().balance().await.unwrap();
 
         let alice_submitted_cancel = btc_balance_after_swap
-            == self.bob_starting_balances.btc
-                - lock_tx_bitcoin_fee
-                - bitcoin::Amount::from_sat(bitcoin::TX_FEE);
+            == self.bob_starting_balances.btc - lock_tx_bitcoin_fee - tx_fees().refund;
 
         let bob_submitted_cancel = btc_balance_after_swap
             == self.bob_starting_balances.btc
                 - lock_tx_bitcoin_fee
-                - bitcoin::Amount::from_sat(2 * bitcoin::TX_FEE);
+                - tx_fees().cancel
+                - tx_fees().refund;
 
         // The cancel tx can be submitted by both Alice and Bob.
         // Since we cannot be sure who submitted it we have to assert accordingly
@@ -394,7 +401,10 @@ where
         alice_jude_wallet.clone(),
         alice_db,
         fixed_rate::RateService::default(),
+        Spread::default(),
+        bitcoin::Amount::ZERO,
         alice_starting_balances.jude,
+        PeerLimits::new(10, 100),
     )
     .unwrap();
 
@@ -429,6 +439,10 @@ where
     testfn(test).await;
 }
 
+fn tx_fees() -> bitcoin::TxFees {
+    bitcoin::TxFees::from_sat_per_vb(FEE_RATE_SAT_PER_VB)
+}
+
 fn random_prefix() -> String {
     use rand::{distributions::Alphanumeric, thread_rng, Rng};
     use std::iter;
@@ -616,6 +630,7 @@ async fn init_test_wallets(
         datadir,
         seed.derive_extended_private_key(bitcoin::Network::Regtest)
             .expect("Could not create extended private key from seed"),
+        bitcoin::FeeConfig::fixed(FEE_RATE_SAT_PER_VB),
     )
     .await
     .expect("could not init btc wallet");